@@ -0,0 +1,209 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+use tracing::warn;
+
+use crate::llm::client::LlmResult;
+use crate::llm::{LlmClient, LlmRequest, ResponseFormat};
+
+/// 邮件分类
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum EmailCategory {
+    #[serde(rename = "工作相关", alias = "work")]
+    Work,
+    #[serde(rename = "个人事务", alias = "personal")]
+    Personal,
+    #[serde(rename = "营销推广", alias = "marketing")]
+    Marketing,
+    #[serde(rename = "系统通知", alias = "notification")]
+    Notification,
+    #[serde(rename = "垃圾邮件", alias = "spam")]
+    Spam,
+    #[default]
+    #[serde(rename = "其他", alias = "other", other)]
+    Other,
+}
+
+impl EmailCategory {
+    pub fn label(&self) -> &'static str {
+        match self {
+            Self::Work => "工作相关",
+            Self::Personal => "个人事务",
+            Self::Marketing => "营销推广",
+            Self::Notification => "系统通知",
+            Self::Spam => "垃圾邮件",
+            Self::Other => "其他",
+        }
+    }
+}
+
+impl fmt::Display for EmailCategory {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.label())
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Urgency {
+    #[serde(alias = "低")]
+    Low,
+    #[serde(alias = "高", alias = "urgent")]
+    High,
+    #[default]
+    #[serde(alias = "medium", alias = "中", other)]
+    Normal,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Sentiment {
+    #[serde(alias = "积极")]
+    Positive,
+    #[serde(alias = "消极")]
+    Negative,
+    #[default]
+    #[serde(alias = "中性", other)]
+    Neutral,
+}
+
+/// 模型对一封邮件的结构化分析结果；`category` 和 `needs_reply` 必须给出，其余字段可以省略
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EmailAnalysis {
+    pub category: EmailCategory,
+    #[serde(default)]
+    pub urgency: Urgency,
+    #[serde(default)]
+    pub sentiment: Sentiment,
+    #[serde(default)]
+    pub language: String,
+    #[serde(default)]
+    pub summary: String,
+    #[serde(default)]
+    pub action_items: Vec<String>,
+    pub needs_reply: bool,
+}
+
+impl Default for EmailAnalysis {
+    /// 解析失败时使用的保守结果：归为“其他”，不需要回复
+    fn default() -> Self {
+        Self {
+            category: EmailCategory::Other,
+            urgency: Urgency::Normal,
+            sentiment: Sentiment::Neutral,
+            language: String::new(),
+            summary: String::new(),
+            action_items: Vec::new(),
+            needs_reply: false,
+        }
+    }
+}
+
+impl EmailAnalysis {
+    /// 从模型输出中解析 JSON，容忍 ```json 代码块和前后的多余文字
+    pub fn parse(output: &str) -> Result<Self, serde_json::Error> {
+        let trimmed = output.trim();
+        let json = match (trimmed.find('{'), trimmed.rfind('}')) {
+            (Some(start), Some(end)) if start < end => &trimmed[start..=end],
+            _ => trimmed,
+        };
+        serde_json::from_str(json)
+    }
+}
+
+/// 请求模型以 JSON 分析邮件；输出无法解析时让模型修复一次，仍失败则返回 None
+///
+/// `quoted_content` 是邮件中引用的历史邮件，只作为背景提供给模型，可以为空。
+/// API 调用本身失败时返回错误，由调用方决定是否稍后重试。
+pub async fn analyze_email(llm_client: &dyn LlmClient, email_content: &str, quoted_content: &str) -> LlmResult<Option<EmailAnalysis>> {
+    let mut context = HashMap::new();
+    context.insert("email_content".to_string(), serde_json::json!(email_content));
+    context.insert("quoted_content".to_string(), serde_json::json!(quoted_content));
+    let request = LlmRequest::new("email_analysis".to_string(), context).with_response_format(ResponseFormat::Json);
+
+    let output = llm_client.generate_response(&request).await?.content;
+    let error = match EmailAnalysis::parse(&output) {
+        Ok(analysis) => return Ok(Some(analysis)),
+        Err(e) => e,
+    };
+    warn!(error = %error, "Email analysis is not valid JSON, asking the model to repair it");
+
+    let mut repair_context = HashMap::new();
+    repair_context.insert("invalid_output".to_string(), serde_json::json!(output));
+    repair_context.insert("error".to_string(), serde_json::json!(error.to_string()));
+    let repair_request =
+        LlmRequest::new("email_analysis_repair".to_string(), repair_context).with_response_format(ResponseFormat::Json);
+
+    let repaired = llm_client.generate_response(&repair_request).await?.content;
+    match EmailAnalysis::parse(&repaired) {
+        Ok(analysis) => Ok(Some(analysis)),
+        Err(e) => {
+            warn!(error = %e, "Repaired email analysis is still invalid");
+            Ok(None)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::llm::client::tests::response;
+    use crate::llm::client::LlmResponse;
+    use async_trait::async_trait;
+    use std::sync::Mutex;
+
+    /// 按顺序返回预设输出的模型
+    struct ScriptedLlm {
+        outputs: Mutex<Vec<&'static str>>,
+        prompts: Mutex<Vec<String>>,
+    }
+
+    impl ScriptedLlm {
+        fn new(outputs: Vec<&'static str>) -> Self {
+            Self {
+                outputs: Mutex::new(outputs),
+                prompts: Mutex::new(Vec::new()),
+            }
+        }
+    }
+
+    #[async_trait]
+    impl LlmClient for ScriptedLlm {
+        async fn generate_response(&self, request: &LlmRequest) -> LlmResult<LlmResponse> {
+            assert_eq!(request.response_format, ResponseFormat::Json);
+            self.prompts.lock().unwrap().push(request.prompt_name.clone());
+            Ok(response(request, self.outputs.lock().unwrap().remove(0)))
+        }
+    }
+
+    #[test]
+    fn test_parse_fenced_json_with_chinese_values() {
+        let output = "```json\n{\"category\": \"系统通知\", \"urgency\": \"高\", \"sentiment\": \"neutral\", \"language\": \"zh\", \"summary\": \"服务器告警\", \"action_items\": [\"检查磁盘\"], \"needs_reply\": false}\n```";
+        let analysis = EmailAnalysis::parse(output).unwrap();
+        assert_eq!(analysis.category, EmailCategory::Notification);
+        assert_eq!(analysis.urgency, Urgency::High);
+        assert_eq!(analysis.action_items, vec!["检查磁盘"]);
+        assert!(!analysis.needs_reply);
+
+        let unknown = EmailAnalysis::parse("{\"category\": \"newsletter\", \"needs_reply\": false}").unwrap();
+        assert_eq!(unknown.category, EmailCategory::Other);
+
+        // 缺少必需字段的对象不算有效的分析
+        assert!(EmailAnalysis::parse("{}").is_err());
+        assert!(EmailAnalysis::parse("{\"result\": \"ok\", \"category\": \"工作相关\"}").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_repair_retry_then_default() {
+        let llm = ScriptedLlm::new(vec!["分类：工作相关", "{\"category\": \"工作相关\", \"needs_reply\": true}"]);
+        let analysis = analyze_email(&llm, "Subject: 周报", "").await.unwrap().unwrap();
+        assert_eq!(analysis.category, EmailCategory::Work);
+        assert_eq!(*llm.prompts.lock().unwrap(), vec!["email_analysis", "email_analysis_repair"]);
+
+        // 与分析无关的 JSON 对象同样要修复
+        let llm = ScriptedLlm::new(vec!["{}", "{\"summary\": \"周报\"}"]);
+        assert_eq!(analyze_email(&llm, "Subject: 周报", "").await.unwrap(), None);
+        assert_eq!(*llm.prompts.lock().unwrap(), vec!["email_analysis", "email_analysis_repair"]);
+        assert!(!EmailAnalysis::default().needs_reply);
+    }
+}
@@ -0,0 +1,286 @@
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::fs;
+use tokio::sync::Mutex;
+use tracing::{info, warn};
+
+use crate::email::{EmailAddress, EmailMessage, InboundEmail};
+
+/// 预览邮件主题的前缀，owner 回复时据此找到对应的草稿
+const SUBJECT_TAG: &str = "[Sentio 草稿 #";
+
+/// 等待 owner 确认的回复草稿
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Draft {
+    pub id: String,
+    /// 原邮件的发件人，发送后的回复记录在这个用户下
+    pub user_id: String,
+    pub session_id: String,
+    /// 确认后发送的回复，正文为 `content` 加上可选的原文引用
+    pub reply: EmailMessage,
+    /// 模型生成（或 owner 修改后）的回复内容
+    pub content: String,
+    pub quoted: Option<String>,
+    /// 生成回复时的记录（模型、工具调用等），发送后写入交互记录
+    #[serde(default)]
+    pub metadata: HashMap<String, serde_json::Value>,
+    pub created_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+}
+
+impl Draft {
+    /// 用 owner 修改后的内容替换回复正文，保留原文引用
+    pub fn set_content(&mut self, content: String) {
+        self.reply.body = match &self.quoted {
+            Some(quoted) => format!("{}\n\n{}", content, quoted),
+            None => content.clone(),
+        };
+        self.content = content;
+    }
+
+    pub fn is_expired(&self) -> bool {
+        self.expires_at <= Utc::now()
+    }
+}
+
+/// owner 对草稿的处理决定
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Decision {
+    Approve,
+    /// 用新的内容替换草稿后发送
+    Edit(String),
+    Reject,
+}
+
+/// 持久化的待确认草稿
+pub struct DraftStore {
+    path: PathBuf,
+    timeout: Duration,
+    lock: Mutex<()>,
+}
+
+impl DraftStore {
+    pub fn new(path: PathBuf, timeout: Duration) -> Self {
+        Self {
+            path,
+            timeout,
+            lock: Mutex::new(()),
+        }
+    }
+
+    async fn load(&self) -> Result<Vec<Draft>> {
+        if !self.path.exists() {
+            return Ok(Vec::new());
+        }
+        let content = fs::read_to_string(&self.path).await?;
+        Ok(serde_json::from_str(&content)?)
+    }
+
+    async fn save(&self, drafts: &[Draft]) -> Result<()> {
+        fs::write(&self.path, serde_json::to_string_pretty(drafts)?).await?;
+        Ok(())
+    }
+
+    pub async fn create(
+        &self,
+        user_id: &str,
+        session_id: &str,
+        reply: EmailMessage,
+        content: String,
+        quoted: Option<String>,
+        metadata: HashMap<String, serde_json::Value>,
+    ) -> Result<Draft> {
+        let created_at = Utc::now();
+        let draft = Draft {
+            id: uuid::Uuid::new_v4().simple().to_string()[..8].to_string(),
+            user_id: user_id.to_string(),
+            session_id: session_id.to_string(),
+            reply,
+            content,
+            quoted,
+            metadata,
+            created_at,
+            expires_at: created_at + chrono::Duration::from_std(self.timeout)?,
+        };
+
+        let _guard = self.lock.lock().await;
+        let mut drafts = self.load().await?;
+        drafts.push(draft.clone());
+        self.save(&drafts).await?;
+        Ok(draft)
+    }
+
+    /// 尚未过期的草稿
+    pub async fn list(&self) -> Result<Vec<Draft>> {
+        let _guard = self.lock.lock().await;
+        Ok(self.load().await?.into_iter().filter(|draft| !draft.is_expired()).collect())
+    }
+
+    /// 取出草稿；不存在或已过期时返回 None
+    pub async fn take(&self, id: &str) -> Result<Option<Draft>> {
+        let _guard = self.lock.lock().await;
+        let mut drafts = self.load().await?;
+        let Some(index) = drafts.iter().position(|draft| draft.id == id) else {
+            return Ok(None);
+        };
+        let draft = drafts.remove(index);
+        self.save(&drafts).await?;
+        Ok((!draft.is_expired()).then_some(draft))
+    }
+
+    /// 发送失败时把草稿放回去
+    pub async fn restore(&self, draft: Draft) -> Result<()> {
+        let _guard = self.lock.lock().await;
+        let mut drafts = self.load().await?;
+        drafts.push(draft);
+        self.save(&drafts).await
+    }
+
+    /// 删除并返回所有过期的草稿
+    pub async fn expire(&self) -> Result<Vec<Draft>> {
+        let _guard = self.lock.lock().await;
+        let (expired, active): (Vec<Draft>, Vec<Draft>) =
+            self.load().await?.into_iter().partition(Draft::is_expired);
+        if !expired.is_empty() {
+            self.save(&active).await?;
+        }
+        Ok(expired)
+    }
+}
+
+/// 后台清理过期草稿
+pub async fn run_expiry(drafts: Arc<DraftStore>, interval: Duration) {
+    let mut ticker = tokio::time::interval(interval);
+    loop {
+        ticker.tick().await;
+        match drafts.expire().await {
+            Ok(expired) => {
+                for draft in expired {
+                    info!(draft = %draft.id, "Draft reply to {} expired without approval", draft.user_id);
+                }
+            }
+            Err(e) => warn!("Failed to expire drafts: {}", e),
+        }
+    }
+}
+
+/// 发给 owner 的草稿预览
+pub fn preview(draft: &Draft, original: &InboundEmail, from: EmailAddress, owner: EmailAddress) -> EmailMessage {
+    let recipients: Vec<&str> = draft.reply.to.iter().map(|to| to.email.as_str()).collect();
+    let body = format!(
+        "{} 的邮件已生成回复草稿，请在 {} 前确认。\n\n\
+         直接回复本邮件，在第一行写上：\n\
+         \x20 批准（APPROVE）：按草稿发送\n\
+         \x20 修改（EDIT）：用第二行起的内容作为回复发送\n\
+         \x20 拒绝（REJECT）：丢弃草稿\n\n\
+         ---------- 回复草稿 ----------\n收件人：{}\n主题：{}\n\n{}\n\n\
+         ---------- 原邮件 ----------\n发件人：{}\n主题：{}\n\n{}",
+        original.from.email,
+        draft.expires_at.format("%Y-%m-%d %H:%M UTC"),
+        recipients.join(", "),
+        draft.reply.subject,
+        draft.content,
+        original.from.email,
+        original.subject,
+        original.body(),
+    );
+
+    EmailMessage {
+        from,
+        to: vec![owner],
+        subject: format!("{}{}] {}", SUBJECT_TAG, draft.id, draft.reply.subject),
+        body,
+        is_html: false,
+        in_reply_to: None,
+        references: Vec::new(),
+    }
+}
+
+/// 从 owner 回复的主题中取出草稿 ID
+pub fn draft_id(subject: &str) -> Option<&str> {
+    let start = subject.find(SUBJECT_TAG)? + SUBJECT_TAG.len();
+    let id = subject[start..].split(']').next()?;
+    (!id.is_empty() && id.chars().all(|c| c.is_ascii_hexdigit())).then_some(id)
+}
+
+/// 解析 owner 回复的第一行命令；修改时取命令之后、引用原文之前的内容
+pub fn parse_command(body: &str) -> Option<Decision> {
+    let mut lines = body.lines().skip_while(|line| line.trim().is_empty());
+    let first = lines.next()?.trim();
+    let is_separator = |c: char| c.is_whitespace() || matches!(c, ':' | '：' | ',' | '，');
+    let (keyword, rest) = first.split_at(first.find(is_separator).unwrap_or(first.len()));
+    let rest = rest.trim_start_matches(is_separator);
+
+    match keyword.to_lowercase().as_str() {
+        "approve" | "批准" => Some(Decision::Approve),
+        "reject" | "拒绝" => Some(Decision::Reject),
+        "edit" | "修改" => {
+            let mut content: Vec<&str> = vec![rest];
+            content.extend(lines.take_while(|line| !line.starts_with('>')));
+            // 去掉邮件客户端在引用前加的署名行
+            if content.last().is_some_and(|line| {
+                let line = line.trim_end();
+                line.ends_with("wrote:") || line.ends_with("写道：") || line.ends_with("写道:")
+            }) {
+                content.pop();
+            }
+            let content = content.join("\n").trim().to_string();
+            (!content.is_empty()).then_some(Decision::Edit(content))
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn reply(body: &str) -> EmailMessage {
+        crate::email::client::tests::message("alice@example.com", "Re: 周报", body)
+    }
+
+    #[test]
+    fn test_parse_owner_commands() {
+        assert_eq!(parse_command("\n批准\n\n> 原邮件"), Some(Decision::Approve));
+        assert_eq!(parse_command("REJECT, not now"), Some(Decision::Reject));
+        assert_eq!(parse_command("好的"), None);
+        assert_eq!(
+            parse_command("修改：\n收到，周五前给你。\n\n谢谢\n\n在 2025-07-11 12:00，Sentio 写道：\n> 原草稿"),
+            Some(Decision::Edit("收到，周五前给你。\n\n谢谢".to_string()))
+        );
+        assert_eq!(parse_command("EDIT: Thanks!\nOn Fri, Sentio wrote:\n> draft"), Some(Decision::Edit("Thanks!".to_string())));
+        assert_eq!(parse_command("edit\n> only quote"), None);
+
+        assert_eq!(draft_id("Re: [Sentio 草稿 #1a2b3c4d] Re: 周报"), Some("1a2b3c4d"));
+        assert_eq!(draft_id("Re: 周报"), None);
+    }
+
+    #[tokio::test]
+    async fn test_take_edit_and_expire() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = DraftStore::new(dir.path().join("drafts.json"), Duration::from_secs(3600));
+        let mut draft = store
+            .create("alice@example.com", "thread", reply("好的\n\n> 原文"), "好的".to_string(), Some("> 原文".to_string()), HashMap::new())
+            .await
+            .unwrap();
+        assert_eq!(store.list().await.unwrap().len(), 1);
+
+        draft.set_content("收到".to_string());
+        assert_eq!(draft.reply.body, "收到\n\n> 原文");
+
+        assert!(store.take(&draft.id).await.unwrap().is_some());
+        assert!(store.take(&draft.id).await.unwrap().is_none());
+
+        // 有效期为零的草稿立即过期，不能再被确认
+        let expired_store = DraftStore::new(dir.path().join("expired.json"), Duration::ZERO);
+        let expired = expired_store.create("alice@example.com", "thread", reply("x"), "x".to_string(), None, HashMap::new()).await.unwrap();
+        assert!(expired_store.list().await.unwrap().is_empty());
+        assert_eq!(expired_store.expire().await.unwrap()[0].id, expired.id);
+        assert!(expired_store.take(&expired.id).await.unwrap().is_none());
+    }
+}
@@ -0,0 +1,497 @@
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use rsa::pkcs1::DecodeRsaPublicKey;
+use rsa::pkcs8::DecodePublicKey;
+use rsa::traits::PublicKeyParts;
+use rsa::{Pkcs1v15Sign, RsaPublicKey};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use tracing::debug;
+
+use super::dns::{DnsError, DnsResolver};
+use super::AuthResult;
+
+/// 一封邮件最多验证的签名数
+const MAX_SIGNATURES: usize = 5;
+/// RFC 8301 要求的最小 RSA 密钥长度
+const MIN_RSA_BITS: usize = 1024;
+
+/// 单个 DKIM-Signature 的验证结果
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DkimOutcome {
+    /// 签名中的 d= 域名
+    pub domain: String,
+    pub result: AuthResult,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum Canonicalization {
+    Simple,
+    Relaxed,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Algorithm {
+    RsaSha256,
+    Ed25519Sha256,
+}
+
+/// 未折叠前的原始头部；值保留原始字节，8 位编码（如 GBK）的头部不经过 UTF-8 转换
+pub(super) struct RawHeader {
+    name: String,
+    raw: Vec<u8>,
+}
+
+/// 把裸 LF 换成 CRLF；Maildir 中的邮件通常只用 LF 换行
+fn to_crlf(raw: &[u8]) -> Vec<u8> {
+    let mut output = Vec::with_capacity(raw.len() + raw.len() / 40);
+    for (i, &byte) in raw.iter().enumerate() {
+        if byte == b'\n' && (i == 0 || raw[i - 1] != b'\r') {
+            output.push(b'\r');
+        }
+        output.push(byte);
+    }
+    output
+}
+
+/// 拆分头部与正文，头部保留折叠行
+pub(super) fn split_message(raw: &[u8]) -> (Vec<RawHeader>, Vec<u8>) {
+    let raw = to_crlf(raw);
+    let (head, body) = match raw.windows(4).position(|w| w == b"\r\n\r\n") {
+        Some(pos) => (&raw[..pos], raw[pos + 4..].to_vec()),
+        None => (&raw[..], Vec::new()),
+    };
+
+    let mut headers: Vec<RawHeader> = Vec::new();
+    for line in split_lines(head) {
+        if line.starts_with(b" ") || line.starts_with(b"\t") {
+            if let Some(last) = headers.last_mut() {
+                last.raw.extend_from_slice(b"\r\n");
+                last.raw.extend_from_slice(line);
+            }
+        } else if let Some(colon) = line.iter().position(|&byte| byte == b':') {
+            headers.push(RawHeader {
+                name: String::from_utf8_lossy(&line[..colon]).trim().to_lowercase(),
+                raw: line.to_vec(),
+            });
+        }
+    }
+    (headers, body)
+}
+
+fn split_lines(data: &[u8]) -> Vec<&[u8]> {
+    let mut lines = Vec::new();
+    let mut rest = data;
+    while let Some(pos) = rest.windows(2).position(|w| w == b"\r\n") {
+        lines.push(&rest[..pos]);
+        rest = &rest[pos + 2..];
+    }
+    lines.push(rest);
+    lines
+}
+
+fn is_wsp(byte: &u8) -> bool {
+    *byte == b' ' || *byte == b'\t'
+}
+
+/// 把连续的 WSP（空格和制表符）压缩为一个空格；按字节处理，其他字节原样保留
+fn compress_whitespace(data: &[u8]) -> Vec<u8> {
+    let mut output = Vec::with_capacity(data.len());
+    let mut in_space = false;
+    for byte in data {
+        if is_wsp(byte) {
+            in_space = true;
+        } else {
+            if in_space {
+                output.push(b' ');
+            }
+            in_space = false;
+            output.push(*byte);
+        }
+    }
+    if in_space {
+        output.push(b' ');
+    }
+    output
+}
+
+fn trim_wsp(data: &[u8]) -> &[u8] {
+    let start = data.iter().position(|byte| !is_wsp(byte)).unwrap_or(data.len());
+    let end = data.iter().rposition(|byte| !is_wsp(byte)).map_or(start, |pos| pos + 1);
+    &data[start..end]
+}
+
+pub(super) fn canonical_header(raw: &[u8], canon: Canonicalization) -> Vec<u8> {
+    match canon {
+        Canonicalization::Simple => raw.to_vec(),
+        Canonicalization::Relaxed => {
+            let colon = raw.iter().position(|&byte| byte == b':').unwrap_or(raw.len());
+            let value: Vec<u8> = raw
+                .get(colon + 1..)
+                .unwrap_or_default()
+                .iter()
+                .copied()
+                .filter(|&byte| byte != b'\r' && byte != b'\n')
+                .collect();
+            let mut output = String::from_utf8_lossy(trim_wsp(&raw[..colon])).to_lowercase().into_bytes();
+            output.push(b':');
+            output.extend_from_slice(trim_wsp(&compress_whitespace(&value)));
+            output
+        }
+    }
+}
+
+pub(super) fn canonical_body(body: &[u8], canon: Canonicalization) -> Vec<u8> {
+    let mut lines: Vec<Vec<u8>> = split_lines(body).into_iter().map(<[u8]>::to_vec).collect();
+
+    if canon == Canonicalization::Relaxed {
+        for line in &mut lines {
+            let mut compressed = compress_whitespace(line);
+            if compressed.last() == Some(&b' ') {
+                compressed.pop();
+            }
+            *line = compressed;
+        }
+    }
+    while lines.last().is_some_and(|line| line.is_empty()) {
+        lines.pop();
+    }
+
+    if lines.is_empty() {
+        return match canon {
+            Canonicalization::Simple => b"\r\n".to_vec(),
+            Canonicalization::Relaxed => Vec::new(),
+        };
+    }
+    let mut output = Vec::with_capacity(body.len());
+    for line in lines {
+        output.extend_from_slice(&line);
+        output.extend_from_slice(b"\r\n");
+    }
+    output
+}
+
+/// 解析 `tag=value; ...` 形式的标签列表
+pub(super) fn parse_tags(value: &str) -> HashMap<String, String> {
+    value
+        .split(';')
+        .filter_map(|part| part.split_once('='))
+        .map(|(tag, value)| (tag.trim().to_string(), value.trim().to_string()))
+        .collect()
+}
+
+/// 去掉签名头中 b= 的值，用于计算头部哈希
+fn strip_signature_value(raw: &[u8]) -> Vec<u8> {
+    let colon = raw.iter().position(|&byte| byte == b':').unwrap_or(raw.len());
+    let mut output = raw[..colon].to_vec();
+    output.push(b':');
+    for (i, part) in raw.get(colon + 1..).unwrap_or_default().split(|&byte| byte == b';').enumerate() {
+        if i > 0 {
+            output.push(b';');
+        }
+        match part.iter().position(|&byte| byte == b'=') {
+            Some(equals) if part[..equals].trim_ascii() == b"b" => output.extend_from_slice(&part[..=equals]),
+            _ => output.extend_from_slice(part),
+        }
+    }
+    output
+}
+
+/// 按 h= 的顺序选取头部（同名头部自下而上取用），最后附上去掉 b= 值的签名头
+pub(super) fn signed_header_data(
+    headers: &[RawHeader],
+    signature: &[u8],
+    signed: &[String],
+    canon: Canonicalization,
+) -> Vec<u8> {
+    let mut used: HashMap<String, usize> = HashMap::new();
+    let mut data = Vec::new();
+    for name in signed {
+        let lower = name.trim().to_lowercase();
+        let skip = used.entry(lower.clone()).or_default();
+        if let Some(header) = headers.iter().rev().filter(|h| h.name == lower).nth(*skip) {
+            data.extend_from_slice(&canonical_header(&header.raw, canon));
+            data.extend_from_slice(b"\r\n");
+        }
+        *skip += 1;
+    }
+    data.extend_from_slice(&canonical_header(&strip_signature_value(signature), canon));
+    data
+}
+
+/// 验证邮件中的全部 DKIM 签名；没有签名时返回空列表
+pub async fn verify(raw: &[u8], resolver: &dyn DnsResolver) -> Vec<DkimOutcome> {
+    let (headers, body) = split_message(raw);
+    let mut outcomes = Vec::new();
+
+    for signature in headers.iter().filter(|h| h.name == "dkim-signature").take(MAX_SIGNATURES) {
+        let raw = String::from_utf8_lossy(&signature.raw);
+        let tags = parse_tags(&raw.split_once(':').map(|(_, v)| v).unwrap_or("").replace("\r\n", ""));
+        let domain = tags.get("d").cloned().unwrap_or_default().to_lowercase();
+        let result = match verify_signature(&headers, &body, signature, &tags, resolver).await {
+            Ok(()) => AuthResult::Pass,
+            Err((result, reason)) => {
+                debug!(domain = %domain, result = %result, "DKIM signature not verified: {}", reason);
+                result
+            }
+        };
+        outcomes.push(DkimOutcome { domain, result });
+    }
+    outcomes
+}
+
+type VerifyError = (AuthResult, String);
+
+fn perm(reason: impl Into<String>) -> VerifyError {
+    (AuthResult::PermError, reason.into())
+}
+
+fn fail(reason: impl Into<String>) -> VerifyError {
+    (AuthResult::Fail, reason.into())
+}
+
+async fn verify_signature(
+    headers: &[RawHeader],
+    body: &[u8],
+    signature: &RawHeader,
+    tags: &HashMap<String, String>,
+    resolver: &dyn DnsResolver,
+) -> Result<(), VerifyError> {
+    let tag = |name: &str| tags.get(name).map(String::as_str).ok_or_else(|| perm(format!("missing {}= tag", name)));
+
+    if tag("v")? != "1" {
+        return Err(perm("unsupported version"));
+    }
+    let algorithm = match tag("a")? {
+        "rsa-sha256" => Algorithm::RsaSha256,
+        "ed25519-sha256" => Algorithm::Ed25519Sha256,
+        other => return Err(perm(format!("unsupported algorithm {}", other))),
+    };
+    let (header_canon, body_canon) = parse_canonicalization(tags.get("c").map(String::as_str).unwrap_or("simple"))?;
+    let domain = tag("d")?;
+    let selector = tag("s")?;
+    let signed: Vec<String> = tag("h")?.split(':').map(|name| name.trim().to_string()).collect();
+    if !signed.iter().any(|name| name.eq_ignore_ascii_case("from")) {
+        return Err(perm("From header is not signed"));
+    }
+    if let Some(expires) = tags.get("x").and_then(|x| x.parse::<i64>().ok()) {
+        if expires < chrono::Utc::now().timestamp() {
+            return Err(fail("signature expired"));
+        }
+    }
+    let decode = |name: &str| -> Result<Vec<u8>, VerifyError> {
+        let value: String = tag(name)?.chars().filter(|c| !c.is_whitespace()).collect();
+        BASE64.decode(value).map_err(|e| perm(format!("invalid {}= value: {}", name, e)))
+    };
+    let body_hash = decode("bh")?;
+    let signature_bytes = decode("b")?;
+
+    // 正文哈希；l= 只对前若干字节签名时，之后追加的内容不受保护，不能算作通过
+    let canonical = canonical_body(body, body_canon);
+    if let Some(length) = tags.get("l") {
+        let length: usize = length.parse().map_err(|_| perm(format!("invalid l= value {}", length)))?;
+        if length > canonical.len() {
+            return Err(fail("l= exceeds body length"));
+        }
+        if length < canonical.len() {
+            return Err((AuthResult::Neutral, format!("signature covers only {} of {} body bytes", length, canonical.len())));
+        }
+    }
+    if Sha256::digest(&canonical).as_slice() != body_hash.as_slice() {
+        return Err(fail("body hash mismatch"));
+    }
+
+    let key = fetch_key(domain, selector, resolver).await?;
+    let data = signed_header_data(headers, &signature.raw, &signed, header_canon);
+    let digest = Sha256::digest(&data);
+
+    match algorithm {
+        Algorithm::RsaSha256 => {
+            let key = RsaPublicKey::from_public_key_der(&key)
+                .or_else(|_| RsaPublicKey::from_pkcs1_der(&key))
+                .map_err(|e| perm(format!("invalid RSA key: {}", e)))?;
+            if key.size() * 8 < MIN_RSA_BITS {
+                return Err(perm("RSA key too short"));
+            }
+            key.verify(Pkcs1v15Sign::new::<Sha256>(), &digest, &signature_bytes)
+                .map_err(|_| fail("signature mismatch"))
+        }
+        Algorithm::Ed25519Sha256 => {
+            let key: [u8; 32] = key.try_into().map_err(|_| perm("invalid Ed25519 key length"))?;
+            let key = ed25519_dalek::VerifyingKey::from_bytes(&key).map_err(|e| perm(format!("invalid Ed25519 key: {}", e)))?;
+            let signature = ed25519_dalek::Signature::from_slice(&signature_bytes)
+                .map_err(|e| perm(format!("invalid Ed25519 signature: {}", e)))?;
+            key.verify_strict(&digest, &signature).map_err(|_| fail("signature mismatch"))
+        }
+    }
+}
+
+fn parse_canonicalization(value: &str) -> Result<(Canonicalization, Canonicalization), VerifyError> {
+    let parse = |name: &str| match name.trim() {
+        "simple" => Ok(Canonicalization::Simple),
+        "relaxed" => Ok(Canonicalization::Relaxed),
+        other => Err(perm(format!("unknown canonicalization {}", other))),
+    };
+    match value.split_once('/') {
+        Some((header, body)) => Ok((parse(header)?, parse(body)?)),
+        None => Ok((parse(value)?, Canonicalization::Simple)),
+    }
+}
+
+/// 查询 `<selector>._domainkey.<domain>` 中的公钥
+async fn fetch_key(domain: &str, selector: &str, resolver: &dyn DnsResolver) -> Result<Vec<u8>, VerifyError> {
+    let name = format!("{}._domainkey.{}", selector, domain);
+    let records = resolver.txt(&name).await.map_err(|e| match e {
+        DnsError::NotFound => perm(format!("no key record at {}", name)),
+        DnsError::Failed(e) => (AuthResult::TempError, e),
+    })?;
+    let record = records.first().ok_or_else(|| perm(format!("no key record at {}", name)))?;
+
+    let tags = parse_tags(record);
+    if tags.get("v").is_some_and(|v| v != "DKIM1") {
+        return Err(perm("invalid key record version"));
+    }
+    let key: String = tags.get("p").map(|p| p.chars().filter(|c| !c.is_whitespace()).collect()).unwrap_or_default();
+    if key.is_empty() {
+        return Err(fail("key revoked"));
+    }
+    BASE64.decode(key).map_err(|e| perm(format!("invalid key encoding: {}", e)))
+}
+
+#[cfg(test)]
+pub(super) mod tests {
+    use super::*;
+    use crate::auth::dns::StaticResolver;
+    use ed25519_dalek::{Signer, SigningKey};
+    use rsa::pkcs8::EncodePublicKey;
+    use rsa::RsaPrivateKey;
+
+    /// 测试签名用的密钥
+    pub enum TestKey {
+        Ed25519(SigningKey),
+        Rsa(RsaPrivateKey),
+    }
+
+    impl TestKey {
+        pub fn ed25519() -> Self {
+            Self::Ed25519(SigningKey::from_bytes(&[7u8; 32]))
+        }
+
+        /// 发布在 DNS 中的公钥记录
+        pub fn record(&self) -> String {
+            match self {
+                Self::Ed25519(key) => format!("v=DKIM1; k=ed25519; p={}", BASE64.encode(key.verifying_key().as_bytes())),
+                Self::Rsa(key) => format!(
+                    "v=DKIM1; k=rsa; p={}",
+                    BASE64.encode(key.to_public_key().to_public_key_der().unwrap().as_bytes())
+                ),
+            }
+        }
+    }
+
+    /// 按 relaxed/relaxed 规范为邮件加上 DKIM-Signature 头
+    pub fn sign(raw: &str, domain: &str, selector: &str, key: &TestKey) -> String {
+        sign_with_length(raw, domain, selector, key, None)
+    }
+
+    /// 同 `sign`，`length` 不为空时加上 l= 标签
+    fn sign_with_length(raw: &str, domain: &str, selector: &str, key: &TestKey, length: Option<usize>) -> String {
+        String::from_utf8(sign_bytes(raw.as_bytes(), domain, selector, key, length)).unwrap()
+    }
+
+    /// 同 `sign_with_length`，用于非 UTF-8 的 8 位邮件
+    fn sign_bytes(raw: &[u8], domain: &str, selector: &str, key: &TestKey, length: Option<usize>) -> Vec<u8> {
+        let (headers, body) = split_message(raw);
+        let canonical = canonical_body(&body, Canonicalization::Relaxed);
+        let body_hash = BASE64.encode(Sha256::digest(&canonical[..length.unwrap_or(canonical.len())]));
+        let length = length.map(|length| format!(" l={};", length)).unwrap_or_default();
+        let algorithm = match key {
+            TestKey::Ed25519(_) => "ed25519-sha256",
+            TestKey::Rsa(_) => "rsa-sha256",
+        };
+        let unsigned = format!(
+            "DKIM-Signature: v=1; a={}; c=relaxed/relaxed; d={}; s={};{}\r\n\th=from:to:subject; bh={}; b=",
+            algorithm, domain, selector, length, body_hash
+        );
+        let signed = ["from", "to", "subject"].map(String::from);
+        let digest = Sha256::digest(signed_header_data(&headers, unsigned.as_bytes(), &signed, Canonicalization::Relaxed));
+        let signature = match key {
+            TestKey::Ed25519(key) => key.sign(&digest).to_bytes().to_vec(),
+            TestKey::Rsa(key) => key.sign(Pkcs1v15Sign::new::<Sha256>(), &digest).unwrap(),
+        };
+        [format!("{}{}\r\n", unsigned, BASE64.encode(signature)).as_bytes(), raw].concat()
+    }
+
+    const MESSAGE: &str = "From: Alice <alice@example.com>\r\nTo: sentio@example.org\r\nSubject:  Project   update \r\n\r\nHello  world \r\n\r\n\r\n";
+
+    #[test]
+    fn test_relaxed_canonicalization() {
+        assert_eq!(
+            canonical_header(b"Subject: \t Project\r\n   update  ", Canonicalization::Relaxed),
+            b"subject:Project update"
+        );
+        assert_eq!(canonical_body(b"a  b \t\r\n\r\n\r\n", Canonicalization::Relaxed), b"a b\r\n");
+        assert_eq!(canonical_body(b"a  b \r\n\r\n", Canonicalization::Simple), b"a  b \r\n");
+        assert_eq!(canonical_body(b"", Canonicalization::Simple), b"\r\n");
+        assert!(canonical_body(b"\r\n", Canonicalization::Relaxed).is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_verify_rsa_and_ed25519_signatures() {
+        let rsa = TestKey::Rsa(RsaPrivateKey::new(&mut rsa::rand_core::OsRng, 1024).unwrap());
+        let ed25519 = TestKey::ed25519();
+        let resolver = StaticResolver::default()
+            .with_txt("rsa._domainkey.example.com", &rsa.record())
+            .with_txt("ed._domainkey.example.com", &ed25519.record());
+
+        for (selector, key) in [("rsa", &rsa), ("ed", &ed25519)] {
+            // 经过 Maildir 后变成 LF 换行也应通过
+            let signed = sign(MESSAGE, "example.com", selector, key).replace("\r\n", "\n");
+            let outcomes = verify(signed.as_bytes(), &resolver).await;
+            assert_eq!(outcomes, vec![DkimOutcome { domain: "example.com".to_string(), result: AuthResult::Pass }]);
+
+            let tampered = signed.replace("Hello", "Goodbye");
+            assert_eq!(verify(tampered.as_bytes(), &resolver).await[0].result, AuthResult::Fail);
+
+            let forged = signed.replace("alice@example.com", "mallory@example.com");
+            assert_eq!(verify(forged.as_bytes(), &resolver).await[0].result, AuthResult::Fail);
+        }
+
+        let unknown_selector = sign(MESSAGE, "example.com", "missing", &ed25519);
+        assert_eq!(verify(unknown_selector.as_bytes(), &resolver).await[0].result, AuthResult::PermError);
+        assert!(verify(MESSAGE.as_bytes(), &resolver).await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_partial_body_length_is_not_a_pass() {
+        let key = TestKey::ed25519();
+        let resolver = StaticResolver::default().with_txt("sel._domainkey.example.com", &key.record());
+        let length = canonical_body(split_message(MESSAGE.as_bytes()).1.as_slice(), Canonicalization::Relaxed).len();
+
+        let whole = sign_with_length(MESSAGE, "example.com", "sel", &key, Some(length));
+        assert_eq!(verify(whole.as_bytes(), &resolver).await[0].result, AuthResult::Pass);
+
+        // l= 之后追加的内容没有签名
+        let appended = format!("{}Please wire the payment to account 1234.\r\n", whole);
+        assert_eq!(verify(appended.as_bytes(), &resolver).await[0].result, AuthResult::Neutral);
+    }
+
+    #[tokio::test]
+    async fn test_8bit_gbk_message_verifies() {
+        // 未做传输编码的 GBK 邮件（qq.com / 163.com 常见）：“你好  世界 ”
+        let body = b"\xc4\xe3\xba\xc3  \xca\xc0\xbd\xe7 \r\n\r\n";
+        assert_eq!(canonical_body(body, Canonicalization::Relaxed), b"\xc4\xe3\xba\xc3 \xca\xc0\xbd\xe7\r\n");
+        assert_eq!(canonical_header(b"Subject:  \xd6\xdc\xb1\xa8 ", Canonicalization::Relaxed), b"subject:\xd6\xdc\xb1\xa8");
+
+        let key = TestKey::ed25519();
+        let resolver = StaticResolver::default().with_txt("sel._domainkey.qq.com", &key.record());
+        let raw = [
+            b"From: Alice <alice@qq.com>\r\nTo: sentio@example.org\r\nSubject: \xd6\xdc\xb1\xa8\r\n".as_slice(),
+            b"Content-Type: text/plain; charset=GBK\r\nContent-Transfer-Encoding: 8bit\r\n\r\n",
+            body,
+        ]
+        .concat();
+        let signed = sign_bytes(&raw, "qq.com", "sel", &key, None);
+        assert_eq!(verify(&signed, &resolver).await[0].result, AuthResult::Pass);
+    }
+}
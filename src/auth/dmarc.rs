@@ -0,0 +1,134 @@
+use super::dns::{DnsError, DnsResolver};
+use super::dkim::parse_tags;
+use super::AuthResult;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DmarcPolicy {
+    None,
+    Quarantine,
+    Reject,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Alignment {
+    Relaxed,
+    Strict,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DmarcRecord {
+    pub policy: DmarcPolicy,
+    pub subdomain_policy: Option<DmarcPolicy>,
+    pub dkim_alignment: Alignment,
+    pub spf_alignment: Alignment,
+}
+
+impl DmarcRecord {
+    pub fn parse(record: &str) -> Option<Self> {
+        if !record.trim_start().starts_with("v=DMARC1") {
+            return None;
+        }
+        let tags = parse_tags(record);
+        let policy = |name: &str| match tags.get(name).map(|p| p.to_ascii_lowercase()).as_deref() {
+            Some("none") => Some(DmarcPolicy::None),
+            Some("quarantine") => Some(DmarcPolicy::Quarantine),
+            Some("reject") => Some(DmarcPolicy::Reject),
+            _ => None,
+        };
+        let alignment = |name: &str| match tags.get(name).map(String::as_str) {
+            Some("s") => Alignment::Strict,
+            _ => Alignment::Relaxed,
+        };
+
+        Some(Self {
+            policy: policy("p")?,
+            subdomain_policy: policy("sp"),
+            dkim_alignment: alignment("adkim"),
+            spf_alignment: alignment("aspf"),
+        })
+    }
+}
+
+/// 组织域：按公共后缀列表去掉子域名后的注册域，如 `mail.example.co.uk` -> `example.co.uk`；
+/// 域名本身就是公共后缀（如 `co.in`）时原样返回，不会与其下的任何注册域对齐
+pub fn organizational_domain(domain: &str) -> String {
+    let domain = domain.trim_end_matches('.').to_lowercase();
+    match psl::domain(domain.as_bytes()) {
+        Some(registrable) => String::from_utf8_lossy(registrable.as_bytes()).into_owned(),
+        None => domain,
+    }
+}
+
+/// 通过认证的域名是否与 From 域名对齐
+pub fn aligned(authenticated: &str, from: &str, alignment: Alignment) -> bool {
+    match alignment {
+        Alignment::Strict => authenticated.eq_ignore_ascii_case(from),
+        Alignment::Relaxed => organizational_domain(authenticated) == organizational_domain(from),
+    }
+}
+
+/// 查询 From 域名的 DMARC 记录，找不到时回退到组织域；返回记录及应采用的策略
+pub async fn lookup(from_domain: &str, resolver: &dyn DnsResolver) -> Result<Option<(DmarcRecord, DmarcPolicy)>, AuthResult> {
+    let org_domain = organizational_domain(from_domain);
+
+    if let Some(record) = fetch(from_domain, resolver).await? {
+        let policy = record.policy;
+        return Ok(Some((record, policy)));
+    }
+    if org_domain != from_domain.to_lowercase() {
+        if let Some(record) = fetch(&org_domain, resolver).await? {
+            let policy = record.subdomain_policy.unwrap_or(record.policy);
+            return Ok(Some((record, policy)));
+        }
+    }
+    Ok(None)
+}
+
+async fn fetch(domain: &str, resolver: &dyn DnsResolver) -> Result<Option<DmarcRecord>, AuthResult> {
+    match resolver.txt(&format!("_dmarc.{}", domain)).await {
+        Ok(records) => Ok(records.iter().find_map(|record| DmarcRecord::parse(record))),
+        Err(DnsError::NotFound) => Ok(None),
+        Err(DnsError::Failed(_)) => Err(AuthResult::TempError),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::dns::StaticResolver;
+
+    #[test]
+    fn test_organizational_domain_and_alignment() {
+        assert_eq!(organizational_domain("mail.example.com"), "example.com");
+        assert_eq!(organizational_domain("a.b.example.co.uk"), "example.co.uk");
+        assert_eq!(organizational_domain("example.com."), "example.com");
+        assert!(aligned("mail.example.com", "example.com", Alignment::Relaxed));
+        assert!(!aligned("mail.example.com", "example.com", Alignment::Strict));
+        assert!(!aligned("example.net", "example.com", Alignment::Relaxed));
+    }
+
+    #[test]
+    fn test_registrable_domains_under_public_suffix_do_not_align() {
+        assert_eq!(organizational_domain("mail.victim.co.in"), "victim.co.in");
+        assert_eq!(organizational_domain("co.in"), "co.in");
+        assert!(!aligned("attacker.co.in", "victim.co.in", Alignment::Relaxed));
+        assert!(!aligned("attacker.com.sg", "boss.victim.com.sg", Alignment::Relaxed));
+        assert!(!aligned("attacker.github.io", "victim.github.io", Alignment::Relaxed));
+        assert!(aligned("mail.victim.co.za", "victim.co.za", Alignment::Relaxed));
+    }
+
+    #[tokio::test]
+    async fn test_lookup_falls_back_to_subdomain_policy() {
+        let resolver = StaticResolver::default()
+            .with_txt("_dmarc.example.com", "v=DMARC1; p=reject; sp=quarantine; adkim=s; rua=mailto:d@example.com")
+            .with_txt("_dmarc.bad.org", "v=DMARC1; rua=mailto:d@bad.org");
+
+        let (record, policy) = lookup("example.com", &resolver).await.unwrap().unwrap();
+        assert_eq!((policy, record.dkim_alignment, record.spf_alignment), (DmarcPolicy::Reject, Alignment::Strict, Alignment::Relaxed));
+
+        let (_, policy) = lookup("news.example.com", &resolver).await.unwrap().unwrap();
+        assert_eq!(policy, DmarcPolicy::Quarantine);
+
+        assert!(lookup("bad.org", &resolver).await.unwrap().is_none());
+    }
+}
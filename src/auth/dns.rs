@@ -0,0 +1,136 @@
+use async_trait::async_trait;
+use hickory_resolver::error::ResolveErrorKind;
+use hickory_resolver::TokioAsyncResolver;
+use std::net::IpAddr;
+use std::time::Duration;
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum DnsError {
+    #[error("No records found")]
+    NotFound,
+
+    #[error("DNS lookup failed: {0}")]
+    Failed(String),
+}
+
+pub type DnsResult<T> = Result<T, DnsError>;
+
+/// 验证发件人所需的 DNS 查询，测试中可替换为固定记录
+#[async_trait]
+pub trait DnsResolver: Send + Sync {
+    /// TXT 记录，多段字符串已拼接
+    async fn txt(&self, name: &str) -> DnsResult<Vec<String>>;
+    /// A 与 AAAA 记录
+    async fn ip(&self, name: &str) -> DnsResult<Vec<IpAddr>>;
+    /// MX 记录的主机名，按优先级排序
+    async fn mx(&self, name: &str) -> DnsResult<Vec<String>>;
+}
+
+/// 使用系统 DNS 配置的解析器
+pub struct SystemResolver {
+    inner: TokioAsyncResolver,
+}
+
+impl SystemResolver {
+    pub fn new(timeout: Duration) -> DnsResult<Self> {
+        let (config, mut options) =
+            hickory_resolver::system_conf::read_system_conf().map_err(|e| DnsError::Failed(e.to_string()))?;
+        options.timeout = timeout;
+        Ok(Self {
+            inner: TokioAsyncResolver::tokio(config, options),
+        })
+    }
+}
+
+fn map_error(error: hickory_resolver::error::ResolveError) -> DnsError {
+    match error.kind() {
+        ResolveErrorKind::NoRecordsFound { .. } => DnsError::NotFound,
+        _ => DnsError::Failed(error.to_string()),
+    }
+}
+
+/// 查询名称加上结尾的点，避免解析器追加本地搜索域
+fn fqdn(name: &str) -> String {
+    format!("{}.", name.trim_end_matches('.'))
+}
+
+#[async_trait]
+impl DnsResolver for SystemResolver {
+    async fn txt(&self, name: &str) -> DnsResult<Vec<String>> {
+        let lookup = self.inner.txt_lookup(fqdn(name)).await.map_err(map_error)?;
+        Ok(lookup
+            .iter()
+            .map(|txt| {
+                txt.txt_data()
+                    .iter()
+                    .map(|part| String::from_utf8_lossy(part))
+                    .collect::<String>()
+            })
+            .collect())
+    }
+
+    async fn ip(&self, name: &str) -> DnsResult<Vec<IpAddr>> {
+        let lookup = self.inner.lookup_ip(fqdn(name)).await.map_err(map_error)?;
+        Ok(lookup.iter().collect())
+    }
+
+    async fn mx(&self, name: &str) -> DnsResult<Vec<String>> {
+        let lookup = self.inner.mx_lookup(fqdn(name)).await.map_err(map_error)?;
+        let mut records: Vec<_> = lookup.iter().collect();
+        records.sort_by_key(|mx| mx.preference());
+        Ok(records
+            .into_iter()
+            .map(|mx| mx.exchange().to_utf8().trim_end_matches('.').to_string())
+            .collect())
+    }
+}
+
+/// 返回预设记录的解析器
+#[cfg(test)]
+#[derive(Default)]
+pub struct StaticResolver {
+    txt: std::collections::HashMap<String, Vec<String>>,
+    ip: std::collections::HashMap<String, Vec<IpAddr>>,
+    mx: std::collections::HashMap<String, Vec<String>>,
+}
+
+#[cfg(test)]
+impl StaticResolver {
+    pub fn with_txt(mut self, name: &str, record: &str) -> Self {
+        self.txt.entry(name.to_lowercase()).or_default().push(record.to_string());
+        self
+    }
+
+    pub fn with_ip(mut self, name: &str, ip: &str) -> Self {
+        self.ip.entry(name.to_lowercase()).or_default().push(ip.parse().unwrap());
+        self
+    }
+
+    pub fn with_mx(mut self, name: &str, host: &str) -> Self {
+        self.mx.entry(name.to_lowercase()).or_default().push(host.to_string());
+        self
+    }
+
+    fn get<T: Clone>(map: &std::collections::HashMap<String, Vec<T>>, name: &str) -> DnsResult<Vec<T>> {
+        map.get(&name.trim_end_matches('.').to_lowercase())
+            .cloned()
+            .ok_or(DnsError::NotFound)
+    }
+}
+
+#[cfg(test)]
+#[async_trait]
+impl DnsResolver for StaticResolver {
+    async fn txt(&self, name: &str) -> DnsResult<Vec<String>> {
+        Self::get(&self.txt, name)
+    }
+
+    async fn ip(&self, name: &str) -> DnsResult<Vec<IpAddr>> {
+        Self::get(&self.ip, name)
+    }
+
+    async fn mx(&self, name: &str) -> DnsResult<Vec<String>> {
+        Self::get(&self.mx, name)
+    }
+}
@@ -0,0 +1,445 @@
+pub mod dkim;
+pub mod dmarc;
+pub mod dns;
+pub mod spf;
+
+pub use dns::{DnsResolver, SystemResolver};
+
+use std::fmt;
+use std::net::IpAddr;
+use tracing::{debug, warn};
+
+use crate::config::AuthPolicy;
+use crate::email::InboundEmail;
+use dkim::DkimOutcome;
+use dmarc::{Alignment, DmarcPolicy};
+use spf::SpfRequest;
+
+/// DKIM / SPF / DMARC 的检查结果
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AuthResult {
+    Pass,
+    Fail,
+    SoftFail,
+    Neutral,
+    None,
+    TempError,
+    PermError,
+}
+
+impl fmt::Display for AuthResult {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Pass => "pass",
+            Self::Fail => "fail",
+            Self::SoftFail => "softfail",
+            Self::Neutral => "neutral",
+            Self::None => "none",
+            Self::TempError => "temperror",
+            Self::PermError => "permerror",
+        })
+    }
+}
+
+/// 一封邮件的发件人验证结果
+#[derive(Debug, Clone)]
+pub struct AuthReport {
+    pub from_domain: String,
+    pub dkim: Vec<DkimOutcome>,
+    pub spf: AuthResult,
+    /// SPF 检查的域名（MAIL FROM 或 HELO）
+    pub spf_domain: Option<String>,
+    pub dmarc: AuthResult,
+    /// 发件域公布的 DMARC 策略
+    pub dmarc_policy: Option<DmarcPolicy>,
+    /// 邮件有多个 From 头：签名验证的和工作流使用的可能不是同一个，按 DMARC 失败处理
+    pub multiple_from: bool,
+}
+
+impl AuthReport {
+    fn has_temp_error(&self) -> bool {
+        self.dmarc == AuthResult::TempError
+            || self.spf == AuthResult::TempError
+            || self.dkim.iter().any(|outcome| outcome.result == AuthResult::TempError)
+    }
+}
+
+impl fmt::Display for AuthReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut parts: Vec<String> = self
+            .dkim
+            .iter()
+            .map(|outcome| format!("dkim={} (d={})", outcome.result, outcome.domain))
+            .collect();
+        if parts.is_empty() {
+            parts.push("dkim=none".to_string());
+        }
+        parts.push(match &self.spf_domain {
+            Some(domain) => format!("spf={} ({})", self.spf, domain),
+            None => format!("spf={}", self.spf),
+        });
+        parts.push(match self.dmarc_policy {
+            _ if self.multiple_from => format!("dmarc={} (multiple From headers)", self.dmarc),
+            Some(policy) => format!("dmarc={} (from={}, p={:?})", self.dmarc, self.from_domain, policy),
+            None => format!("dmarc={} (from={})", self.dmarc, self.from_domain),
+        });
+        f.write_str(&parts.join(" "))
+    }
+}
+
+/// 根据验证结果对邮件的处理决定
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Verdict {
+    Accept,
+    Reject,
+    /// DNS 临时失败，稍后重试
+    Retry,
+}
+
+/// 在交给模型之前验证发件人的 DKIM 签名、SPF 和 DMARC 对齐
+pub struct Authenticator {
+    policy: AuthPolicy,
+    resolver: Box<dyn DnsResolver>,
+    /// 自己的 MX / 中继地址段，由它们转交的 Received 跳会被跳过
+    trusted_relays: Vec<(IpAddr, Option<u8>)>,
+}
+
+impl Authenticator {
+    pub fn new(policy: AuthPolicy, resolver: Box<dyn DnsResolver>) -> Self {
+        Self {
+            policy,
+            resolver,
+            trusted_relays: Vec::new(),
+        }
+    }
+
+    pub fn with_trusted_relays(mut self, relays: Vec<(IpAddr, Option<u8>)>) -> Self {
+        self.trusted_relays = relays;
+        self
+    }
+
+    pub async fn verify(&self, message: &InboundEmail) -> AuthReport {
+        let from_domain = domain_of(&message.from.email).to_lowercase();
+        let dkim = dkim::verify(&message.raw, self.resolver.as_ref()).await;
+
+        // SPF 检查投递到我们这里的那一跳，发件人取 Return-Path，为空时用 HELO
+        let (spf, spf_domain) = match self.connecting_client(message) {
+            Some((ip, helo)) => {
+                let sender = match (return_path(message), &helo) {
+                    (Some(sender), _) => Some(sender),
+                    (None, Some(helo)) => Some(format!("postmaster@{}", helo)),
+                    (None, None) => None,
+                };
+                match sender {
+                    Some(sender) => {
+                        let domain = domain_of(&sender).to_lowercase();
+                        let request = SpfRequest {
+                            ip,
+                            helo: helo.as_deref(),
+                            sender: &sender,
+                        };
+                        (spf::check_host(&request, &domain, self.resolver.as_ref()).await, Some(domain))
+                    }
+                    None => (AuthResult::None, None),
+                }
+            }
+            None => (AuthResult::None, None),
+        };
+
+        let (dmarc, dmarc_policy) = match dmarc::lookup(&from_domain, self.resolver.as_ref()).await {
+            Err(result) => (result, None),
+            Ok(found) => {
+                // 没有 DMARC 记录时按宽松对齐计算，供 strict 策略使用
+                let (dkim_alignment, spf_alignment) = found
+                    .as_ref()
+                    .map(|(record, _)| (record.dkim_alignment, record.spf_alignment))
+                    .unwrap_or((Alignment::Relaxed, Alignment::Relaxed));
+                let dkim_aligned = dkim.iter().any(|outcome| {
+                    outcome.result == AuthResult::Pass && dmarc::aligned(&outcome.domain, &from_domain, dkim_alignment)
+                });
+                let spf_aligned = spf == AuthResult::Pass
+                    && spf_domain
+                        .as_deref()
+                        .is_some_and(|domain| dmarc::aligned(domain, &from_domain, spf_alignment));
+
+                let result = match (dkim_aligned || spf_aligned, &found) {
+                    (true, _) => AuthResult::Pass,
+                    (false, Some(_)) => AuthResult::Fail,
+                    (false, None) => AuthResult::None,
+                };
+                (result, found.map(|(_, policy)| policy))
+            }
+        };
+
+        // RFC 7489 §6.6.1：多个 From 头时无法确定对齐的作者域
+        let multiple_from = message.headers.iter().filter(|(name, _)| name.eq_ignore_ascii_case("From")).count() > 1;
+        let dmarc = if multiple_from { AuthResult::Fail } else { dmarc };
+
+        AuthReport {
+            from_domain,
+            dkim,
+            spf,
+            spf_domain,
+            dmarc,
+            dmarc_policy,
+            multiple_from,
+        }
+    }
+
+    pub fn verdict(&self, report: &AuthReport) -> Verdict {
+        let accepted = match self.policy {
+            AuthPolicy::Off => true,
+            _ if report.multiple_from => false,
+            AuthPolicy::Dmarc => match report.dmarc {
+                AuthResult::Fail => !matches!(report.dmarc_policy, Some(DmarcPolicy::Quarantine | DmarcPolicy::Reject)),
+                AuthResult::TempError => false,
+                _ => true,
+            },
+            AuthPolicy::Strict => report.dmarc == AuthResult::Pass,
+        };
+
+        match (accepted, report.has_temp_error()) {
+            (true, _) => Verdict::Accept,
+            (false, true) => Verdict::Retry,
+            (false, false) => Verdict::Reject,
+        }
+    }
+}
+
+fn domain_of(address: &str) -> &str {
+    address.rsplit_once('@').map(|(_, domain)| domain).unwrap_or(address)
+}
+
+fn return_path(message: &InboundEmail) -> Option<String> {
+    let value = message.header("Return-Path")?.trim();
+    let address = value.trim_start_matches('<').trim_end_matches('>').trim();
+    (!address.is_empty()).then(|| address.to_string())
+}
+
+impl Authenticator {
+    /// 自上而下跳过本机内部转交和受信中继转交的 Received 头，返回其下第一跳的连接方 IP 和 HELO 名称。
+    /// 只有这一跳是我们自己的服务器记录的，更下面的头可能由发件人伪造，因此不再往下找；
+    /// 这一跳无法解析时返回 None，不做 SPF 检查
+    fn connecting_client(&self, message: &InboundEmail) -> Option<(IpAddr, Option<String>)> {
+        let received = message.headers.iter().filter(|(name, _)| name.eq_ignore_ascii_case("Received"));
+        for (_, value) in received {
+            match parse_received(value) {
+                Hop::Local => continue,
+                Hop::Remote(ip, _) if ip.is_loopback() || self.is_trusted_relay(ip) => continue,
+                Hop::Remote(ip, helo) => {
+                    if !is_public(&ip) {
+                        debug!("Received hop from private address {}; add it to email.auth.trusted_relays if it is a relay", ip);
+                    }
+                    return Some((ip, helo));
+                }
+                Hop::Unparsed => {
+                    warn!("Cannot find the client address in Received header {:?}, skipping SPF", value);
+                    return None;
+                }
+            }
+        }
+        debug!("No external Received hop found, skipping SPF");
+        None
+    }
+
+    fn is_trusted_relay(&self, ip: IpAddr) -> bool {
+        self.trusted_relays.iter().any(|&(network, prefix)| spf::in_network(ip, network, prefix))
+    }
+}
+
+/// 解析 `192.0.2.25`、`192.0.2.0/24` 或 `2001:db8::/32` 形式的地址段
+pub fn parse_network(value: &str) -> Option<(IpAddr, Option<u8>)> {
+    let (address, prefix) = match value.trim().split_once('/') {
+        Some((address, prefix)) => (address, Some(prefix.parse::<u8>().ok()?)),
+        None => (value.trim(), None),
+    };
+    let address: IpAddr = address.parse().ok()?;
+    let max = if address.is_ipv4() { 32 } else { 128 };
+    prefix.is_none_or(|prefix| prefix <= max).then_some((address, prefix))
+}
+
+/// 一个 Received 头记录的连接方
+#[derive(Debug, PartialEq)]
+enum Hop {
+    /// 没有 from 子句：由记录它的主机在本地生成
+    Local,
+    Remote(IpAddr, Option<String>),
+    /// 有 from 子句但找不到连接方地址
+    Unparsed,
+}
+
+/// 连接方地址取自接收方记录的 `[ip]`，没有方括号时取括号内的 IP（如 Exchange 的 `(2603:…)`）；
+/// 开头的 HELO 名称由发件人自报，即使是地址字面量也不采用
+fn parse_received(value: &str) -> Hop {
+    let value = value.trim();
+    if !value.get(..5).is_some_and(|from| from.eq_ignore_ascii_case("from ")) {
+        return Hop::Local;
+    }
+    let clause = &value[5..];
+    let clause = match clause.to_ascii_lowercase().find(" by ") {
+        Some(pos) => &clause[..pos],
+        None => clause,
+    };
+
+    let clause = clause.trim_start();
+    let (helo, recorded) = clause.split_at(clause.find(char::is_whitespace).unwrap_or(clause.len()));
+    let helo = Some(helo.trim_end_matches('.').to_lowercase()).filter(|helo| !helo.is_empty());
+    let parse = |token: &str| {
+        let token = token.strip_prefix("IPv6:").or_else(|| token.strip_prefix("ipv6:")).unwrap_or(token);
+        token.parse::<IpAddr>().ok()
+    };
+
+    let bracketed = recorded
+        .split('[')
+        .skip(1)
+        .filter_map(|rest| rest.split_once(']'))
+        .find_map(|(address, _)| parse(address));
+    let parenthesized = || {
+        recorded
+            .split('(')
+            .skip(1)
+            .filter_map(|rest| rest.split_once(')'))
+            .flat_map(|(inner, _)| inner.split_whitespace())
+            .find_map(parse)
+    };
+    match bracketed.or_else(parenthesized) {
+        Some(ip) => Hop::Remote(ip, helo),
+        None => Hop::Unparsed,
+    }
+}
+
+fn is_public(ip: &IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            !(ip.is_private() || ip.is_loopback() || ip.is_link_local() || ip.is_unspecified() || ip.octets()[0] == 100 && (ip.octets()[1] & 0xc0) == 64)
+        }
+        IpAddr::V6(ip) => {
+            let first = ip.segments()[0];
+            !(ip.is_loopback() || ip.is_unspecified() || (first & 0xfe00) == 0xfc00 || (first & 0xffc0) == 0xfe80)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::dkim::tests::{sign, TestKey};
+    use crate::auth::dns::StaticResolver;
+    use crate::email::parser::parse_message;
+
+    const MESSAGE: &str = concat!(
+        "Received: from mx.internal (localhost [127.0.0.1]) by imap.example.org; Fri, 11 Jul 2025 11:44:00 +0800\r\n",
+        "Received: from mail.example.com (mail.example.com. [192.0.2.25])\r\n by mx.example.org with ESMTPS; Fri, 11 Jul 2025 11:43:59 +0800\r\n",
+        "Return-Path: <bounces@example.com>\r\n",
+        "From: Alice <alice@example.com>\r\n",
+        "To: sentio@example.org\r\n",
+        "Subject: hello\r\n",
+        "\r\n",
+        "Hi\r\n",
+    );
+
+    fn resolver(key: &TestKey, spf: &str, dmarc: &str) -> StaticResolver {
+        StaticResolver::default()
+            .with_txt("sel._domainkey.example.com", &key.record())
+            .with_txt("example.com", spf)
+            .with_txt("_dmarc.example.com", dmarc)
+    }
+
+    async fn check(raw: &str, resolver: StaticResolver, policy: AuthPolicy) -> (AuthReport, Verdict) {
+        let authenticator = Authenticator::new(policy, Box::new(resolver));
+        let report = authenticator.verify(&parse_message(raw.as_bytes()).unwrap()).await;
+        let verdict = authenticator.verdict(&report);
+        (report, verdict)
+    }
+
+    #[test]
+    fn test_received_chain_skips_internal_hops() {
+        let message = parse_message(MESSAGE.as_bytes()).unwrap();
+        let authenticator = Authenticator::new(AuthPolicy::Dmarc, Box::new(StaticResolver::default()));
+        assert_eq!(
+            authenticator.connecting_client(&message),
+            Some(("192.0.2.25".parse().unwrap(), Some("mail.example.com".to_string())))
+        );
+        assert_eq!(return_path(&message).as_deref(), Some("bounces@example.com"));
+
+        assert_eq!(
+            parse_received("from AM0PR01MB1234.eurprd01.prod.outlook.com (2603:10a6:20b:41e::23) by DB9PR01MB5678 with HTTPS"),
+            Hop::Remote("2603:10a6:20b:41e::23".parse().unwrap(), Some("am0pr01mb1234.eurprd01.prod.outlook.com".to_string()))
+        );
+        assert_eq!(
+            parse_received("from [198.51.100.1] (unknown [203.0.113.9]) by mx.example.org"),
+            Hop::Remote("203.0.113.9".parse().unwrap(), Some("[198.51.100.1]".to_string()))
+        );
+        assert_eq!(parse_received("from relay.example.net (relay.example.net) by mx.example.org"), Hop::Unparsed);
+        assert_eq!(parse_received("by mx.example.org with LMTP id abc"), Hop::Local);
+    }
+
+    #[tokio::test]
+    async fn test_forged_received_below_our_hop_is_ignored() {
+        let key = TestKey::ed25519();
+        // 攻击者从 203.0.113.9 投递，并在自己的头部下面伪造了一跳来自受害域 SPF 地址段的 Received
+        let forged = MESSAGE.replace(
+            "Received: from mail.example.com (mail.example.com. [192.0.2.25])\r\n by mx.example.org",
+            "Received: from relay.example.net (relay.example.net [10.1.2.3]) by mx.example.org; Fri, 11 Jul 2025 11:43:59 +0800\r\n\
+             Received: from attacker.test (attacker.test [203.0.113.9]) by relay.example.net; Fri, 11 Jul 2025 11:43:58 +0800\r\n\
+             Received: from mail.example.com (mail.example.com. [192.0.2.25])\r\n by relay.example.net",
+        );
+        let spf = "v=spf1 ip4:192.0.2.0/24 -all";
+
+        // 没有配置受信中继时只看最上面的外部跳（内网中继），SPF 不会通过
+        let (report, verdict) = check(&forged, resolver(&key, spf, "v=DMARC1; p=none"), AuthPolicy::Strict).await;
+        assert_eq!((report.spf, report.dmarc, verdict), (AuthResult::Fail, AuthResult::Fail, Verdict::Reject));
+
+        // 跳过受信中继后检查的是攻击者的真实地址，而不是更下面伪造的那一跳
+        let authenticator = Authenticator::new(AuthPolicy::Strict, Box::new(resolver(&key, spf, "v=DMARC1; p=none")))
+            .with_trusted_relays(vec![parse_network("10.0.0.0/8").unwrap()]);
+        let message = parse_message(forged.as_bytes()).unwrap();
+        assert_eq!(authenticator.connecting_client(&message).unwrap().0, "203.0.113.9".parse::<IpAddr>().unwrap());
+        let report = authenticator.verify(&message).await;
+        assert_eq!(report.spf, AuthResult::Fail);
+        assert_eq!(authenticator.verdict(&report), Verdict::Reject);
+
+        // 受信中继下面那一跳无法解析时不检查 SPF，也不会跳到更下面的头
+        let unparsed = forged.replace("attacker.test [203.0.113.9]", "attacker.test");
+        let report = authenticator.verify(&parse_message(unparsed.as_bytes()).unwrap()).await;
+        assert_eq!((report.spf, report.spf_domain), (AuthResult::None, None));
+    }
+
+    #[tokio::test]
+    async fn test_dmarc_alignment_and_policies() {
+        let key = TestKey::ed25519();
+
+        // DKIM 与 SPF 都通过并对齐
+        let signed = sign(MESSAGE, "example.com", "sel", &key);
+        let (report, verdict) = check(&signed, resolver(&key, "v=spf1 ip4:192.0.2.0/24 -all", "v=DMARC1; p=reject"), AuthPolicy::Strict).await;
+        assert_eq!((report.dmarc, report.spf, verdict), (AuthResult::Pass, AuthResult::Pass, Verdict::Accept));
+
+        // 伪造的 From：签名域不对齐，SPF 失败，p=reject 时拒收
+        let forged = sign(&MESSAGE.replace("alice@example.com", "ceo@example.com"), "attacker.net", "sel", &key);
+        let forged_resolver = resolver(&key, "v=spf1 ip4:198.51.100.0/24 -all", "v=DMARC1; p=reject")
+            .with_txt("sel._domainkey.attacker.net", &key.record());
+        let (report, verdict) = check(&forged, forged_resolver, AuthPolicy::Dmarc).await;
+        assert_eq!(report.dkim[0].result, AuthResult::Pass);
+        assert_eq!((report.dmarc, verdict), (AuthResult::Fail, Verdict::Reject));
+
+        // p=none 时 dmarc 策略放行，strict 策略仍然拒收
+        let none = || resolver(&key, "v=spf1 -all", "v=DMARC1; p=none");
+        assert_eq!(check(MESSAGE, none(), AuthPolicy::Dmarc).await.1, Verdict::Accept);
+        assert_eq!(check(MESSAGE, none(), AuthPolicy::Strict).await.1, Verdict::Reject);
+    }
+
+    #[tokio::test]
+    async fn test_extra_from_header_is_rejected() {
+        let key = TestKey::ed25519();
+        let spf = "v=spf1 ip4:192.0.2.0/24 -all";
+
+        // 同域的签名覆盖最下面的 From，上面另加一个 From 冒充其他发件人
+        let signed = sign(&MESSAGE.replace("alice@example.com", "attacker@example.com"), "example.com", "sel", &key);
+        let forged = format!("From: owner@example.com\r\n{}", signed);
+        for policy in [AuthPolicy::Dmarc, AuthPolicy::Strict] {
+            let (report, verdict) = check(&forged, resolver(&key, spf, "v=DMARC1; p=none"), policy).await;
+            assert!(report.multiple_from && report.dkim[0].result == AuthResult::Pass);
+            assert_eq!((report.dmarc, verdict), (AuthResult::Fail, Verdict::Reject));
+        }
+    }
+}
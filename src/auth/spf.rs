@@ -0,0 +1,388 @@
+use futures::future::BoxFuture;
+use std::net::IpAddr;
+use tracing::debug;
+
+use super::dns::{DnsError, DnsResolver};
+use super::AuthResult;
+
+/// RFC 7208 4.6.4：会触发 DNS 查询的机制最多 10 个
+const MAX_DNS_MECHANISMS: usize = 10;
+/// 每个 mx / a 机制最多检查的地址记录数
+const MAX_ADDRESS_LOOKUPS: usize = 10;
+
+/// SPF 检查的输入：连接方 IP、HELO 名称和 MAIL FROM 地址
+pub struct SpfRequest<'a> {
+    pub ip: IpAddr,
+    pub helo: Option<&'a str>,
+    pub sender: &'a str,
+}
+
+/// 按 RFC 7208 的 check_host() 评估发件域的 SPF 记录
+pub async fn check_host(request: &SpfRequest<'_>, domain: &str, resolver: &dyn DnsResolver) -> AuthResult {
+    let mut evaluator = Evaluator {
+        request,
+        resolver,
+        dns_mechanisms: 0,
+    };
+    let result = evaluator.check(domain.to_string()).await;
+    debug!(domain, ip = %request.ip, result = %result, "SPF evaluated");
+    result
+}
+
+struct Evaluator<'a> {
+    request: &'a SpfRequest<'a>,
+    resolver: &'a dyn DnsResolver,
+    dns_mechanisms: usize,
+}
+
+/// 机制参数：目标域名与 IPv4 / IPv6 前缀长度
+struct Target {
+    domain: Option<String>,
+    v4: Option<u8>,
+    v6: Option<u8>,
+}
+
+enum Qualifier {
+    Pass,
+    Fail,
+    SoftFail,
+    Neutral,
+}
+
+impl Qualifier {
+    fn result(&self) -> AuthResult {
+        match self {
+            Self::Pass => AuthResult::Pass,
+            Self::Fail => AuthResult::Fail,
+            Self::SoftFail => AuthResult::SoftFail,
+            Self::Neutral => AuthResult::Neutral,
+        }
+    }
+}
+
+impl Evaluator<'_> {
+    fn check(&mut self, domain: String) -> BoxFuture<'_, AuthResult> {
+        Box::pin(async move {
+            let record = match self.fetch_record(&domain).await {
+                Ok(Some(record)) => record,
+                Ok(None) => return AuthResult::None,
+                Err(result) => return result,
+            };
+
+            let mut redirect = None;
+            for term in record.split_whitespace().skip(1) {
+                if let Some((name, value)) = term.split_once('=') {
+                    if name.eq_ignore_ascii_case("redirect") {
+                        redirect = Some(value.to_string());
+                    }
+                    continue;
+                }
+
+                let (qualifier, mechanism) = match term.chars().next() {
+                    Some('+') => (Qualifier::Pass, &term[1..]),
+                    Some('-') => (Qualifier::Fail, &term[1..]),
+                    Some('~') => (Qualifier::SoftFail, &term[1..]),
+                    Some('?') => (Qualifier::Neutral, &term[1..]),
+                    _ => (Qualifier::Pass, term),
+                };
+                match self.matches(mechanism, &domain).await {
+                    Ok(true) => return qualifier.result(),
+                    Ok(false) => {}
+                    Err(result) => return result,
+                }
+            }
+
+            match redirect {
+                Some(target) => {
+                    if let Err(result) = self.count_lookup() {
+                        return result;
+                    }
+                    match self.expand(&target, &domain) {
+                        Some(target) => match self.check(target).await {
+                            AuthResult::None => AuthResult::PermError,
+                            result => result,
+                        },
+                        None => AuthResult::PermError,
+                    }
+                }
+                None => AuthResult::Neutral,
+            }
+        })
+    }
+
+    async fn fetch_record(&self, domain: &str) -> Result<Option<String>, AuthResult> {
+        let records = match self.resolver.txt(domain).await {
+            Ok(records) => records,
+            Err(DnsError::NotFound) => return Ok(None),
+            Err(DnsError::Failed(_)) => return Err(AuthResult::TempError),
+        };
+        let mut spf = records.into_iter().filter(|record| {
+            let lower = record.to_ascii_lowercase();
+            lower == "v=spf1" || lower.starts_with("v=spf1 ")
+        });
+        match (spf.next(), spf.next()) {
+            (Some(record), None) => Ok(Some(record)),
+            (None, _) => Ok(None),
+            (Some(_), Some(_)) => Err(AuthResult::PermError),
+        }
+    }
+
+    fn count_lookup(&mut self) -> Result<(), AuthResult> {
+        self.dns_mechanisms += 1;
+        if self.dns_mechanisms > MAX_DNS_MECHANISMS {
+            return Err(AuthResult::PermError);
+        }
+        Ok(())
+    }
+
+    async fn matches(&mut self, mechanism: &str, domain: &str) -> Result<bool, AuthResult> {
+        let (name, argument) = match mechanism.find([':', '/']) {
+            Some(pos) => (&mechanism[..pos], &mechanism[pos..]),
+            None => (mechanism, ""),
+        };
+        let name = name.to_ascii_lowercase();
+        match name.as_str() {
+            "all" => Ok(true),
+            "ip4" | "ip6" => {
+                let network = argument.strip_prefix(':').ok_or(AuthResult::PermError)?;
+                let (address, prefix) = match network.split_once('/') {
+                    Some((address, prefix)) => (address, Some(prefix.parse::<u8>().map_err(|_| AuthResult::PermError)?)),
+                    None => (network, None),
+                };
+                let address: IpAddr = address.parse().map_err(|_| AuthResult::PermError)?;
+                if (name == "ip4") != address.is_ipv4() {
+                    return Err(AuthResult::PermError);
+                }
+                Ok(in_network(self.request.ip, address, prefix))
+            }
+            "a" => {
+                self.count_lookup()?;
+                let target = self.target(argument, domain)?;
+                let host = target.domain.unwrap_or_else(|| domain.to_string());
+                self.host_matches(&host, target.v4, target.v6).await
+            }
+            "mx" => {
+                self.count_lookup()?;
+                let target = self.target(argument, domain)?;
+                let host = target.domain.unwrap_or_else(|| domain.to_string());
+                let exchanges = match self.resolver.mx(&host).await {
+                    Ok(exchanges) => exchanges,
+                    Err(DnsError::NotFound) => return Ok(false),
+                    Err(DnsError::Failed(_)) => return Err(AuthResult::TempError),
+                };
+                for exchange in exchanges.iter().take(MAX_ADDRESS_LOOKUPS) {
+                    if self.host_matches(exchange, target.v4, target.v6).await? {
+                        return Ok(true);
+                    }
+                }
+                Ok(false)
+            }
+            "include" => {
+                self.count_lookup()?;
+                let spec = self.target(argument, domain)?.domain.ok_or(AuthResult::PermError)?;
+                match self.check(spec).await {
+                    AuthResult::Pass => Ok(true),
+                    AuthResult::Fail | AuthResult::SoftFail | AuthResult::Neutral => Ok(false),
+                    AuthResult::TempError => Err(AuthResult::TempError),
+                    AuthResult::None | AuthResult::PermError => Err(AuthResult::PermError),
+                }
+            }
+            "exists" => {
+                self.count_lookup()?;
+                let spec = self.target(argument, domain)?.domain.ok_or(AuthResult::PermError)?;
+                match self.resolver.ip(&spec).await {
+                    Ok(addresses) => Ok(addresses.iter().any(IpAddr::is_ipv4)),
+                    Err(DnsError::NotFound) => Ok(false),
+                    Err(DnsError::Failed(_)) => Err(AuthResult::TempError),
+                }
+            }
+            // ptr 已不推荐使用，视为不匹配
+            "ptr" => {
+                self.count_lookup()?;
+                Ok(false)
+            }
+            _ => Err(AuthResult::PermError),
+        }
+    }
+
+    /// 解析机制参数中的 `:domain-spec` 和 `/cidr//cidr6`
+    fn target(&self, argument: &str, domain: &str) -> Result<Target, AuthResult> {
+        let (spec, cidr) = match argument.find('/') {
+            Some(pos) => (&argument[..pos], &argument[pos..]),
+            None => (argument, ""),
+        };
+        let spec = match spec.strip_prefix(':') {
+            Some(spec) => Some(self.expand(spec, domain).ok_or(AuthResult::PermError)?),
+            None => None,
+        };
+        let (v4, v6) = parse_dual_cidr(cidr).ok_or(AuthResult::PermError)?;
+        Ok(Target { domain: spec, v4, v6 })
+    }
+
+    async fn host_matches(&self, host: &str, v4: Option<u8>, v6: Option<u8>) -> Result<bool, AuthResult> {
+        let addresses = match self.resolver.ip(host).await {
+            Ok(addresses) => addresses,
+            Err(DnsError::NotFound) => return Ok(false),
+            Err(DnsError::Failed(_)) => return Err(AuthResult::TempError),
+        };
+        Ok(addresses.into_iter().take(MAX_ADDRESS_LOOKUPS).any(|address| {
+            let prefix = if address.is_ipv4() { v4 } else { v6 };
+            in_network(self.request.ip, address, prefix)
+        }))
+    }
+
+    /// 展开宏（RFC 7208 第 7 节）；不支持的宏返回 None
+    fn expand(&self, spec: &str, domain: &str) -> Option<String> {
+        let (local, sender_domain) = self.request.sender.rsplit_once('@').unwrap_or(("postmaster", self.request.sender));
+        let mut output = String::new();
+        let mut chars = spec.chars();
+        while let Some(c) = chars.next() {
+            if c != '%' {
+                output.push(c);
+                continue;
+            }
+            match chars.next()? {
+                '%' => output.push('%'),
+                '_' => output.push(' '),
+                '-' => output.push_str("%20"),
+                '{' => {
+                    let body: String = chars.by_ref().take_while(|&c| c != '}').collect();
+                    let mut body = body.chars();
+                    let value = match body.next()?.to_ascii_lowercase() {
+                        's' => self.request.sender.to_string(),
+                        'l' => local.to_string(),
+                        'o' => sender_domain.to_string(),
+                        'd' => domain.to_string(),
+                        'i' => match self.request.ip {
+                            IpAddr::V4(ip) => ip.to_string(),
+                            IpAddr::V6(ip) => ip
+                                .octets()
+                                .iter()
+                                .flat_map(|byte| [byte >> 4, byte & 0xf])
+                                .map(|nibble| format!("{:x}", nibble))
+                                .collect::<Vec<_>>()
+                                .join("."),
+                        },
+                        'h' => self.request.helo.unwrap_or("unknown").to_string(),
+                        'v' => (if self.request.ip.is_ipv4() { "in-addr" } else { "ip6" }).to_string(),
+                        _ => return None,
+                    };
+                    output.push_str(&transform(&value, body.as_str())?);
+                }
+                _ => return None,
+            }
+        }
+        Some(output)
+    }
+}
+
+/// 宏变换：可选的保留段数、`r` 反转以及自定义分隔符
+fn transform(value: &str, transformers: &str) -> Option<String> {
+    let digits: String = transformers.chars().take_while(char::is_ascii_digit).collect();
+    let rest = &transformers[digits.len()..];
+    let (reverse, delimiters) = match rest.strip_prefix(['r', 'R']) {
+        Some(delimiters) => (true, delimiters),
+        None => (false, rest),
+    };
+    if !delimiters.chars().all(|c| ".-+,/_=".contains(c)) {
+        return None;
+    }
+    let delimiters = if delimiters.is_empty() { "." } else { delimiters };
+
+    let mut parts: Vec<&str> = value.split(|c| delimiters.contains(c)).collect();
+    if reverse {
+        parts.reverse();
+    }
+    if !digits.is_empty() {
+        let keep: usize = digits.parse().ok().filter(|&n| n > 0)?;
+        parts = parts.split_off(parts.len().saturating_sub(keep));
+    }
+    Some(parts.join("."))
+}
+
+/// 解析 `/24//64` 形式的 IPv4 / IPv6 前缀长度
+fn parse_dual_cidr(cidr: &str) -> Option<(Option<u8>, Option<u8>)> {
+    if cidr.is_empty() {
+        return Some((None, None));
+    }
+    let (v4, v6) = match cidr.split_once("//") {
+        Some((v4, v6)) => (v4, Some(v6)),
+        None => (cidr, None),
+    };
+    let v4 = match v4.strip_prefix('/') {
+        Some(prefix) => Some(prefix.parse::<u8>().ok().filter(|&p| p <= 32)?),
+        None if v4.is_empty() => None,
+        None => return None,
+    };
+    let v6 = match v6 {
+        Some(prefix) => Some(prefix.parse::<u8>().ok().filter(|&p| p <= 128)?),
+        None => None,
+    };
+    Some((v4, v6))
+}
+
+pub(super) fn in_network(ip: IpAddr, network: IpAddr, prefix: Option<u8>) -> bool {
+    match (ip, network) {
+        (IpAddr::V4(ip), IpAddr::V4(network)) => {
+            let prefix = u32::from(prefix.unwrap_or(32).min(32));
+            let mask = u32::MAX.checked_shl(32 - prefix).unwrap_or(0);
+            u32::from(ip) & mask == u32::from(network) & mask
+        }
+        (IpAddr::V6(ip), IpAddr::V6(network)) => {
+            let prefix = u32::from(prefix.unwrap_or(128).min(128));
+            let mask = u128::MAX.checked_shl(128 - prefix).unwrap_or(0);
+            u128::from(ip) & mask == u128::from(network) & mask
+        }
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::dns::StaticResolver;
+
+    fn request(ip: &str) -> SpfRequest<'static> {
+        SpfRequest {
+            ip: ip.parse().unwrap(),
+            helo: Some("mail.example.com"),
+            sender: "alice@example.com",
+        }
+    }
+
+    #[tokio::test]
+    async fn test_mechanisms_and_include() {
+        let resolver = StaticResolver::default()
+            .with_txt("example.com", "v=spf1 ip4:192.0.2.0/24 mx include:_spf.provider.net ~all")
+            .with_txt("example.com", "google-site-verification=abc")
+            .with_mx("example.com", "mx.example.com")
+            .with_ip("mx.example.com", "198.51.100.7")
+            .with_txt("_spf.provider.net", "v=spf1 ip6:2001:db8::/32 exists:%{ir}.allow.provider.net -all")
+            .with_ip("9.113.0.203.allow.provider.net", "127.0.0.2")
+            .with_txt("other.org", "v=spf1 redirect=example.com");
+
+        let cases = [
+            ("192.0.2.10", AuthResult::Pass),
+            ("198.51.100.7", AuthResult::Pass),
+            ("2001:db8::1", AuthResult::Pass),
+            ("203.0.113.9", AuthResult::Pass),
+            ("203.0.113.10", AuthResult::SoftFail),
+        ];
+        for (ip, expected) in cases {
+            assert_eq!(check_host(&request(ip), "example.com", &resolver).await, expected, "{}", ip);
+        }
+        assert_eq!(check_host(&request("192.0.2.1"), "other.org", &resolver).await, AuthResult::Pass);
+        assert_eq!(check_host(&request("192.0.2.1"), "nospf.org", &resolver).await, AuthResult::None);
+    }
+
+    #[tokio::test]
+    async fn test_lookup_limit_and_duplicate_records() {
+        let resolver = StaticResolver::default()
+            .with_txt("loop.example", "v=spf1 include:loop.example -all")
+            .with_txt("dup.example", "v=spf1 -all")
+            .with_txt("dup.example", "v=spf1 +all");
+
+        assert_eq!(check_host(&request("192.0.2.1"), "loop.example", &resolver).await, AuthResult::PermError);
+        assert_eq!(check_host(&request("192.0.2.1"), "dup.example", &resolver).await, AuthResult::PermError);
+    }
+}
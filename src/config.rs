@@ -1,7 +1,11 @@
 use anyhow::Result;
 use serde::{Deserialize, Serialize};
+
+use crate::analysis::EmailCategory;
+use crate::llm::client::Sampling;
+use std::collections::HashMap;
 use std::sync::OnceLock;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
 static GLOBAL_CONFIG: OnceLock<Config> = OnceLock::new();
 
@@ -9,6 +13,16 @@ static GLOBAL_CONFIG: OnceLock<Config> = OnceLock::new();
 pub struct Config {
     pub email: EmailConfig,
     pub llm: LlmConfig,
+    #[serde(default)]
+    pub prompts: PromptConfig,
+    #[serde(default)]
+    pub routing: RoutingConfig,
+    #[serde(default)]
+    pub approval: ApprovalConfig,
+    #[serde(default)]
+    pub tools: ToolsConfig,
+    #[serde(default)]
+    pub usage: UsageConfig,
     pub telemetry: TelemetryConfig,
     pub server: ServerConfig,
 }
@@ -16,26 +30,797 @@ pub struct Config {
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct EmailConfig {
     pub smtp: SmtpConfig,
+    #[serde(default)]
+    pub inbound: InboundSource,
+    #[serde(default)]
+    pub imap: Option<ImapConfig>,
+    #[serde(default)]
+    pub maildir: Option<MaildirConfig>,
+    /// 单个允许的发件人，等价于一条只写了 `address` 的 `senders` 条目
+    #[serde(default)]
     pub allowed_sender: String,
+    /// 允许的发件人列表及各自的回复策略
+    #[serde(default)]
+    pub senders: Vec<SenderConfig>,
+    /// 接收摘要、通知等邮件的地址，默认为 `allowed_sender`
+    #[serde(default)]
+    pub owner: Option<String>,
+    /// 回复时在生成内容下方附上带署名行的原文引用
+    #[serde(default)]
+    pub quote_original: bool,
+    #[serde(default)]
+    pub auth: AuthConfig,
+    #[serde(default)]
+    pub queue: QueueConfig,
+    #[serde(default)]
+    pub loop_protection: LoopProtectionConfig,
+}
+
+/// 防止与其他自动回复程序互相回复的兜底限制
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LoopProtectionConfig {
+    /// 时间窗口内同一线程最多回复的次数，0 表示不限制
+    #[serde(default = "default_max_replies_per_thread")]
+    pub max_replies_per_thread: usize,
+    /// 时间窗口（秒）
+    #[serde(default = "default_loop_window")]
+    pub window: u64,
+}
+
+impl Default for LoopProtectionConfig {
+    fn default() -> Self {
+        Self {
+            max_replies_per_thread: default_max_replies_per_thread(),
+            window: default_loop_window(),
+        }
+    }
+}
+
+fn default_max_replies_per_thread() -> usize {
+    5
+}
+
+fn default_loop_window() -> u64 {
+    60 * 60
+}
+
+/// 持久化发件队列
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QueueConfig {
+    /// 队列目录，待发送邮件在 `pending/`，放弃发送的在 `dead/`
+    #[serde(default = "default_queue_dir")]
+    pub dir: PathBuf,
+    /// 临时失败时的最大尝试次数，超过后移入 `dead/`
+    #[serde(default = "default_queue_max_attempts")]
+    pub max_attempts: u32,
+    /// 首次重试的等待时间（秒），之后每次翻倍
+    #[serde(default = "default_queue_initial_backoff")]
+    pub initial_backoff: u64,
+    /// 重试等待时间上限（秒）
+    #[serde(default = "default_queue_max_backoff")]
+    pub max_backoff: u64,
+    /// 检查待发送邮件的间隔（秒）
+    #[serde(default = "default_queue_poll_interval")]
+    pub poll_interval: u64,
+}
+
+impl Default for QueueConfig {
+    fn default() -> Self {
+        Self {
+            dir: default_queue_dir(),
+            max_attempts: default_queue_max_attempts(),
+            initial_backoff: default_queue_initial_backoff(),
+            max_backoff: default_queue_max_backoff(),
+            poll_interval: default_queue_poll_interval(),
+        }
+    }
+}
+
+fn default_queue_dir() -> PathBuf {
+    PathBuf::from("outbox")
+}
+
+fn default_queue_max_attempts() -> u32 {
+    8
+}
+
+fn default_queue_initial_backoff() -> u64 {
+    30
+}
+
+fn default_queue_max_backoff() -> u64 {
+    60 * 60
+}
+
+fn default_queue_poll_interval() -> u64 {
+    10
+}
+
+/// 入站邮件的发件人验证（DKIM / SPF / DMARC）
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuthConfig {
+    #[serde(default)]
+    pub policy: AuthPolicy,
+    /// DNS 查询超时（秒）
+    #[serde(default = "default_dns_timeout")]
+    pub dns_timeout: u64,
+    /// 自己的 MX / 中继（主机名或 IP）；SPF 从最上面的 Received 头开始，跳过这些主机转交的跳，
+    /// 检查紧接其下的那一跳，更下面的 Received 头可能由发件人伪造
+    #[serde(default)]
+    pub trusted_relays: Vec<String>,
+}
+
+impl Default for AuthConfig {
+    fn default() -> Self {
+        Self {
+            policy: AuthPolicy::default(),
+            dns_timeout: default_dns_timeout(),
+            trusted_relays: Vec::new(),
+        }
+    }
+}
+
+fn default_dns_timeout() -> u64 {
+    5
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AuthPolicy {
+    /// 不验证，From 头可以被任意伪造
+    Off,
+    /// 按发件域公布的 DMARC 策略处理：p=quarantine / reject 且未通过时拒收
+    #[default]
+    Dmarc,
+    /// 必须有与 From 域名对齐的 DKIM 或 SPF 通过，不论发件域是否公布 DMARC 记录
+    Strict,
+}
+
+/// 允许的发件人条目
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SenderConfig {
+    /// 精确地址（`a@example.com`）、通配符（`*@example.com`、`@example.com`）
+    /// 或用斜杠包围的正则表达式（`/^ops-.*@example\.com$/`），均不区分大小写
+    pub address: String,
+    /// 回复使用的提示词名称，默认为 `email_reply`
+    #[serde(default)]
+    pub reply_prompt: Option<String>,
+    /// 回复时采用的身份与语气
+    #[serde(default)]
+    pub persona: Option<String>,
+    /// 回复使用的语言
+    #[serde(default)]
+    pub language: Option<String>,
+    /// 允许对该发件人执行的动作，未设置时不限制
+    #[serde(default)]
+    pub allowed_actions: Option<Vec<ActionKind>>,
+}
+
+/// 收件来源
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum InboundSource {
+    #[default]
+    Imap,
+    Maildir,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SmtpConfig {
+    pub host: String,
+    pub port: u16,
+    pub username: String,
+    pub password: String,
+    /// 连接加密方式；未设置时按 `use_tls` 和端口推断
+    #[serde(default)]
+    pub security: Option<SmtpSecurity>,
+    /// 已废弃，请改用 `security`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub use_tls: Option<bool>,
+    /// 建立 TCP 连接的超时（秒）
+    #[serde(default = "default_smtp_connect_timeout")]
+    pub connect_timeout: u64,
+    /// 发送一封邮件的整个 SMTP 会话（取连接、握手、认证到 DATA 结束）的超时（秒）
+    #[serde(default = "default_smtp_send_timeout")]
+    pub send_timeout: u64,
+    /// 已废弃且不再生效：lettre 的异步传输只对建立连接设置超时，连接池也不允许替换底层连接，
+    /// 无法为单条命令计时，命令无响应由 `send_timeout` 兜底
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub command_timeout: Option<u64>,
+    /// 连接池中保留的最大连接数
+    #[serde(default = "default_smtp_pool_size")]
+    pub pool_size: u32,
+    /// 空闲连接保留时间（秒）
+    #[serde(default = "default_smtp_pool_idle_timeout")]
+    pub pool_idle_timeout: u64,
+}
+
+/// SMTP 连接加密方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SmtpSecurity {
+    /// 连接建立后立即 TLS 握手（通常为 465 端口）
+    #[serde(rename = "tls", alias = "implicit_tls")]
+    ImplicitTls,
+    /// 明文连接后通过 STARTTLS 升级，服务器不支持时发送失败（通常为 587 端口）
+    #[serde(rename = "starttls")]
+    StartTls,
+    /// 不加密，仅用于本机或可信网络中的中继
+    #[serde(rename = "none")]
+    None,
+}
+
+impl SmtpConfig {
+    pub fn security(&self) -> SmtpSecurity {
+        match (self.security, self.use_tls) {
+            (Some(security), _) => security,
+            (None, Some(false)) => SmtpSecurity::None,
+            (None, _) if self.port == 465 => SmtpSecurity::ImplicitTls,
+            (None, _) => SmtpSecurity::StartTls,
+        }
+    }
+}
+
+fn default_smtp_connect_timeout() -> u64 {
+    10
+}
+
+fn default_smtp_send_timeout() -> u64 {
+    60
+}
+
+fn default_smtp_pool_size() -> u32 {
+    4
+}
+
+fn default_smtp_pool_idle_timeout() -> u64 {
+    60
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ImapConfig {
     pub host: String,
     pub port: u16,
     pub username: String,
     pub password: String,
     pub use_tls: bool,
+    #[serde(default = "default_imap_mailbox")]
+    pub mailbox: String,
+    /// 处理成功后移动到的文件夹；未设置时仅标记为已读
+    #[serde(default)]
+    pub move_to: Option<String>,
+    /// IDLE 等待超时（秒），到期后重新发起 IDLE 以保持连接
+    #[serde(default = "default_imap_idle_timeout")]
+    pub idle_timeout: u64,
+    /// 服务器不支持 IDLE 时的轮询间隔（秒）
+    #[serde(default = "default_imap_poll_interval")]
+    pub poll_interval: u64,
+}
+
+fn default_imap_mailbox() -> String {
+    "INBOX".to_string()
+}
+
+fn default_imap_idle_timeout() -> u64 {
+    25 * 60
+}
+
+fn default_imap_poll_interval() -> u64 {
+    60
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MaildirConfig {
+    /// Maildir 根目录（包含 new/、cur/、tmp/）
+    pub path: PathBuf,
+    /// 处理失败的邮件存放目录，默认为 `<path>/.Quarantine`
+    #[serde(default)]
+    pub quarantine: Option<PathBuf>,
+    /// 扫描 new/ 的间隔（秒）
+    #[serde(default = "default_maildir_poll_interval")]
+    pub poll_interval: u64,
+}
+
+fn default_maildir_poll_interval() -> u64 {
+    5
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct LlmConfig {
+    /// deepseek、openai、vllm、llamacpp（OpenAI 兼容接口）、ollama 或 anthropic
     pub provider: String,
+    /// 本地的 Ollama / vLLM / llama.cpp 服务可以留空
+    #[serde(default)]
     pub api_key: String,
+    /// 留空时使用提供方的默认地址
+    #[serde(default)]
     pub base_url: String,
     pub model: String,
     pub timeout: u64,
     pub max_retries: u32,
+    /// 一次生成（含所有重试）允许花费的总时间（秒）
+    #[serde(default = "default_retry_deadline")]
+    pub retry_deadline: u64,
+    /// 生成回复时带上同一线程中最近的消息条数
+    #[serde(default = "default_history_turns")]
+    pub history_turns: usize,
+    /// 主后端不可用时按顺序尝试的备用后端
+    #[serde(default)]
+    pub fallbacks: Vec<LlmBackendConfig>,
+    #[serde(default)]
+    pub circuit_breaker: CircuitBreakerConfig,
+    #[serde(default)]
+    pub cache: LlmCacheConfig,
+    #[serde(default)]
+    pub replay: LlmReplayConfig,
+    #[serde(default)]
+    pub context: ContextConfig,
+}
+
+fn default_history_turns() -> usize {
+    10
+}
+
+fn default_retry_deadline() -> u64 {
+    5 * 60
+}
+
+/// 备用模型后端；未设置的超时和重试次数沿用 `[llm]` 中的值
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LlmBackendConfig {
+    /// 日志和回复记录中使用的名称，默认为 `provider`
+    #[serde(default)]
+    pub name: Option<String>,
+    pub provider: String,
+    #[serde(default)]
+    pub api_key: String,
+    #[serde(default)]
+    pub base_url: String,
+    pub model: String,
+    /// 这个后端还提供的模型；提示词设置或预算指定的模型不在其中时，改用 `model` 回答
+    #[serde(default)]
+    pub models: Vec<String>,
+    #[serde(default)]
+    pub timeout: Option<u64>,
+    #[serde(default)]
+    pub max_retries: Option<u32>,
+}
+
+impl LlmConfig {
+    /// 主后端的名称
+    pub fn backend_name(&self) -> String {
+        self.provider.to_lowercase()
+    }
+
+    /// 备用后端的完整配置
+    pub fn fallback_config(&self, fallback: &LlmBackendConfig) -> LlmConfig {
+        LlmConfig {
+            provider: fallback.provider.clone(),
+            api_key: fallback.api_key.clone(),
+            base_url: fallback.base_url.clone(),
+            model: fallback.model.clone(),
+            timeout: fallback.timeout.unwrap_or(self.timeout),
+            max_retries: fallback.max_retries.unwrap_or(self.max_retries),
+            retry_deadline: self.retry_deadline,
+            history_turns: self.history_turns,
+            fallbacks: Vec::new(),
+            circuit_breaker: self.circuit_breaker.clone(),
+            // 缓存包在整个回退链外面
+            cache: LlmCacheConfig::default(),
+            replay: LlmReplayConfig::default(),
+            context: self.context.clone(),
+        }
+    }
+}
+
+/// 模型响应的磁盘缓存，键为模型、渲染后的消息和采样参数的哈希
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LlmCacheConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    #[serde(default = "default_cache_dir")]
+    pub dir: PathBuf,
+    /// 缓存有效期（秒）
+    #[serde(default = "default_cache_ttl")]
+    pub ttl: u64,
+    /// 缓存目录的大小上限（MB），超出时淘汰最久未使用的条目
+    #[serde(default = "default_cache_max_size_mb")]
+    pub max_size_mb: u64,
+    /// 不读取缓存，总是调用模型并用新结果覆盖缓存
+    #[serde(default)]
+    pub bypass: bool,
+}
+
+impl Default for LlmCacheConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            dir: default_cache_dir(),
+            ttl: default_cache_ttl(),
+            max_size_mb: default_cache_max_size_mb(),
+            bypass: false,
+        }
+    }
+}
+
+fn default_cache_dir() -> PathBuf {
+    PathBuf::from("llm_cache")
+}
+
+fn default_cache_ttl() -> u64 {
+    7 * 24 * 60 * 60
+}
+
+fn default_cache_max_size_mb() -> u64 {
+    100
+}
+
+/// 提示词的上下文预算
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ContextConfig {
+    /// 模型的上下文窗口（token），不设置时按模型名称推断
+    #[serde(default)]
+    pub window: Option<u32>,
+    /// 留给系统提示、记忆和模型输出的 token，其余一半给邮件内容、一半给线程历史
+    #[serde(default = "default_reserved_tokens")]
+    pub reserved: u32,
+    /// 分段摘要时每段的 token 数
+    #[serde(default = "default_chunk_tokens")]
+    pub chunk_tokens: u32,
+    /// 最多摘要的段数，更长的内容截断
+    #[serde(default = "default_max_chunks")]
+    pub max_chunks: usize,
+}
+
+impl Default for ContextConfig {
+    fn default() -> Self {
+        Self {
+            window: None,
+            reserved: default_reserved_tokens(),
+            chunk_tokens: default_chunk_tokens(),
+            max_chunks: default_max_chunks(),
+        }
+    }
+}
+
+fn default_reserved_tokens() -> u32 {
+    4000
+}
+
+fn default_chunk_tokens() -> u32 {
+    3000
+}
+
+fn default_max_chunks() -> usize {
+    16
+}
+
+/// 录制或回放模型调用，用于离线测试和评估
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LlmReplayConfig {
+    #[serde(default)]
+    pub mode: ReplayMode,
+    /// 录制文件目录，每次调用一个 JSON 文件
+    #[serde(default = "default_replay_dir")]
+    pub dir: PathBuf,
+}
+
+impl Default for LlmReplayConfig {
+    fn default() -> Self {
+        Self {
+            mode: ReplayMode::default(),
+            dir: default_replay_dir(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ReplayMode {
+    #[default]
+    Off,
+    /// 正常调用模型，并把请求和响应写入录制目录
+    Record,
+    /// 不调用模型，只返回录制的响应；没有录制的请求直接报错
+    Replay,
+}
+
+fn default_replay_dir() -> PathBuf {
+    PathBuf::from("fixtures/llm")
+}
+
+/// 连续失败达到阈值后暂停使用该后端，冷却结束后放行一次试探请求
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CircuitBreakerConfig {
+    #[serde(default = "default_failure_threshold")]
+    pub failure_threshold: u32,
+    /// 熔断持续时间（秒）
+    #[serde(default = "default_open_duration")]
+    pub open_duration: u64,
+}
+
+impl Default for CircuitBreakerConfig {
+    fn default() -> Self {
+        Self {
+            failure_threshold: default_failure_threshold(),
+            open_duration: default_open_duration(),
+        }
+    }
+}
+
+fn default_failure_threshold() -> u32 {
+    3
+}
+
+fn default_open_duration() -> u64 {
+    60
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PromptConfig {
+    /// 存放 `<name>.toml` 提示词文件的目录
+    #[serde(default = "default_prompt_dir")]
+    pub dir: PathBuf,
+    /// 直接在配置中定义的提示词，优先于目录中的同名文件
+    #[serde(default)]
+    pub templates: HashMap<String, PromptTemplate>,
+    /// 按提示词名称设置的模型和采样参数
+    #[serde(default)]
+    pub settings: HashMap<String, PromptSettings>,
+}
+
+impl Default for PromptConfig {
+    fn default() -> Self {
+        Self {
+            dir: default_prompt_dir(),
+            templates: HashMap::new(),
+            settings: HashMap::new(),
+        }
+    }
+}
+
+fn default_prompt_dir() -> PathBuf {
+    PathBuf::from("prompts")
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PromptTemplate {
+    pub system: String,
+    pub user: String,
+}
+
+/// 某个提示词使用的模型和采样参数，未设置的项使用后端默认值
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PromptSettings {
+    /// 替换主后端的 `[llm] model`，备用后端只在其 `models` 中列出时使用；超出预算改用便宜模型时以预算设置为准
+    #[serde(default)]
+    pub model: Option<String>,
+    #[serde(flatten)]
+    pub sampling: Sampling,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RoutingConfig {
+    /// 按顺序匹配，命中第一条规则后执行它的动作
+    #[serde(default)]
+    pub rules: Vec<RoutingRule>,
+    /// 没有规则命中时执行的动作
+    #[serde(default = "default_routing_actions")]
+    pub default_actions: Vec<RuleAction>,
+    #[serde(default)]
+    pub digest: DigestConfig,
+}
+
+impl Default for RoutingConfig {
+    fn default() -> Self {
+        Self {
+            rules: Vec::new(),
+            default_actions: default_routing_actions(),
+            digest: DigestConfig::default(),
+        }
+    }
+}
+
+fn default_routing_actions() -> Vec<RuleAction> {
+    vec![RuleAction::Reply]
+}
+
+/// 路由规则；未设置的条件视为匹配任意值
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RoutingRule {
+    #[serde(default)]
+    pub name: Option<String>,
+    #[serde(default)]
+    pub categories: Vec<EmailCategory>,
+    /// 发件人地址的正则表达式（不区分大小写）
+    #[serde(default)]
+    pub sender: Option<String>,
+    /// 主题的正则表达式（不区分大小写）
+    #[serde(default)]
+    pub subject: Option<String>,
+    #[serde(default)]
+    pub needs_reply: Option<bool>,
+    pub actions: Vec<RuleAction>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum RuleAction {
+    /// 生成并发送自动回复
+    Reply,
+    /// 转发原邮件
+    Forward { to: String },
+    /// 保存为任务记忆
+    Task,
+    /// 加入发给 owner 的定期摘要
+    Digest,
+    /// 不做任何处理
+    Ignore,
+}
+
+impl RuleAction {
+    pub fn kind(&self) -> ActionKind {
+        match self {
+            Self::Reply => ActionKind::Reply,
+            Self::Forward { .. } => ActionKind::Forward,
+            Self::Task => ActionKind::Task,
+            Self::Digest => ActionKind::Digest,
+            Self::Ignore => ActionKind::Ignore,
+        }
+    }
+}
+
+/// 不带参数的动作类型，用于按发件人限制可执行的动作
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ActionKind {
+    Reply,
+    Forward,
+    Task,
+    Digest,
+    Ignore,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DigestConfig {
+    /// 待发送摘要条目的存放文件
+    #[serde(default = "default_digest_path")]
+    pub path: PathBuf,
+    /// 摘要发送间隔（秒）
+    #[serde(default = "default_digest_interval")]
+    pub interval: u64,
+}
+
+impl Default for DigestConfig {
+    fn default() -> Self {
+        Self {
+            path: default_digest_path(),
+            interval: default_digest_interval(),
+        }
+    }
+}
+
+fn default_digest_path() -> PathBuf {
+    PathBuf::from("digest.json")
+}
+
+fn default_digest_interval() -> u64 {
+    24 * 60 * 60
+}
+
+/// 人工确认模式：回复先保存为草稿，owner 确认后才发送
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ApprovalConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    /// 待确认草稿的存放文件
+    #[serde(default = "default_drafts_path")]
+    pub path: PathBuf,
+    /// 草稿有效期（秒），过期未确认的草稿会被丢弃
+    #[serde(default = "default_approval_timeout")]
+    pub timeout: u64,
+}
+
+impl Default for ApprovalConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            path: default_drafts_path(),
+            timeout: default_approval_timeout(),
+        }
+    }
+}
+
+fn default_drafts_path() -> PathBuf {
+    PathBuf::from("drafts.json")
+}
+
+fn default_approval_timeout() -> u64 {
+    24 * 60 * 60
+}
+
+/// 回复前允许模型调用工具：查询和添加记忆、查看发件队列、读取线程历史
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ToolsConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    /// 最多执行几轮工具调用，之后要求模型直接写出回复
+    #[serde(default = "default_tool_rounds")]
+    pub max_rounds: usize,
+}
+
+impl Default for ToolsConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            max_rounds: default_tool_rounds(),
+        }
+    }
+}
+
+fn default_tool_rounds() -> usize {
+    4
+}
+
+/// 模型调用的用量记录、价格和预算
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UsageConfig {
+    /// 每次调用追加一行 JSON 的用量记录文件
+    #[serde(default = "default_usage_path")]
+    pub path: PathBuf,
+    /// 每百万 token 的价格，键为模型名；没有完全相同的键时使用最长的前缀匹配
+    #[serde(default)]
+    pub prices: HashMap<String, ModelPrice>,
+    #[serde(default)]
+    pub budget: BudgetConfig,
+}
+
+impl Default for UsageConfig {
+    fn default() -> Self {
+        Self {
+            path: default_usage_path(),
+            prices: HashMap::new(),
+            budget: BudgetConfig::default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct ModelPrice {
+    pub input: f64,
+    pub output: f64,
+}
+
+/// 费用预算，0 表示不限制；日、月均按 UTC 计算
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct BudgetConfig {
+    /// 所有用户合计
+    #[serde(default)]
+    pub daily: f64,
+    #[serde(default)]
+    pub monthly: f64,
+    /// 单个发件人
+    #[serde(default)]
+    pub user_daily: f64,
+    #[serde(default)]
+    pub user_monthly: f64,
+    #[serde(default)]
+    pub action: BudgetAction,
+    /// `action = "cheaper_model"` 时改用的模型，需要主后端支持
+    #[serde(default)]
+    pub cheaper_model: Option<String>,
+}
+
+/// 超出预算后的处理方式
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BudgetAction {
+    #[default]
+    StopReplying,
+    CheaperModel,
+}
+
+fn default_usage_path() -> PathBuf {
+    PathBuf::from("usage.jsonl")
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -50,6 +835,18 @@ pub struct ServerConfig {
     pub host: String,
     pub port: u16,
     pub workers: usize,
+    /// HTTP 接口的访问令牌，设置后请求需带 `Authorization: Bearer <token>`
+    #[serde(default)]
+    pub api_token: Option<String>,
+}
+
+impl EmailConfig {
+    /// 未设置 `owner` 时使用 `allowed_sender`；只配置了 `senders` 时返回 None
+    pub fn owner_address(&self) -> Option<&str> {
+        self.owner
+            .as_deref()
+            .or(Some(self.allowed_sender.as_str()).filter(|sender| !sender.is_empty()))
+    }
 }
 
 impl Config {
@@ -69,20 +866,6 @@ impl Config {
         
         Self::from_file(config_path)
     }
-
-    pub fn get_email_analysis_prompt(&self) -> (&str, &str) {
-        (
-            "你是一个邮件分类专家。请分析邮件内容并进行分类。\n\n分类包括：\n- 工作相关\n- 个人事务\n- 营销推广\n- 系统通知\n- 垃圾邮件\n- 其他",
-            "请分析以下邮件并进行分类：\n\n{email_content}"
-        )
-    }
-
-    pub fn get_email_reply_prompt(&self) -> (&str, &str) {
-        (
-            "你是一位专业的邮件回复助手。请根据邮件内容生成合适的回复。",
-            "请为以下邮件生成合适的回复：\n\n原始邮件：\n{original_email}\n\n分析结果：\n{analysis_result}"
-        )
-    }
 }
 
 pub async fn initialize() -> Result<()> {
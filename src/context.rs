@@ -0,0 +1,274 @@
+use anyhow::Result;
+use futures::future::try_join_all;
+use std::collections::HashMap;
+use tracing::{info, warn};
+
+use crate::config::ContextConfig;
+use crate::email::quote::BodyParts;
+use crate::llm::{ChatMessage, LlmClient, LlmRequest};
+
+/// 按模型名称前缀（最长匹配）确定上下文窗口和估算系数：
+/// (前缀, 上下文窗口, 每个 ASCII 字符的 token 数, 每个其他字符的 token 数)
+const MODEL_PROFILES: &[(&str, u32, f32, f32)] = &[
+    ("deepseek", 65_536, 0.3, 0.6),
+    ("gpt-4o", 128_000, 0.25, 0.7),
+    ("gpt-4-turbo", 128_000, 0.25, 1.0),
+    ("gpt-4", 8_192, 0.25, 1.0),
+    ("gpt-3.5", 16_385, 0.25, 1.0),
+    ("o1", 128_000, 0.25, 0.7),
+    ("o3", 200_000, 0.25, 0.7),
+    ("claude", 200_000, 0.3, 1.1),
+    ("qwen", 32_768, 0.3, 0.7),
+    ("llama3", 8_192, 0.3, 1.0),
+    ("mistral", 32_768, 0.3, 1.0),
+];
+
+/// 未知模型按较小的窗口和偏保守的系数估算
+const DEFAULT_PROFILE: (u32, f32, f32) = (8_192, 0.3, 1.0);
+
+/// 摘要之后仍然过长时最多再合并摘要的轮数
+const MAX_REDUCE_ROUNDS: usize = 3;
+
+/// 不依赖分词器的 token 估算：英文约 3-4 个字符一个 token，中文约每字 0.6-1 个 token
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TokenEstimator {
+    window: u32,
+    ascii: f32,
+    other: f32,
+}
+
+impl TokenEstimator {
+    pub fn for_model(model: &str) -> Self {
+        let model = model.to_lowercase();
+        let (window, ascii, other) = MODEL_PROFILES
+            .iter()
+            .filter(|(prefix, ..)| model.starts_with(prefix))
+            .max_by_key(|(prefix, ..)| prefix.len())
+            .map(|&(_, window, ascii, other)| (window, ascii, other))
+            .unwrap_or(DEFAULT_PROFILE);
+        Self { window, ascii, other }
+    }
+
+    /// 同时适用于两个模型的估算：较小的窗口、较大的系数
+    fn merge(self, other: Self) -> Self {
+        Self {
+            window: self.window.min(other.window),
+            ascii: self.ascii.max(other.ascii),
+            other: self.other.max(other.other),
+        }
+    }
+
+    pub fn estimate(&self, text: &str) -> u32 {
+        let tokens: f32 = text.chars().map(|c| if c.is_ascii() { self.ascii } else { self.other }).sum();
+        tokens.ceil() as u32
+    }
+
+    /// 不超过 `tokens` 的最长前缀
+    fn truncate<'a>(&self, text: &'a str, tokens: u32) -> &'a str {
+        let mut used = 0.0;
+        for (index, c) in text.char_indices() {
+            used += if c.is_ascii() { self.ascii } else { self.other };
+            if used > tokens as f32 {
+                return &text[..index];
+            }
+        }
+        text
+    }
+}
+
+/// 提示词中邮件内容和线程历史可以使用的 token 数
+///
+/// 同一封邮件可能由 `models` 中任何一个模型处理（备用后端、按提示词指定的模型、超出预算时
+/// 改用的模型），按其中最小的上下文窗口计算。上下文窗口减去 `reserved` 后，一半给本封邮件，一半给线程历史。邮件过长时先截短
+/// 引用的历史邮件，新写的内容仍然过长则分段摘要再合并（map-reduce）。
+pub struct ContextBudget {
+    estimator: TokenEstimator,
+    content_tokens: u32,
+    history_tokens: u32,
+    chunk_tokens: u32,
+    max_chunks: usize,
+}
+
+impl ContextBudget {
+    pub fn new(config: &ContextConfig, models: &[&str]) -> Self {
+        let estimator = models
+            .iter()
+            .map(|model| TokenEstimator::for_model(model))
+            .reduce(TokenEstimator::merge)
+            .unwrap_or_else(|| TokenEstimator::for_model(""));
+        let available = config.window.unwrap_or(estimator.window).saturating_sub(config.reserved);
+        let content_tokens = available / 2;
+        Self {
+            estimator,
+            content_tokens,
+            history_tokens: available - content_tokens,
+            chunk_tokens: config.chunk_tokens.clamp(1, available.max(1)),
+            max_chunks: config.max_chunks.max(1),
+        }
+    }
+
+    fn fits(&self, text: &str) -> bool {
+        self.estimator.estimate(text) <= self.content_tokens
+    }
+
+    /// 把拆分后的邮件压缩到预算以内，没有超出时原样返回
+    pub async fn fit_parts(&self, llm: &dyn LlmClient, parts: BodyParts) -> Result<BodyParts> {
+        let text_tokens = self.estimator.estimate(&parts.text);
+        let quoted_tokens = self.estimator.estimate(&parts.quoted);
+        if text_tokens + quoted_tokens <= self.content_tokens {
+            return Ok(parts);
+        }
+
+        // 新写的内容放得下时，引用在剩余空间内保留最近的部分
+        let remaining = self.content_tokens.saturating_sub(text_tokens);
+        if remaining > 0 {
+            let kept = self.estimator.truncate(&parts.quoted, remaining.saturating_sub(20));
+            let quoted = format!("{}\n[……更早的引用内容已省略]", kept.trim_end());
+            info!(
+                original = quoted_tokens,
+                trimmed = self.estimator.estimate(&quoted),
+                "Trimmed quoted history to fit the context budget"
+            );
+            return Ok(BodyParts { quoted, ..parts });
+        }
+
+        Ok(BodyParts {
+            text: self.summarise(llm, &parts.text).await?,
+            quoted: String::new(),
+        })
+    }
+
+    /// 分段摘要，合并后仍然过长时对摘要再做一轮
+    async fn summarise(&self, llm: &dyn LlmClient, text: &str) -> Result<String> {
+        let mut text = text.to_string();
+        for round in 1..=MAX_REDUCE_ROUNDS {
+            let mut chunks = self.chunks(&text);
+            if chunks.len() > self.max_chunks {
+                warn!(chunks = chunks.len(), max_chunks = self.max_chunks, "Email too long to summarise in full, dropping the tail");
+                chunks.truncate(self.max_chunks);
+            }
+            info!(round, chunks = chunks.len(), "Summarising long email chunk by chunk");
+
+            let total = chunks.len();
+            let summaries = try_join_all(chunks.into_iter().enumerate().map(|(index, chunk)| async move {
+                let context = HashMap::from([
+                    ("chunk".to_string(), serde_json::json!(chunk)),
+                    ("part".to_string(), serde_json::json!(index + 1)),
+                    ("total".to_string(), serde_json::json!(total)),
+                ]);
+                let request = LlmRequest::new("email_chunk_summary".to_string(), context);
+                llm.generate_response(&request).await.map(|response| response.content.trim().to_string())
+            }))
+            .await?;
+
+            text = format!("[原邮件过长，以下为分段摘要]\n{}", summaries.join("\n\n"));
+            if self.fits(&text) {
+                return Ok(text);
+            }
+        }
+        warn!("Summary still exceeds the context budget, truncating");
+        Ok(self.estimator.truncate(&text, self.content_tokens).to_string())
+    }
+
+    /// 按行切分，每段不超过 `chunk_tokens`；单独一行过长时按字符切开
+    fn chunks<'a>(&self, text: &'a str) -> Vec<&'a str> {
+        let mut chunks = Vec::new();
+        let mut rest = text;
+        while !rest.is_empty() {
+            let head = self.estimator.truncate(rest, self.chunk_tokens);
+            let end = match head.rfind('\n') {
+                Some(newline) if head.len() < rest.len() && newline > 0 => newline + 1,
+                _ if head.is_empty() => rest.chars().next().map_or(rest.len(), char::len_utf8),
+                _ => head.len(),
+            };
+            chunks.push(&rest[..end]);
+            rest = &rest[end..];
+        }
+        chunks
+    }
+
+    /// 从最早的一轮开始丢弃线程历史，直到放得下
+    pub fn fit_history(&self, mut history: Vec<ChatMessage>) -> Vec<ChatMessage> {
+        let mut total: u32 = history.iter().map(|message| self.estimator.estimate(&message.content)).sum();
+        let mut dropped = 0;
+        while total > self.history_tokens && !history.is_empty() {
+            total -= self.estimator.estimate(&history.remove(0).content);
+            dropped += 1;
+        }
+        if dropped > 0 {
+            info!(dropped, "Dropped earlier thread messages to fit the context budget");
+        }
+        history
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::email::quote::split_body;
+    use crate::llm::client::tests::response;
+    use crate::llm::client::{LlmResponse, LlmResult};
+    use async_trait::async_trait;
+    use std::sync::Mutex;
+
+    /// 把每段摘要成一行的模型，记录收到的片段
+    #[derive(Default)]
+    struct Summariser {
+        chunks: Mutex<Vec<String>>,
+    }
+
+    #[async_trait]
+    impl LlmClient for Summariser {
+        async fn generate_response(&self, request: &LlmRequest) -> LlmResult<LlmResponse> {
+            assert_eq!(request.prompt_name, "email_chunk_summary");
+            let chunk = request.context["chunk"].as_str().unwrap().to_string();
+            self.chunks.lock().unwrap().push(chunk);
+            Ok(response(request, format!("第 {} 段摘要", request.context["part"])))
+        }
+    }
+
+    fn budget(window: u32) -> ContextBudget {
+        let config = ContextConfig { window: Some(window), reserved: 0, chunk_tokens: 100, max_chunks: 4 };
+        ContextBudget::new(&config, &["deepseek-chat"])
+    }
+
+    #[test]
+    fn test_estimates_by_model_family() {
+        let deepseek = TokenEstimator::for_model("deepseek-chat");
+        assert_eq!(deepseek.estimate("hello world"), 4);
+        assert_eq!(deepseek.estimate("你好世界"), 3);
+        assert_eq!(TokenEstimator::for_model("gpt-4o-mini").window, 128_000);
+        assert_eq!(TokenEstimator::for_model("GPT-4").window, 8_192);
+        assert_eq!(TokenEstimator::for_model("unknown").window, DEFAULT_PROFILE.0);
+
+        // 备用后端的窗口更小时按它计算
+        let chain = ContextBudget::new(&ContextConfig::default(), &["deepseek-chat", "llama3:8b"]);
+        assert_eq!((chain.estimator.window, chain.estimator.other), (8_192, 1.0));
+
+        let history = vec![ChatMessage::user("a".repeat(800)), ChatMessage::assistant("b".repeat(100))];
+        assert_eq!(budget(400).fit_history(history).len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_trims_quotes_then_summarises() {
+        let llm = Summariser::default();
+        let short = split_body("Thanks, see you Thursday.");
+        assert_eq!(budget(400).fit_parts(&llm, short.clone()).await.unwrap(), short);
+
+        // 引用的历史邮件放不下时只保留最近的部分，不需要调用模型
+        let quoted = split_body(&format!("{}\n\nOn Mon, Bob wrote:\n{}", short.text, "> earlier message\n".repeat(100)));
+        let trimmed = budget(400).fit_parts(&llm, quoted.clone()).await.unwrap();
+        assert_eq!(trimmed.text, short.text);
+        assert!(trimmed.quoted.contains("> earlier message") && trimmed.quoted.ends_with("已省略]"));
+        assert!(trimmed.quoted.len() < quoted.quoted.len());
+        assert!(llm.chunks.lock().unwrap().is_empty());
+
+        // 新写的内容本身过长（例如粘贴的日志）时分段摘要
+        let log = "ERROR connection reset by peer at 10.0.0.1:443\n".repeat(40);
+        let summary = budget(400).fit_parts(&llm, BodyParts { text: log, quoted: String::new() }).await.unwrap();
+        assert!(summary.text.starts_with("[原邮件过长") && summary.quoted.is_empty());
+        let chunks = llm.chunks.lock().unwrap();
+        assert_eq!(chunks.len(), 4);
+        assert!(chunks.iter().all(|chunk| chunk.ends_with('\n')));
+    }
+}
@@ -0,0 +1,157 @@
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use std::time::Duration;
+use tokio::fs;
+use tokio::sync::Mutex;
+use tracing::{info, warn};
+
+use crate::email::{EmailAddress, EmailClient, EmailMessage};
+
+/// 摘要中的一条记录
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DigestEntry {
+    pub received_at: DateTime<Utc>,
+    pub from: String,
+    pub subject: String,
+    pub category: String,
+    pub summary: String,
+}
+
+/// 持久化的待发送摘要条目
+pub struct DigestStore {
+    path: PathBuf,
+    lock: Mutex<()>,
+}
+
+impl DigestStore {
+    pub fn new(path: PathBuf) -> Self {
+        Self {
+            path,
+            lock: Mutex::new(()),
+        }
+    }
+
+    async fn load(&self) -> Result<Vec<DigestEntry>> {
+        if !self.path.exists() {
+            return Ok(Vec::new());
+        }
+        let content = fs::read_to_string(&self.path).await?;
+        Ok(serde_json::from_str(&content)?)
+    }
+
+    pub async fn add(&self, entry: DigestEntry) -> Result<()> {
+        let _guard = self.lock.lock().await;
+        let mut entries = self.load().await?;
+        entries.push(entry);
+        fs::write(&self.path, serde_json::to_string_pretty(&entries)?).await?;
+        Ok(())
+    }
+
+    /// 取出全部条目并清空存储
+    pub async fn take_all(&self) -> Result<Vec<DigestEntry>> {
+        let _guard = self.lock.lock().await;
+        let entries = self.load().await?;
+        if !entries.is_empty() {
+            fs::write(&self.path, "[]").await?;
+        }
+        Ok(entries)
+    }
+
+    /// 失败时把条目放回去，下次一起发送
+    async fn restore(&self, mut entries: Vec<DigestEntry>) -> Result<()> {
+        let _guard = self.lock.lock().await;
+        entries.extend(self.load().await?);
+        fs::write(&self.path, serde_json::to_string_pretty(&entries)?).await?;
+        Ok(())
+    }
+}
+
+fn render_digest(entries: &[DigestEntry]) -> String {
+    let mut body = format!("以下是最近收到的 {} 封邮件：\n", entries.len());
+    for entry in entries {
+        body.push_str(&format!(
+            "\n[{}] {}\n发件人：{}\n时间：{}\n摘要：{}\n",
+            entry.category,
+            entry.subject,
+            entry.from,
+            entry.received_at.format("%Y-%m-%d %H:%M UTC"),
+            entry.summary
+        ));
+    }
+    body
+}
+
+/// 定期把累积的摘要条目发给 owner
+pub async fn run_digest_sender(
+    store: std::sync::Arc<DigestStore>,
+    email_client: Box<dyn EmailClient>,
+    from: EmailAddress,
+    to: EmailAddress,
+    interval: Duration,
+) {
+    let mut ticker = tokio::time::interval(interval);
+    ticker.tick().await;
+    loop {
+        ticker.tick().await;
+
+        let entries = match store.take_all().await {
+            Ok(entries) if entries.is_empty() => continue,
+            Ok(entries) => entries,
+            Err(e) => {
+                warn!(error = %e, "Failed to read digest entries");
+                continue;
+            }
+        };
+
+        let message = EmailMessage {
+            from: from.clone(),
+            to: vec![to.clone()],
+            subject: format!("Sentio 邮件摘要（{} 封）", entries.len()),
+            body: render_digest(&entries),
+            is_html: false,
+            in_reply_to: None,
+            references: Vec::new(),
+        };
+
+        match email_client.send(message).await {
+            Ok(_) => info!("Digest with {} entries sent to {}", entries.len(), to.email),
+            Err(e) => {
+                warn!(error = %e, "Failed to send digest, will retry next time");
+                if let Err(e) = store.restore(entries).await {
+                    warn!(error = %e, "Failed to restore digest entries");
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_take_all_drains_entries() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = DigestStore::new(dir.path().join("digest.json"));
+
+        for subject in ["a", "b"] {
+            store
+                .add(DigestEntry {
+                    received_at: Utc::now(),
+                    from: "owner@qq.com".to_string(),
+                    subject: subject.to_string(),
+                    category: "系统通知".to_string(),
+                    summary: "summary".to_string(),
+                })
+                .await
+                .unwrap();
+        }
+
+        let entries = store.take_all().await.unwrap();
+        assert_eq!(entries.len(), 2);
+        assert!(render_digest(&entries).contains("最近收到的 2 封邮件"));
+        assert!(store.take_all().await.unwrap().is_empty());
+    }
+}
@@ -0,0 +1,91 @@
+use super::InboundEmail;
+use crate::senders::normalize_address;
+
+/// 自动回复邮件常见的主题前缀（小写）
+const AUTO_REPLY_SUBJECTS: [&str; 8] = [
+    "auto:",
+    "automatic reply",
+    "autoreply",
+    "auto-reply",
+    "out of office",
+    "out of the office",
+    "自动回复",
+    "自動回覆",
+];
+
+/// 不应回复的退信发件人（本地部分）
+const DAEMON_SENDERS: [&str; 3] = ["mailer-daemon", "postmaster", "noreply"];
+
+/// 邮件是否由 Sentio 自己发出（带 `X-Sentio` 头）
+pub fn sent_by_sentio(message: &InboundEmail) -> bool {
+    message.header("X-Sentio").is_some()
+}
+
+/// 判断入站邮件是否来自自动程序（退信、休假回复、邮件列表、Sentio 自己），返回原因
+pub fn automated_reason(message: &InboundEmail) -> Option<&'static str> {
+    let header = |name: &str| message.header(name).map(|value| value.trim().to_ascii_lowercase());
+
+    // RFC 3834：Auto-Submitted 为 no 以外的值都表示自动发出
+    if header("Auto-Submitted").is_some_and(|value| !value.starts_with("no")) {
+        return Some("Auto-Submitted header");
+    }
+    if sent_by_sentio(message) {
+        return Some("sent by Sentio");
+    }
+    if header("Precedence").is_some_and(|value| matches!(value.as_str(), "bulk" | "list" | "junk" | "auto_reply")) {
+        return Some("Precedence header");
+    }
+    if message.header("List-Id").is_some() {
+        return Some("mailing list");
+    }
+    if header("Return-Path").is_some_and(|value| value.trim_matches(|c: char| c == '<' || c == '>' || c.is_whitespace()).is_empty()) {
+        return Some("null return path");
+    }
+
+    // 休假回复：各家邮件系统使用的私有头部以及主题前缀
+    if message.header("X-Autoreply").is_some()
+        || message.header("X-Autorespond").is_some()
+        || header("X-Auto-Response-Suppress").is_some_and(|value| value.contains("all") || value.contains("oof"))
+    {
+        return Some("out-of-office reply");
+    }
+    let subject = message.subject.trim().to_lowercase();
+    if AUTO_REPLY_SUBJECTS.iter().any(|prefix| subject.starts_with(prefix)) {
+        return Some("out-of-office reply");
+    }
+
+    let sender = normalize_address(&message.from.email);
+    let local = sender.split('@').next().unwrap_or_default();
+    if DAEMON_SENDERS.iter().any(|daemon| local.starts_with(daemon)) {
+        return Some("bounce or no-reply sender");
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::email::parser::parse_message;
+
+    fn reason(headers: &str) -> Option<&'static str> {
+        let raw = format!("{}\r\nTo: sentio@example.com\r\n\r\nbody\r\n", headers);
+        automated_reason(&parse_message(raw.as_bytes()).unwrap())
+    }
+
+    #[test]
+    fn test_detects_automated_mail() {
+        let from = "From: alice@example.com\r\nSubject: 周报";
+        assert_eq!(reason(from), None);
+        assert_eq!(reason(&format!("{}\r\nAuto-Submitted: no", from)), None);
+        assert_eq!(reason(&format!("{}\r\nAuto-Submitted: auto-replied", from)), Some("Auto-Submitted header"));
+        assert_eq!(reason(&format!("{}\r\nX-Sentio: 0.1.0", from)), Some("sent by Sentio"));
+        assert_eq!(reason(&format!("{}\r\nPrecedence: Bulk", from)), Some("Precedence header"));
+        assert_eq!(reason(&format!("{}\r\nList-Id: <dev.lists.example.com>", from)), Some("mailing list"));
+        assert_eq!(reason(&format!("Return-Path: <>\r\n{}", from)), Some("null return path"));
+        assert_eq!(reason(&format!("Return-Path: <alice@example.com>\r\n{}", from)), None);
+        assert_eq!(reason(&format!("{}\r\nX-Auto-Response-Suppress: All", from)), Some("out-of-office reply"));
+        assert_eq!(reason("From: alice@example.com\r\nSubject: Automatic reply: 周报"), Some("out-of-office reply"));
+        assert_eq!(reason("From: alice@example.com\r\nSubject: =?UTF-8?B?6Ieq5Yqo5Zue5aSN77ya5ZGo5oql?="), Some("out-of-office reply"));
+        assert_eq!(reason("From: MAILER-DAEMON@example.com\r\nSubject: Undelivered Mail"), Some("bounce or no-reply sender"));
+    }
+}
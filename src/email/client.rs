@@ -1,13 +1,24 @@
 use async_trait::async_trait;
 use lettre::{
-    message::{header::ContentType, Mailbox},
-    transport::smtp::authentication::Credentials,
-    Message, SmtpTransport, Transport,
+    message::{
+        header::{ContentType, HeaderName, HeaderValue},
+        Mailbox,
+    },
+    transport::smtp::{
+        authentication::Credentials,
+        client::{Tls, TlsParameters},
+        PoolConfig,
+    },
+    AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor,
 };
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
 use thiserror::Error;
+use tokio::time::timeout;
+use tracing::warn;
 
 use super::EmailAddress;
-use crate::config::SmtpConfig;
+use crate::config::{SmtpConfig, SmtpSecurity};
 
 #[derive(Error, Debug)]
 pub enum EmailError {
@@ -16,20 +27,53 @@ pub enum EmailError {
     
     #[error("Send error: {0}")]
     Send(String),
+
+    /// 服务器以 5xx 永久拒绝，重试也不会成功
+    #[error("Rejected by server: {0}")]
+    Rejected(String),
+
+    /// 会话超时，无法确定服务器是否已经收下邮件
+    #[error("Delivery unconfirmed: {0}")]
+    Unconfirmed(String),
     
     #[error("Validation error: {0}")]
     Validation(String),
+
+    #[error("Receive error: {0}")]
+    Receive(String),
+
+    #[error("Parse error: {0}")]
+    Parse(String),
+
+    #[error("Queue error: {0}")]
+    Queue(String),
+}
+
+impl EmailError {
+    /// 重试不会改变结果的错误
+    pub fn is_permanent(&self) -> bool {
+        matches!(self, Self::Rejected(_) | Self::Validation(_))
+    }
+
+    /// 邮件可能已经送达，重发可能让对方收到两封
+    pub fn may_have_been_delivered(&self) -> bool {
+        matches!(self, Self::Unconfirmed(_))
+    }
 }
 
 pub type EmailResult<T> = Result<T, EmailError>;
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct EmailMessage {
     pub from: EmailAddress,
     pub to: Vec<EmailAddress>,
     pub subject: String,
     pub body: String,
     pub is_html: bool,
+    /// 回复时被回复邮件的 Message-ID（不含尖括号）
+    pub in_reply_to: Option<String>,
+    /// 线程中的 Message-ID 链，从最早的邮件开始
+    pub references: Vec<String>,
 }
 
 #[async_trait]
@@ -38,27 +82,43 @@ pub trait EmailClient: Send + Sync {
 }
 
 pub struct SmtpClient {
-    transport: SmtpTransport,
+    transport: AsyncSmtpTransport<Tokio1Executor>,
+    send_timeout: Duration,
 }
 
 impl SmtpClient {
     pub fn new(config: SmtpConfig) -> EmailResult<Self> {
+        if config.command_timeout.is_some() {
+            warn!("email.smtp.command_timeout is no longer supported and is ignored; send_timeout limits the whole SMTP session");
+        }
         let creds = Credentials::new(config.username.clone(), config.password.clone());
-        
-        let transport = if config.use_tls {
-            SmtpTransport::relay(&config.host)
-                .map_err(|e| EmailError::Connection(e.to_string()))?
-                .port(config.port)
-                .credentials(creds)
-                .build()
-        } else {
-            SmtpTransport::builder_dangerous(&config.host)
-                .port(config.port)
-                .credentials(creds)
-                .build()
+
+        let tls_parameters = || {
+            TlsParameters::new(config.host.clone()).map_err(|e| EmailError::Connection(e.to_string()))
+        };
+        let tls = match config.security() {
+            SmtpSecurity::ImplicitTls => Tls::Wrapper(tls_parameters()?),
+            SmtpSecurity::StartTls => Tls::Required(tls_parameters()?),
+            SmtpSecurity::None => Tls::None,
         };
 
-        Ok(Self { transport })
+        // 加密方式已通过 tls() 显式设置，builder_dangerous 只是不带默认 TLS 的构造入口
+        let transport = AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(&config.host)
+            .port(config.port)
+            .tls(tls)
+            .credentials(creds)
+            .timeout(Some(Duration::from_secs(config.connect_timeout)))
+            .pool_config(
+                PoolConfig::new()
+                    .max_size(config.pool_size)
+                    .idle_timeout(Duration::from_secs(config.pool_idle_timeout)),
+            )
+            .build();
+
+        Ok(Self {
+            transport,
+            send_timeout: Duration::from_secs(config.send_timeout),
+        })
     }
 
     fn build_message(&self, msg: &EmailMessage) -> EmailResult<Message> {
@@ -67,9 +127,26 @@ impl SmtpClient {
             msg.from.email.parse().map_err(|e| EmailError::Validation(format!("Invalid from address: {}", e)))?,
         );
 
+        let subject = if msg.in_reply_to.is_some() {
+            reply_subject(&msg.subject)
+        } else {
+            msg.subject.clone()
+        };
+
+        // 标记为自动回复（RFC 3834），对方的自动回复程序和我们自己的入站检查都会据此跳过
         let mut message = Message::builder()
             .from(from_mailbox)
-            .subject(&msg.subject);
+            .subject(subject)
+            .raw_header(HeaderValue::new(HeaderName::new_from_ascii_str("Auto-Submitted"), "auto-replied".to_string()))
+            .raw_header(HeaderValue::new(HeaderName::new_from_ascii_str("X-Sentio"), env!("CARGO_PKG_VERSION").to_string()));
+
+        if let Some(in_reply_to) = &msg.in_reply_to {
+            message = message.in_reply_to(format!("<{}>", in_reply_to));
+        }
+        if !msg.references.is_empty() {
+            let references: Vec<String> = msg.references.iter().map(|id| format!("<{}>", id)).collect();
+            message = message.references(references.join(" "));
+        }
 
         for to_addr in &msg.to {
             let to_mailbox = Mailbox::new(
@@ -92,21 +169,62 @@ impl SmtpClient {
     }
 }
 
+/// 去掉主题前面重复的回复前缀（Re:、RE[2]:、Aw:、回复：、答复：等），只保留一个 `Re: `
+pub fn reply_subject(subject: &str) -> String {
+    let mut rest = subject.trim();
+    while let Some(stripped) = strip_reply_prefix(rest) {
+        rest = stripped.trim_start();
+    }
+    format!("Re: {}", rest)
+}
+
+fn strip_reply_prefix(subject: &str) -> Option<&str> {
+    const PREFIXES: [&str; 5] = ["re", "aw", "sv", "回复", "答复"];
+
+    let mut rest = PREFIXES.iter().find_map(|prefix| {
+        let head = subject.get(..prefix.len())?;
+        (head.to_lowercase() == *prefix).then(|| &subject[prefix.len()..])
+    })?;
+
+    // 部分客户端会写成 "Re[2]:" 或 "Re(2):"
+    if let Some(after) = rest.strip_prefix('[').or_else(|| rest.strip_prefix('(')) {
+        let end = after.find([']', ')'])?;
+        if !after[..end].chars().all(|c| c.is_ascii_digit()) {
+            return None;
+        }
+        rest = &after[end + 1..];
+    }
+
+    let rest = rest.trim_start();
+    rest.strip_prefix(':').or_else(|| rest.strip_prefix('：'))
+}
+
 #[async_trait]
 impl EmailClient for SmtpClient {
     async fn send(&self, message: EmailMessage) -> EmailResult<String> {
         let email = self.build_message(&message)?;
-        
-        self.transport
-            .send(&email)
-            .map(|_| "Email sent successfully".to_string())
-            .map_err(|e| EmailError::Send(e.to_string()))
+
+        // 不在这里重试：出错时可能已经发完 DATA，服务器也许已经收下邮件，重发会让对方收到两封。
+        // 连接池取出空闲连接时会先用 NOOP 检查，失效的连接不会用来发信；临时失败交给发件队列稍后重试。
+        match timeout(self.send_timeout, self.transport.send(email)).await {
+            Ok(Ok(_)) => Ok("Email sent successfully".to_string()),
+            Ok(Err(e)) if e.is_permanent() => Err(EmailError::Rejected(e.to_string())),
+            Ok(Err(e)) if e.is_response() || e.is_client() || e.is_tls() => Err(EmailError::Send(e.to_string())),
+            Ok(Err(e)) => Err(EmailError::Connection(e.to_string())),
+            // 超时可能发生在 DATA 结束之后、服务器回复之前，不能当作临时失败重发
+            Err(_) => Err(EmailError::Unconfirmed(format!("SMTP session timed out after {:?}", self.send_timeout))),
+        }
     }
 }
 
 #[cfg(test)]
-mod tests {
+pub(crate) mod tests {
     use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+    use tokio::net::{TcpListener, TcpStream};
+    use tokio::time::Instant;
 
     #[test]
     fn test_email_address_creation() {
@@ -118,4 +236,143 @@ mod tests {
         assert_eq!(addr_with_name.email, "test@example.com");
         assert_eq!(addr_with_name.name.as_deref(), Some("Test User"));
     }
+
+    #[test]
+    fn test_reply_subject_normalises_prefixes() {
+        assert_eq!(reply_subject("项目进展"), "Re: 项目进展");
+        assert_eq!(reply_subject("Re: Re: 项目进展"), "Re: 项目进展");
+        assert_eq!(reply_subject("RE[2]: re:项目进展"), "Re: 项目进展");
+        assert_eq!(reply_subject("回复：答复: 项目进展"), "Re: 项目进展");
+        assert_eq!(reply_subject("Report"), "Re: Report");
+    }
+
+    fn smtp_config(port: u16) -> SmtpConfig {
+        toml::from_str(&format!(
+            "host = \"127.0.0.1\"\nport = {}\nusername = \"sentio@example.com\"\npassword = \"secret\"\nsecurity = \"none\"\nsend_timeout = 1",
+            port
+        ))
+        .unwrap()
+    }
+
+    /// 测试用的待发邮件：由 sentio@example.com 发出的纯文本邮件，不属于任何线程
+    pub(crate) fn message(to: &str, subject: &str, body: &str) -> EmailMessage {
+        EmailMessage {
+            from: EmailAddress::new("sentio@example.com"),
+            to: vec![EmailAddress::new(to)],
+            subject: subject.to_string(),
+            body: body.to_string(),
+            is_html: false,
+            in_reply_to: None,
+            references: Vec::new(),
+        }
+    }
+
+    /// 进程内的最小 SMTP 服务器会话；`stall_after` 为 Some 时在收到该命令（`.` 表示邮件内容结束）后不再响应
+    async fn serve(socket: TcpStream, stall_after: Option<&'static str>) -> Vec<String> {
+        let (read, mut write) = socket.into_split();
+        let mut lines = BufReader::new(read).lines();
+        let mut commands = Vec::new();
+        let mut in_data = false;
+
+        write.write_all(b"220 stand-in ESMTP\r\n").await.unwrap();
+        while let Ok(Some(line)) = lines.next_line().await {
+            if in_data {
+                if line == "." {
+                    if stall_after == Some(".") {
+                        tokio::time::sleep(Duration::from_secs(30)).await;
+                    }
+                    in_data = false;
+                    write.write_all(b"250 queued\r\n").await.unwrap();
+                }
+                continue;
+            }
+
+            let verb = line.split_whitespace().next().unwrap_or("").to_ascii_uppercase();
+            commands.push(verb.clone());
+            if stall_after == Some(verb.as_str()) {
+                tokio::time::sleep(Duration::from_secs(30)).await;
+            }
+            let reply: &[u8] = match verb.as_str() {
+                "EHLO" => b"250-stand-in\r\n250 AUTH PLAIN LOGIN\r\n",
+                "AUTH" => b"235 authenticated\r\n",
+                "DATA" => {
+                    in_data = true;
+                    b"354 go ahead\r\n"
+                }
+                "QUIT" => b"221 bye\r\n",
+                _ => b"250 ok\r\n",
+            };
+            write.write_all(reply).await.unwrap();
+        }
+        commands
+    }
+
+    #[test]
+    fn test_security_inferred_from_legacy_use_tls() {
+        let legacy = |port: u16, use_tls: bool| -> SmtpConfig {
+            toml::from_str(&format!(
+                "host = \"smtp.example.com\"\nport = {}\nusername = \"u\"\npassword = \"p\"\nuse_tls = {}",
+                port, use_tls
+            ))
+            .unwrap()
+        };
+        assert_eq!(legacy(465, true).security(), SmtpSecurity::ImplicitTls);
+        assert_eq!(legacy(587, true).security(), SmtpSecurity::StartTls);
+        assert_eq!(legacy(25, false).security(), SmtpSecurity::None);
+        assert_eq!(smtp_config(465).security(), SmtpSecurity::None);
+    }
+
+    #[tokio::test]
+    async fn test_async_send_and_session_timeout() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = tokio::spawn(async move { serve(listener.accept().await.unwrap().0, None).await });
+
+        let client = SmtpClient::new(smtp_config(port)).unwrap();
+        client.send(message("owner@qq.com", "周报", "本周进展顺利")).await.unwrap();
+        client.transport.shutdown().await;
+        let commands = server.await.unwrap();
+        assert_eq!(commands[..5], ["EHLO", "AUTH", "MAIL", "RCPT", "DATA"]);
+
+        // 服务器无响应：会话 1 秒超时后返回无法确认送达的错误，不重新发送
+        for stall_after in ["MAIL", "."] {
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let port = listener.local_addr().unwrap().port();
+            let sessions = Arc::new(AtomicUsize::new(0));
+            let accepted = sessions.clone();
+            tokio::spawn(async move {
+                while let Ok((socket, _)) = listener.accept().await {
+                    accepted.fetch_add(1, Ordering::SeqCst);
+                    tokio::spawn(serve(socket, Some(stall_after)));
+                }
+            });
+
+            let client = SmtpClient::new(smtp_config(port)).unwrap();
+            let started = Instant::now();
+            let err = client.send(message("owner@qq.com", "周报", "本周进展顺利")).await.unwrap_err();
+            assert!(err.may_have_been_delivered(), "{:?}", err);
+            assert!(started.elapsed() < Duration::from_secs(2));
+            assert_eq!(sessions.load(Ordering::SeqCst), 1);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_build_message_sets_threading_and_auto_submitted_headers() {
+        let client = SmtpClient::new(smtp_config(25)).unwrap();
+
+        let message = client
+            .build_message(&EmailMessage {
+                in_reply_to: Some("b@qq.com".to_string()),
+                references: vec!["a@qq.com".to_string(), "b@qq.com".to_string()],
+                ..message("owner@qq.com", "Re: Re: 项目进展", "收到")
+            })
+            .unwrap();
+
+        let headers = message.headers();
+        assert_eq!(headers.get_raw("In-Reply-To"), Some("<b@qq.com>"));
+        assert_eq!(headers.get_raw("References"), Some("<a@qq.com> <b@qq.com>"));
+        assert_eq!(headers.get_raw("Subject"), Some("Re: 项目进展"));
+        assert_eq!(headers.get_raw("Auto-Submitted"), Some("auto-replied"));
+        assert_eq!(headers.get_raw("X-Sentio"), Some(env!("CARGO_PKG_VERSION")));
+    }
 }
\ No newline at end of file
@@ -0,0 +1,417 @@
+use async_imap::extensions::idle::IdleResponse;
+use async_imap::types::Fetch;
+use async_imap::{Client, Session};
+use async_trait::async_trait;
+use futures::TryStreamExt;
+use std::collections::HashSet;
+use std::fmt::Debug;
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::net::TcpStream;
+use tracing::{debug, error, info, warn};
+
+use super::client::{EmailError, EmailResult};
+use super::parser::parse_message;
+use super::receiver::{MailReceiver, MessageHandler};
+use crate::config::ImapConfig;
+
+const MAX_RECONNECT_DELAY: Duration = Duration::from_secs(300);
+
+/// IMAP 连接可以是 TLS 也可以是明文 TCP（仅用于本地测试）
+trait ImapStream: AsyncRead + AsyncWrite + Unpin + Send + Debug {}
+
+impl<T: AsyncRead + AsyncWrite + Unpin + Send + Debug> ImapStream for T {}
+
+struct ImapConnection {
+    session: Session<Box<dyn ImapStream>>,
+    supports_idle: bool,
+    supports_move: bool,
+}
+
+pub struct ImapReceiver {
+    config: ImapConfig,
+}
+
+impl ImapReceiver {
+    pub fn new(config: ImapConfig) -> Self {
+        Self { config }
+    }
+
+    async fn connect(&self) -> EmailResult<ImapConnection> {
+        let tcp = TcpStream::connect((self.config.host.as_str(), self.config.port))
+            .await
+            .map_err(|e| EmailError::Connection(e.to_string()))?;
+
+        let stream: Box<dyn ImapStream> = if self.config.use_tls {
+            let connector = tokio_native_tls::native_tls::TlsConnector::new()
+                .map_err(|e| EmailError::Connection(e.to_string()))?;
+            let tls = tokio_native_tls::TlsConnector::from(connector)
+                .connect(&self.config.host, tcp)
+                .await
+                .map_err(|e| EmailError::Connection(e.to_string()))?;
+            Box::new(tls)
+        } else {
+            Box::new(tcp)
+        };
+
+        let mut client = Client::new(stream);
+        client
+            .read_response()
+            .await
+            .map_err(|e| EmailError::Connection(e.to_string()))?
+            .ok_or_else(|| EmailError::Connection("Connection closed before IMAP greeting".to_string()))?;
+
+        let mut session = client
+            .login(&self.config.username, &self.config.password)
+            .await
+            .map_err(|(e, _)| EmailError::Connection(format!("IMAP login failed: {}", e)))?;
+
+        let capabilities = session.capabilities().await.map_err(receive_error)?;
+        let supports_idle = capabilities.has_str("IDLE");
+        let supports_move = capabilities.has_str("MOVE");
+
+        session.select(&self.config.mailbox).await.map_err(receive_error)?;
+        info!(
+            host = %self.config.host,
+            mailbox = %self.config.mailbox,
+            supports_idle,
+            "Connected to IMAP server"
+        );
+
+        Ok(ImapConnection {
+            session,
+            supports_idle,
+            supports_move,
+        })
+    }
+
+    async fn listen(&self, mut connection: ImapConnection, handler: &dyn MessageHandler) -> EmailResult<()> {
+        if !connection.supports_idle {
+            info!("IMAP server does not support IDLE, polling every {}s", self.config.poll_interval);
+        }
+
+        // 本次连接中处理失败的邮件不再重复处理，重连后会再次尝试
+        let mut failed = HashSet::new();
+        loop {
+            self.process_unseen(&mut connection, handler, &mut failed).await?;
+
+            if connection.supports_idle {
+                connection.session = self.idle(connection.session).await?;
+            } else {
+                tokio::time::sleep(Duration::from_secs(self.config.poll_interval)).await;
+            }
+        }
+    }
+
+    async fn idle(&self, session: Session<Box<dyn ImapStream>>) -> EmailResult<Session<Box<dyn ImapStream>>> {
+        let mut handle = session.idle();
+        handle.init().await.map_err(receive_error)?;
+
+        {
+            let (wait, _stop) = handle.wait_with_timeout(Duration::from_secs(self.config.idle_timeout));
+            match wait.await.map_err(receive_error)? {
+                IdleResponse::NewData(data) => debug!("IMAP IDLE notification: {:?}", data.parsed()),
+                IdleResponse::Timeout => debug!("IMAP IDLE timed out, re-issuing"),
+                IdleResponse::ManualInterrupt => {}
+            }
+        }
+
+        handle.done().await.map_err(receive_error)
+    }
+
+    async fn process_unseen(
+        &self,
+        connection: &mut ImapConnection,
+        handler: &dyn MessageHandler,
+        failed: &mut HashSet<u32>,
+    ) -> EmailResult<usize> {
+        let mut uids: Vec<u32> = connection
+            .session
+            .uid_search("UNSEEN")
+            .await
+            .map_err(receive_error)?
+            .into_iter()
+            .filter(|uid| !failed.contains(uid))
+            .collect();
+        uids.sort_unstable();
+
+        let mut processed = 0;
+        for uid in uids {
+            let Some(raw) = fetch_raw(&mut connection.session, uid).await? else {
+                warn!(uid, "IMAP server returned no body for message");
+                continue;
+            };
+
+            let message = match parse_message(&raw) {
+                Ok(message) => message,
+                Err(e) => {
+                    warn!(uid, error = %e, "Failed to parse email, leaving it unseen");
+                    failed.insert(uid);
+                    continue;
+                }
+            };
+
+            match handler.handle(&message).await {
+                Ok(()) => {
+                    self.mark_processed(connection, uid).await?;
+                    processed += 1;
+                }
+                Err(e) => {
+                    error!(uid, error = %e, "Failed to process email, leaving it unseen");
+                    failed.insert(uid);
+                }
+            }
+        }
+
+        Ok(processed)
+    }
+
+    async fn mark_processed(&self, connection: &mut ImapConnection, uid: u32) -> EmailResult<()> {
+        let session = &mut connection.session;
+        let uid_set = uid.to_string();
+
+        let _: Vec<Fetch> = session
+            .uid_store(&uid_set, "+FLAGS (\\Seen)")
+            .await
+            .map_err(receive_error)?
+            .try_collect()
+            .await
+            .map_err(receive_error)?;
+
+        let Some(folder) = &self.config.move_to else {
+            return Ok(());
+        };
+
+        if connection.supports_move {
+            session.uid_mv(&uid_set, folder).await.map_err(receive_error)?;
+        } else {
+            session.uid_copy(&uid_set, folder).await.map_err(receive_error)?;
+            let _: Vec<Fetch> = session
+                .uid_store(&uid_set, "+FLAGS (\\Deleted)")
+                .await
+                .map_err(receive_error)?
+                .try_collect()
+                .await
+                .map_err(receive_error)?;
+            let _: Vec<u32> = session
+                .uid_expunge(&uid_set)
+                .await
+                .map_err(receive_error)?
+                .try_collect()
+                .await
+                .map_err(receive_error)?;
+        }
+        Ok(())
+    }
+}
+
+async fn fetch_raw(session: &mut Session<Box<dyn ImapStream>>, uid: u32) -> EmailResult<Option<Vec<u8>>> {
+    let fetches: Vec<Fetch> = session
+        .uid_fetch(uid.to_string(), "BODY.PEEK[]")
+        .await
+        .map_err(receive_error)?
+        .try_collect()
+        .await
+        .map_err(receive_error)?;
+
+    Ok(fetches.iter().find_map(|fetch| fetch.body().map(<[u8]>::to_vec)))
+}
+
+#[async_trait]
+impl MailReceiver for ImapReceiver {
+    /// 持续监听收件箱，连接断开后按指数退避重连
+    async fn run(&self, handler: &dyn MessageHandler) -> EmailResult<()> {
+        let mut delay = Duration::from_secs(1);
+        loop {
+            let result = match self.connect().await {
+                Ok(connection) => {
+                    delay = Duration::from_secs(1);
+                    self.listen(connection, handler).await
+                }
+                Err(e) => Err(e),
+            };
+
+            if let Err(e) = result {
+                warn!(error = %e, "IMAP connection lost, reconnecting in {}s", delay.as_secs());
+                tokio::time::sleep(delay).await;
+                delay = (delay * 2).min(MAX_RECONNECT_DELAY);
+            }
+        }
+    }
+}
+
+fn receive_error(e: async_imap::error::Error) -> EmailError {
+    EmailError::Receive(e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::email::InboundEmail;
+    use std::sync::{Arc, Mutex};
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+    use tokio::net::TcpListener;
+    use tokio::sync::mpsc;
+
+    struct StoredMessage {
+        uid: u32,
+        raw: String,
+        seen: bool,
+    }
+
+    type Mailbox = Arc<Mutex<Vec<StoredMessage>>>;
+
+    fn raw_email(subject: &str) -> String {
+        format!(
+            "From: owner@example.com\r\nTo: sentio@example.com\r\nSubject: {}\r\n\r\nbody of {}\r\n",
+            subject, subject
+        )
+    }
+
+    /// 进程内的最小 IMAP 服务器，只实现接收流程用到的命令
+    async fn serve(listener: TcpListener, mailbox: Mailbox, mut incoming: mpsc::Receiver<String>) {
+        let (socket, _) = listener.accept().await.unwrap();
+        let (read, mut write) = socket.into_split();
+        let mut lines = BufReader::new(read).lines();
+
+        write.write_all(b"* OK stand-in ready\r\n").await.unwrap();
+
+        while let Ok(Some(line)) = lines.next_line().await {
+            let (tag, command) = line.split_once(' ').unwrap();
+            let upper = command.to_ascii_uppercase();
+            let mut reply = String::new();
+
+            if upper.starts_with("LOGIN") {
+                reply.push_str(&format!("{} OK LOGIN completed\r\n", tag));
+            } else if upper.starts_with("CAPABILITY") {
+                reply.push_str(&format!("* CAPABILITY IMAP4rev1 IDLE\r\n{} OK CAPABILITY completed\r\n", tag));
+            } else if upper.starts_with("SELECT") {
+                let exists = mailbox.lock().unwrap().len();
+                reply.push_str(&format!(
+                    "* {} EXISTS\r\n* 0 RECENT\r\n* FLAGS (\\Seen \\Deleted)\r\n{} OK [READ-WRITE] SELECT completed\r\n",
+                    exists, tag
+                ));
+            } else if upper.starts_with("UID SEARCH") {
+                let unseen: Vec<String> = mailbox
+                    .lock()
+                    .unwrap()
+                    .iter()
+                    .filter(|m| !m.seen)
+                    .map(|m| m.uid.to_string())
+                    .collect();
+                reply.push_str(&format!("* SEARCH {}\r\n{} OK SEARCH completed\r\n", unseen.join(" "), tag));
+            } else if upper.starts_with("UID FETCH") {
+                let uid: u32 = command.split_whitespace().nth(2).unwrap().parse().unwrap();
+                let mailbox = mailbox.lock().unwrap();
+                let (seq, message) = mailbox.iter().enumerate().find(|(_, m)| m.uid == uid).unwrap();
+                reply.push_str(&format!(
+                    "* {} FETCH (UID {} BODY[] {{{}}}\r\n{})\r\n{} OK FETCH completed\r\n",
+                    seq + 1,
+                    uid,
+                    message.raw.len(),
+                    message.raw,
+                    tag
+                ));
+            } else if upper.starts_with("UID STORE") {
+                let uid: u32 = command.split_whitespace().nth(2).unwrap().parse().unwrap();
+                let mut mailbox = mailbox.lock().unwrap();
+                let (seq, message) = mailbox.iter_mut().enumerate().find(|(_, m)| m.uid == uid).unwrap();
+                message.seen = true;
+                reply.push_str(&format!(
+                    "* {} FETCH (UID {} FLAGS (\\Seen))\r\n{} OK STORE completed\r\n",
+                    seq + 1,
+                    uid,
+                    tag
+                ));
+            } else if upper.starts_with("IDLE") {
+                write.write_all(b"+ idling\r\n").await.unwrap();
+                tokio::select! {
+                    Some(raw) = incoming.recv() => {
+                        let exists = {
+                            let mut mailbox = mailbox.lock().unwrap();
+                            let uid = mailbox.len() as u32 + 1;
+                            mailbox.push(StoredMessage { uid, raw, seen: false });
+                            mailbox.len()
+                        };
+                        write.write_all(format!("* {} EXISTS\r\n", exists).as_bytes()).await.unwrap();
+                        lines.next_line().await.unwrap();
+                    }
+                    _ = lines.next_line() => {}
+                }
+                reply.push_str(&format!("{} OK IDLE terminated\r\n", tag));
+            } else if upper.starts_with("LOGOUT") {
+                reply.push_str(&format!("* BYE\r\n{} OK LOGOUT completed\r\n", tag));
+            } else {
+                reply.push_str(&format!("{} BAD unsupported\r\n", tag));
+            }
+
+            write.write_all(reply.as_bytes()).await.unwrap();
+        }
+    }
+
+    struct RecordingHandler {
+        handled: mpsc::UnboundedSender<String>,
+    }
+
+    #[async_trait::async_trait]
+    impl MessageHandler for RecordingHandler {
+        async fn handle(&self, message: &InboundEmail) -> anyhow::Result<()> {
+            self.handled.send(message.subject.clone()).unwrap();
+            if message.subject == "fail" {
+                anyhow::bail!("simulated failure");
+            }
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_marks_only_processed_messages_as_seen() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let mailbox: Mailbox = Arc::new(Mutex::new(vec![
+            StoredMessage { uid: 1, raw: raw_email("hello"), seen: false },
+            StoredMessage { uid: 2, raw: raw_email("fail"), seen: false },
+        ]));
+        let (incoming_tx, incoming_rx) = mpsc::channel(1);
+        tokio::spawn(serve(listener, mailbox.clone(), incoming_rx));
+
+        let receiver = ImapReceiver::new(ImapConfig {
+            host: "127.0.0.1".to_string(),
+            port,
+            username: "sentio".to_string(),
+            password: "secret".to_string(),
+            use_tls: false,
+            mailbox: "INBOX".to_string(),
+            move_to: None,
+            idle_timeout: 60,
+            poll_interval: 60,
+        });
+        let (handled_tx, mut handled_rx) = mpsc::unbounded_channel();
+        let handler = RecordingHandler { handled: handled_tx };
+        let task = tokio::spawn(async move { receiver.run(&handler).await });
+
+        let timeout = Duration::from_secs(5);
+        let first = tokio::time::timeout(timeout, handled_rx.recv()).await.unwrap().unwrap();
+        let second = tokio::time::timeout(timeout, handled_rx.recv()).await.unwrap().unwrap();
+        assert_eq!((first.as_str(), second.as_str()), ("hello", "fail"));
+
+        // 新邮件通过 IDLE 推送
+        incoming_tx.send(raw_email("later")).await.unwrap();
+        let third = tokio::time::timeout(timeout, handled_rx.recv()).await.unwrap().unwrap();
+        assert_eq!(third, "later");
+
+        tokio::time::timeout(timeout, async {
+            while !mailbox.lock().unwrap()[2].seen {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .unwrap();
+        task.abort();
+
+        let mailbox = mailbox.lock().unwrap();
+        assert!(mailbox[0].seen);
+        assert!(!mailbox[1].seen);
+        assert!(mailbox[2].seen);
+    }
+}
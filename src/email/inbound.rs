@@ -0,0 +1,191 @@
+use chrono::{DateTime, Utc};
+
+use super::client::reply_subject;
+use super::{EmailAddress, EmailMessage};
+
+/// 解析后的入站邮件，保留线程头、全部头部、正文的各个版本以及附件
+#[allow(dead_code)]
+#[derive(Debug, Clone)]
+pub struct InboundEmail {
+    /// Message-ID（不含尖括号）
+    pub message_id: Option<String>,
+    pub in_reply_to: Option<String>,
+    pub references: Vec<String>,
+    pub date: Option<DateTime<Utc>>,
+    pub from: EmailAddress,
+    pub to: Vec<EmailAddress>,
+    pub cc: Vec<EmailAddress>,
+    pub reply_to: Vec<EmailAddress>,
+    pub subject: String,
+    /// 按出现顺序保存的全部头部，值已做 RFC 2047 解码
+    pub headers: Vec<(String, String)>,
+    pub text_body: Option<String>,
+    pub html_body: Option<String>,
+    pub attachments: Vec<Attachment>,
+    /// 原始邮件字节，DKIM 验证需要未经解码的头部和正文
+    pub raw: Vec<u8>,
+}
+
+#[allow(dead_code)]
+#[derive(Debug, Clone)]
+pub struct Attachment {
+    pub filename: Option<String>,
+    pub content_type: String,
+    pub content_id: Option<String>,
+    pub inline: bool,
+    pub data: Vec<u8>,
+}
+
+impl InboundEmail {
+    /// 按名称（不区分大小写）取第一个头部的值
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// 纯文本正文；只有 HTML 版本时去掉标签后返回
+    pub fn body(&self) -> String {
+        match (&self.text_body, &self.html_body) {
+            (Some(text), _) => text.clone(),
+            (None, Some(html)) => html_to_text(html),
+            (None, None) => String::new(),
+        }
+    }
+
+    /// 线程的根 Message-ID，作为会话 ID 把同一线程的往来邮件归为一组
+    pub fn thread_id(&self) -> Option<&str> {
+        self.references
+            .first()
+            .or(self.in_reply_to.as_ref())
+            .or(self.message_id.as_ref())
+            .map(String::as_str)
+    }
+
+    /// 回复时应发往的地址：优先 Reply-To，其次 From
+    pub fn reply_address(&self) -> &EmailAddress {
+        self.reply_to.first().unwrap_or(&self.from)
+    }
+
+    /// 构造对这封邮件的回复，带上 In-Reply-To 与 References 以便邮件客户端归入同一线程
+    pub fn reply(&self, from: EmailAddress, body: String) -> EmailMessage {
+        // RFC 5322 §3.6.4：References 为原邮件的 References（没有时用 In-Reply-To）加上原邮件的 Message-ID
+        let mut references = if self.references.is_empty() {
+            self.in_reply_to.iter().cloned().collect()
+        } else {
+            self.references.clone()
+        };
+        references.extend(self.message_id.iter().cloned());
+
+        EmailMessage {
+            from,
+            to: vec![self.reply_address().clone()],
+            subject: reply_subject(&self.subject),
+            body,
+            is_html: false,
+            in_reply_to: self.message_id.clone(),
+            references,
+        }
+    }
+
+    /// 带署名行的原文引用，每行加 `> ` 前缀
+    pub fn quoted_body(&self) -> String {
+        let sender = match &self.from.name {
+            Some(name) => format!("{} <{}>", name, self.from.email),
+            None => self.from.email.clone(),
+        };
+        let attribution = match self.date {
+            Some(date) => format!("在 {}，{} 写道：", date.format("%Y-%m-%d %H:%M UTC"), sender),
+            None => format!("{} 写道：", sender),
+        };
+
+        let quoted: Vec<String> = self
+            .body()
+            .trim_end()
+            .lines()
+            .map(|line| if line.is_empty() { ">".to_string() } else { format!("> {}", line) })
+            .collect();
+
+        format!("{}\n{}", attribution, quoted.join("\n"))
+    }
+}
+
+/// 粗略地把 HTML 转成纯文本：去掉标签、脚本和样式，块级元素换行，解码常见实体
+fn html_to_text(html: &str) -> String {
+    let mut text = String::with_capacity(html.len());
+    let mut rest = html;
+
+    while let Some(start) = rest.find('<') {
+        text.push_str(&rest[..start]);
+        let after = &rest[start..];
+        let Some(end) = after.find('>') else {
+            rest = "";
+            break;
+        };
+
+        let tag = after[1..end].trim().to_ascii_lowercase();
+        let name = tag.trim_start_matches('/').split(|c: char| c.is_whitespace() || c == '/').next().unwrap_or("");
+        rest = &after[end + 1..];
+
+        if !tag.starts_with('/') && (name == "script" || name == "style") {
+            let closing = format!("</{}", name);
+            rest = match rest.to_ascii_lowercase().find(&closing) {
+                Some(pos) => rest[pos..].split_once('>').map_or("", |(_, r)| r),
+                None => "",
+            };
+            continue;
+        }
+
+        if matches!(name, "br" | "p" | "div" | "li" | "tr" | "h1" | "h2" | "h3" | "h4" | "h5" | "h6" | "blockquote") {
+            text.push('\n');
+        }
+    }
+    text.push_str(rest);
+
+    let text = text
+        .replace("&nbsp;", " ")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&amp;", "&");
+
+    let lines: Vec<&str> = text.lines().map(str::trim_end).collect();
+    lines.join("\n").trim().to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::email::parser::parse_message;
+
+    #[test]
+    fn test_reply_threads_and_quotes_original() {
+        let raw = concat!(
+            "From: =?UTF-8?B?5byg5LiJ?= <owner@qq.com>\r\n",
+            "To: sentio@example.com\r\n",
+            "Subject: Re: 项目进展\r\n",
+            "Date: Fri, 11 Jul 2025 11:44:20 +0800\r\n",
+            "Message-ID: <b@qq.com>\r\n",
+            "In-Reply-To: <a@example.com>\r\n",
+            "\r\n",
+            "最新进展如何？\r\n",
+            "\r\n",
+            "谢谢\r\n",
+        );
+        let original = parse_message(raw.as_bytes()).unwrap();
+
+        let reply = original.reply(EmailAddress::new("sentio@example.com"), "一切顺利".to_string());
+        assert_eq!(reply.to[0].email, "owner@qq.com");
+        assert_eq!(reply.subject, "Re: 项目进展");
+        assert_eq!(reply.in_reply_to.as_deref(), Some("b@qq.com"));
+        assert_eq!(reply.references, vec!["a@example.com", "b@qq.com"]);
+        assert_eq!(original.thread_id(), Some("a@example.com"));
+
+        assert_eq!(
+            original.quoted_body(),
+            "在 2025-07-11 03:44 UTC，张三 <owner@qq.com> 写道：\n> 最新进展如何？\n>\n> 谢谢"
+        );
+    }
+}
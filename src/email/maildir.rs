@@ -0,0 +1,167 @@
+use async_trait::async_trait;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tokio::fs;
+use tracing::{error, info, warn};
+
+use super::client::{EmailError, EmailResult};
+use super::parser::parse_message;
+use super::receiver::{MailReceiver, MessageHandler};
+use crate::config::MaildirConfig;
+
+/// 监听本地 Maildir（fetchmail / getmail 投递）的 new/ 目录
+pub struct MaildirReceiver {
+    config: MaildirConfig,
+}
+
+impl MaildirReceiver {
+    pub fn new(config: MaildirConfig) -> Self {
+        Self { config }
+    }
+
+    fn quarantine_dir(&self) -> PathBuf {
+        self.config
+            .quarantine
+            .clone()
+            .unwrap_or_else(|| self.config.path.join(".Quarantine"))
+    }
+
+    async fn ensure_layout(&self) -> EmailResult<()> {
+        for root in [self.config.path.clone(), self.quarantine_dir()] {
+            for sub in ["new", "cur", "tmp"] {
+                fs::create_dir_all(root.join(sub))
+                    .await
+                    .map_err(|e| EmailError::Receive(format!("Failed to create {}: {}", root.join(sub).display(), e)))?;
+            }
+        }
+        Ok(())
+    }
+
+    async fn process_new(&self, handler: &dyn MessageHandler) -> EmailResult<usize> {
+        let new_dir = self.config.path.join("new");
+        let mut entries = fs::read_dir(&new_dir)
+            .await
+            .map_err(|e| EmailError::Receive(format!("Failed to read {}: {}", new_dir.display(), e)))?;
+
+        let mut names = Vec::new();
+        while let Some(entry) = entries.next_entry().await.map_err(|e| EmailError::Receive(e.to_string()))? {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            if !name.starts_with('.') {
+                names.push(name);
+            }
+        }
+        // Maildir 文件名以投递时间戳开头，按名称排序即近似按到达顺序处理
+        names.sort();
+
+        let mut processed = 0;
+        for name in names {
+            let path = new_dir.join(&name);
+            let raw = fs::read(&path)
+                .await
+                .map_err(|e| EmailError::Receive(format!("Failed to read {}: {}", path.display(), e)))?;
+
+            let result = match parse_message(&raw) {
+                Ok(message) => handler.handle(&message).await,
+                Err(e) => Err(e.into()),
+            };
+
+            match result {
+                Ok(()) => {
+                    let target = self.config.path.join("cur").join(with_flag(&name, 'S'));
+                    move_file(&path, &target).await?;
+                    processed += 1;
+                }
+                Err(e) => {
+                    let target = self.quarantine_dir().join("new").join(&name);
+                    error!(file = %name, error = %e, "Failed to process email, moved to {}", target.display());
+                    move_file(&path, &target).await?;
+                }
+            }
+        }
+
+        Ok(processed)
+    }
+}
+
+#[async_trait]
+impl MailReceiver for MaildirReceiver {
+    async fn run(&self, handler: &dyn MessageHandler) -> EmailResult<()> {
+        self.ensure_layout().await?;
+        info!(path = %self.config.path.display(), "Watching Maildir for new emails");
+
+        loop {
+            if let Err(e) = self.process_new(handler).await {
+                warn!(error = %e, "Failed to scan Maildir");
+            }
+            tokio::time::sleep(Duration::from_secs(self.config.poll_interval)).await;
+        }
+    }
+}
+
+async fn move_file(from: &Path, to: &Path) -> EmailResult<()> {
+    fs::rename(from, to)
+        .await
+        .map_err(|e| EmailError::Receive(format!("Failed to move {} to {}: {}", from.display(), to.display(), e)))
+}
+
+/// 按 Maildir 规范在文件名的 `:2,` 信息段中加入标记，标记按 ASCII 排序
+fn with_flag(name: &str, flag: char) -> String {
+    let (base, flags) = name.split_once(":2,").unwrap_or((name, ""));
+    let mut flags: Vec<char> = flags.chars().chain(std::iter::once(flag)).collect();
+    flags.sort_unstable();
+    flags.dedup();
+    format!("{}:2,{}", base, flags.into_iter().collect::<String>())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::email::InboundEmail;
+
+    struct FailOnSubject;
+
+    #[async_trait]
+    impl MessageHandler for FailOnSubject {
+        async fn handle(&self, message: &InboundEmail) -> anyhow::Result<()> {
+            if message.subject == "fail" {
+                anyhow::bail!("simulated failure");
+            }
+            Ok(())
+        }
+    }
+
+    fn raw_email(subject: &str) -> String {
+        format!("From: owner@example.com\r\nTo: sentio@example.com\r\nSubject: {}\r\n\r\nbody\r\n", subject)
+    }
+
+    #[tokio::test]
+    async fn test_processed_to_cur_and_failures_quarantined() {
+        let dir = tempfile::tempdir().unwrap();
+        let receiver = MaildirReceiver::new(MaildirConfig {
+            path: dir.path().to_path_buf(),
+            quarantine: None,
+            poll_interval: 1,
+        });
+        receiver.ensure_layout().await.unwrap();
+
+        let new_dir = dir.path().join("new");
+        std::fs::write(new_dir.join("1700000000.1.host"), raw_email("hello")).unwrap();
+        std::fs::write(new_dir.join("1700000001.2.host"), raw_email("fail")).unwrap();
+        std::fs::write(new_dir.join("1700000002.3.host"), "not an email").unwrap();
+
+        let processed = receiver.process_new(&FailOnSubject).await.unwrap();
+        assert_eq!(processed, 1);
+
+        assert!(dir.path().join("cur/1700000000.1.host:2,S").exists());
+        assert!(dir.path().join(".Quarantine/new/1700000001.2.host").exists());
+        assert!(dir.path().join(".Quarantine/new/1700000002.3.host").exists());
+        assert_eq!(std::fs::read_dir(&new_dir).unwrap().count(), 0);
+    }
+
+    #[test]
+    fn test_with_flag_keeps_flags_sorted() {
+        assert_eq!(with_flag("123.host", 'S'), "123.host:2,S");
+        assert_eq!(with_flag("123.host:2,RF", 'S'), "123.host:2,FRS");
+        assert_eq!(with_flag("123.host:2,S", 'S'), "123.host:2,S");
+    }
+}
@@ -1,6 +1,19 @@
+pub mod autoreply;
 pub mod client;
+pub mod imap;
+pub mod inbound;
+pub mod maildir;
+pub mod parser;
+pub mod queue;
+pub mod quote;
+pub mod receiver;
 
 pub use client::{EmailClient, SmtpClient, EmailMessage};
+pub use imap::ImapReceiver;
+pub use inbound::InboundEmail;
+pub use maildir::MaildirReceiver;
+pub use queue::OutboundQueue;
+pub use receiver::{create_receiver, MessageHandler};
 
 use serde::{Deserialize, Serialize};
 
@@ -0,0 +1,266 @@
+use chrono::{DateTime, Utc};
+use mailparse::{DispositionType, MailAddr, MailHeaderMap, ParsedMail};
+
+use super::client::{EmailError, EmailResult};
+use super::inbound::{Attachment, InboundEmail};
+use super::EmailAddress;
+
+/// 将原始 RFC 5322 / MIME 邮件解析为 `InboundEmail`
+///
+/// 支持 multipart、base64 / quoted-printable 传输编码、RFC 2047 编码的头部，
+/// 以及 GBK、GB2312 等非 UTF-8 字符集。
+pub fn parse_message(raw: &[u8]) -> EmailResult<InboundEmail> {
+    let parsed = mailparse::parse_mail(raw).map_err(|e| EmailError::Parse(e.to_string()))?;
+
+    let from = parse_addresses(&parsed, "From")?
+        .into_iter()
+        .next()
+        .ok_or_else(|| EmailError::Parse("Missing From header".to_string()))?;
+
+    let headers = parsed
+        .headers
+        .iter()
+        .map(|header| (header.get_key(), header.get_value()))
+        .collect();
+
+    let mut email = InboundEmail {
+        message_id: parsed
+            .headers
+            .get_first_value("Message-ID")
+            .and_then(|value| parse_message_ids(&value).into_iter().next()),
+        in_reply_to: parsed
+            .headers
+            .get_first_value("In-Reply-To")
+            .and_then(|value| parse_message_ids(&value).into_iter().next()),
+        references: parsed
+            .headers
+            .get_first_value("References")
+            .map(|value| parse_message_ids(&value))
+            .unwrap_or_default(),
+        date: parsed.headers.get_first_value("Date").and_then(|value| parse_date(&value)),
+        from,
+        to: parse_addresses(&parsed, "To")?,
+        cc: parse_addresses(&parsed, "Cc")?,
+        reply_to: parse_addresses(&parsed, "Reply-To")?,
+        subject: parsed.headers.get_first_value("Subject").unwrap_or_default(),
+        headers,
+        text_body: None,
+        html_body: None,
+        attachments: Vec::new(),
+        raw: raw.to_vec(),
+    };
+
+    collect_parts(&parsed, &mut email)?;
+    Ok(email)
+}
+
+/// 遍历 MIME 树：第一个非附件的 text/plain 和 text/html 作为正文，其余叶子部分作为附件
+fn collect_parts(part: &ParsedMail, email: &mut InboundEmail) -> EmailResult<()> {
+    if part.ctype.mimetype.starts_with("multipart/") {
+        for sub in &part.subparts {
+            collect_parts(sub, email)?;
+        }
+        return Ok(());
+    }
+
+    let disposition = part.get_content_disposition();
+    let filename = disposition
+        .params
+        .get("filename")
+        .or_else(|| part.ctype.params.get("name"))
+        .cloned();
+    let is_attachment = disposition.disposition == DispositionType::Attachment || filename.is_some();
+    let mimetype = part.ctype.mimetype.to_ascii_lowercase();
+
+    if !is_attachment {
+        let slot = match mimetype.as_str() {
+            "text/plain" => Some(&mut email.text_body),
+            "text/html" => Some(&mut email.html_body),
+            _ => None,
+        };
+        if let Some(slot) = slot {
+            if slot.is_none() {
+                *slot = Some(decode_text(part)?);
+                return Ok(());
+            }
+        }
+    }
+
+    email.attachments.push(Attachment {
+        filename,
+        content_type: mimetype,
+        content_id: part
+            .headers
+            .get_first_value("Content-ID")
+            .and_then(|value| parse_message_ids(&value).into_iter().next()),
+        inline: disposition.disposition == DispositionType::Inline,
+        data: part.get_body_raw().map_err(|e| EmailError::Parse(e.to_string()))?,
+    });
+    Ok(())
+}
+
+/// 解码文本部分；未声明字符集（默认 us-ascii）但内容是合法 UTF-8 时按 UTF-8 处理
+fn decode_text(part: &ParsedMail) -> EmailResult<String> {
+    if part.ctype.charset.eq_ignore_ascii_case("us-ascii") {
+        let bytes = part.get_body_raw().map_err(|e| EmailError::Parse(e.to_string()))?;
+        if let Ok(text) = String::from_utf8(bytes) {
+            return Ok(text);
+        }
+    }
+    part.get_body().map_err(|e| EmailError::Parse(e.to_string()))
+}
+
+fn parse_addresses(parsed: &ParsedMail, header: &str) -> EmailResult<Vec<EmailAddress>> {
+    let Some(value) = parsed.headers.get_first_header(header) else {
+        return Ok(Vec::new());
+    };
+
+    let addrs = mailparse::addrparse_header(value).map_err(|e| EmailError::Parse(format!("Invalid {} header: {}", header, e)))?;
+
+    let mut result = Vec::new();
+    for addr in addrs.iter() {
+        match addr {
+            MailAddr::Single(info) => result.push(to_email_address(info)),
+            MailAddr::Group(group) => result.extend(group.addrs.iter().map(to_email_address)),
+        }
+    }
+    Ok(result)
+}
+
+fn to_email_address(info: &mailparse::SingleInfo) -> EmailAddress {
+    match &info.display_name {
+        Some(name) if !name.is_empty() => EmailAddress::with_name(&info.addr, name),
+        _ => EmailAddress::new(&info.addr),
+    }
+}
+
+/// 宽松地提取 `<...>` 形式的 Message-ID；没有尖括号时把整个值当作一个 ID
+fn parse_message_ids(value: &str) -> Vec<String> {
+    let mut ids = Vec::new();
+    let mut rest = value;
+    while let Some(start) = rest.find('<') {
+        let Some(len) = rest[start..].find('>') else {
+            break;
+        };
+        let id = rest[start + 1..start + len].trim();
+        if !id.is_empty() {
+            ids.push(id.to_string());
+        }
+        rest = &rest[start + len + 1..];
+    }
+
+    if ids.is_empty() && !value.trim().is_empty() {
+        ids.push(value.trim().to_string());
+    }
+    ids
+}
+
+fn parse_date(value: &str) -> Option<DateTime<Utc>> {
+    let timestamp = mailparse::dateparse(value).ok()?;
+    DateTime::from_timestamp(timestamp, 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_multipart_prefers_plain_text() {
+        let raw = concat!(
+            "From: =?UTF-8?B?5byg5LiJ?= <zhangsan@qq.com>\r\n",
+            "To: sentio@example.com\r\n",
+            "Subject: =?UTF-8?B?6aG555uu6L+b5bGV?=\r\n",
+            "MIME-Version: 1.0\r\n",
+            "Content-Type: multipart/alternative; boundary=\"b1\"\r\n",
+            "\r\n",
+            "--b1\r\n",
+            "Content-Type: text/plain; charset=utf-8\r\n",
+            "\r\n",
+            "hello\r\n",
+            "--b1\r\n",
+            "Content-Type: text/html; charset=utf-8\r\n",
+            "\r\n",
+            "<p>hello</p>\r\n",
+            "--b1--\r\n",
+        );
+
+        let message = parse_message(raw.as_bytes()).unwrap();
+        assert_eq!(message.from.email, "zhangsan@qq.com");
+        assert_eq!(message.from.name.as_deref(), Some("张三"));
+        assert_eq!(message.to[0].email, "sentio@example.com");
+        assert_eq!(message.subject, "项目进展");
+        assert_eq!(message.body().trim(), "hello");
+        assert_eq!(message.html_body.as_deref().map(str::trim), Some("<p>hello</p>"));
+    }
+
+    #[test]
+    fn test_parse_gbk_threading_headers_and_attachment() {
+        let mut raw = concat!(
+            "From: owner@qq.com\r\n",
+            "To: sentio@example.com\r\n",
+            "Cc: Team <team@example.com>, other@example.com\r\n",
+            "Reply-To: reply@qq.com\r\n",
+            "Subject: =?gb2312?B?xOO6ww==?=\r\n",
+            "Date: Fri, 11 Jul 2025 11:44:20 +0800\r\n",
+            "Message-ID: <abc@qq.com>\r\n",
+            "In-Reply-To: <prev@example.com>\r\n",
+            "References: <root@example.com>\r\n <prev@example.com>\r\n",
+            "MIME-Version: 1.0\r\n",
+            "Content-Type: multipart/mixed; boundary=\"outer\"\r\n",
+            "\r\n",
+            "--outer\r\n",
+            "Content-Type: text/plain; charset=GBK\r\n",
+            "Content-Transfer-Encoding: 8bit\r\n",
+            "\r\n",
+        )
+        .as_bytes()
+        .to_vec();
+        // "你好" 的 GBK 编码
+        raw.extend_from_slice(&[0xC4, 0xE3, 0xBA, 0xC3]);
+        raw.extend_from_slice(
+            concat!(
+                "\r\n--outer\r\n",
+                "Content-Type: application/pdf; name=\"report.pdf\"\r\n",
+                "Content-Disposition: attachment; filename=\"report.pdf\"\r\n",
+                "Content-Transfer-Encoding: base64\r\n",
+                "\r\n",
+                "JVBERi0=\r\n",
+                "--outer--\r\n",
+            )
+            .as_bytes(),
+        );
+
+        let message = parse_message(&raw).unwrap();
+        assert_eq!(message.subject, "你好");
+        assert_eq!(message.body().trim(), "你好");
+        assert_eq!(message.message_id.as_deref(), Some("abc@qq.com"));
+        assert_eq!(message.in_reply_to.as_deref(), Some("prev@example.com"));
+        assert_eq!(message.references, vec!["root@example.com", "prev@example.com"]);
+        assert_eq!(message.date.unwrap().to_rfc3339(), "2025-07-11T03:44:20+00:00");
+        assert_eq!(message.cc.len(), 2);
+        assert_eq!(message.reply_address().email, "reply@qq.com");
+        assert_eq!(message.header("content-type").map(|v| v.starts_with("multipart/mixed")), Some(true));
+
+        assert_eq!(message.attachments.len(), 1);
+        let attachment = &message.attachments[0];
+        assert_eq!(attachment.filename.as_deref(), Some("report.pdf"));
+        assert_eq!(attachment.content_type, "application/pdf");
+        assert_eq!(attachment.data, b"%PDF-");
+    }
+
+    #[test]
+    fn test_html_only_body_is_converted_to_text() {
+        let raw = concat!(
+            "From: owner@qq.com\r\n",
+            "Subject: html\r\n",
+            "Content-Type: text/html; charset=utf-8\r\n",
+            "Content-Transfer-Encoding: quoted-printable\r\n",
+            "\r\n",
+            "<style>p{color:red}</style><p>=E4=BD=A0=E5=A5=BD</p><p>a &amp; b</p>\r\n",
+        );
+
+        let message = parse_message(raw.as_bytes()).unwrap();
+        assert!(message.text_body.is_none());
+        assert_eq!(message.body(), "你好\n\na & b");
+    }
+}
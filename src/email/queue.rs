@@ -0,0 +1,362 @@
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tokio::fs;
+use tracing::{info, warn};
+
+use super::client::{EmailClient, EmailError, EmailMessage, EmailResult};
+use crate::config::QueueConfig;
+
+/// 队列中的一封待发送邮件
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QueuedMessage {
+    pub id: String,
+    pub message: EmailMessage,
+    pub created_at: DateTime<Utc>,
+    pub attempts: u32,
+    pub next_attempt_at: DateTime<Utc>,
+    pub last_error: Option<String>,
+}
+
+/// 持久化的发件队列
+///
+/// 每封邮件保存为 `pending/<id>.json`，由后台发送任务投递；临时失败按指数退避重试，
+/// 被服务器永久拒绝、超过重试次数或无法确认是否送达的邮件移到 `dead/`，可以查看后重新放回队列。
+/// 发送前先把邮件移到 `sending/`，发出后即使删除失败也不会再次发送；进程在发送中途退出时，
+/// 下次启动把留在 `sending/` 中的邮件移到 `dead/`，由人确认后再重发。
+/// 队列状态完全保存在磁盘上，重启后继续投递。投递时不加锁，同一目录只能由一个实例投递，
+/// 否则每封邮件都会被发送多次。
+#[derive(Debug, Clone)]
+pub struct OutboundQueue {
+    pending: PathBuf,
+    sending: PathBuf,
+    dead: PathBuf,
+    config: QueueConfig,
+}
+
+fn io_error(path: &Path, e: impl std::fmt::Display) -> EmailError {
+    EmailError::Queue(format!("{}: {}", path.display(), e))
+}
+
+impl OutboundQueue {
+    pub fn open(config: QueueConfig) -> EmailResult<Self> {
+        let pending = config.dir.join("pending");
+        let sending = config.dir.join("sending");
+        let dead = config.dir.join("dead");
+        for dir in [&pending, &sending, &dead] {
+            std::fs::create_dir_all(dir).map_err(|e| io_error(dir, e))?;
+        }
+        Ok(Self { pending, sending, dead, config })
+    }
+
+    pub async fn enqueue(&self, message: EmailMessage) -> EmailResult<String> {
+        let now = Utc::now();
+        let queued = QueuedMessage {
+            id: format!("{}-{}", now.format("%Y%m%d%H%M%S"), uuid::Uuid::new_v4().simple()),
+            message,
+            created_at: now,
+            attempts: 0,
+            next_attempt_at: now,
+            last_error: None,
+        };
+        write_entry(&self.pending, &queued).await?;
+        Ok(queued.id)
+    }
+
+    /// 待发送的邮件，按入队时间排序
+    pub async fn pending(&self) -> EmailResult<Vec<QueuedMessage>> {
+        read_entries(&self.pending).await
+    }
+
+    /// 放弃发送的邮件
+    pub async fn dead_letters(&self) -> EmailResult<Vec<QueuedMessage>> {
+        read_entries(&self.dead).await
+    }
+
+    /// 把死信重新放回队列，重置尝试次数并立即发送
+    pub async fn replay(&self, id: &str) -> EmailResult<()> {
+        let path = entry_path(&self.dead, id);
+        let content = fs::read_to_string(&path).await.map_err(|e| io_error(&path, e))?;
+        let mut queued: QueuedMessage = serde_json::from_str(&content).map_err(|e| io_error(&path, e))?;
+        queued.attempts = 0;
+        queued.next_attempt_at = Utc::now();
+        queued.last_error = None;
+
+        write_entry(&self.pending, &queued).await?;
+        fs::remove_file(&path).await.map_err(|e| io_error(&path, e))
+    }
+
+    /// 第 `attempts` 次失败后的等待时间
+    fn backoff(&self, attempts: u32) -> Duration {
+        let exponent = attempts.saturating_sub(1).min(31);
+        let seconds = self.config.initial_backoff.saturating_mul(1 << exponent);
+        Duration::from_secs(seconds.min(self.config.max_backoff))
+    }
+
+    /// 投递所有到期的邮件，返回成功发送的数量；单封邮件的队列文件读写失败不影响其他邮件
+    pub async fn deliver_due(&self, client: &dyn EmailClient) -> EmailResult<usize> {
+        let now = Utc::now();
+        let mut delivered = 0;
+
+        for queued in self.pending().await? {
+            if queued.next_attempt_at > now {
+                continue;
+            }
+            let id = queued.id.clone();
+            match self.deliver(queued, client).await {
+                Ok(true) => delivered += 1,
+                Ok(false) => {}
+                Err(e) => warn!(id = %id, error = %e, "Failed to update outbound queue entry"),
+            }
+        }
+        Ok(delivered)
+    }
+
+    /// 发送一封邮件并更新它在队列中的位置，返回是否已发出
+    async fn deliver(&self, mut queued: QueuedMessage, client: &dyn EmailClient) -> EmailResult<bool> {
+        let pending = entry_path(&self.pending, &queued.id);
+        let path = entry_path(&self.sending, &queued.id);
+        fs::rename(&pending, &path).await.map_err(|e| io_error(&pending, e))?;
+
+        queued.attempts += 1;
+        match client.send(queued.message.clone()).await {
+            Ok(_) => {
+                info!(id = %queued.id, attempts = queued.attempts, "Queued email delivered");
+                // 邮件已经发出，删除失败时留在 sending/ 中，下次启动移入死信，不会重发
+                if let Err(e) = fs::remove_file(&path).await {
+                    warn!(id = %queued.id, error = %io_error(&path, e), "Failed to remove delivered email from the queue");
+                }
+                Ok(true)
+            }
+            Err(e) if e.is_permanent() || e.may_have_been_delivered() || queued.attempts >= self.config.max_attempts => {
+                if e.may_have_been_delivered() {
+                    warn!(id = %queued.id, error = %e, "Queued email may already have been delivered, moving to dead letters instead of resending");
+                } else {
+                    warn!(id = %queued.id, attempts = queued.attempts, error = %e, "Giving up on queued email, moving to dead letters");
+                }
+                queued.last_error = Some(e.to_string());
+                write_entry(&self.dead, &queued).await?;
+                fs::remove_file(&path).await.map_err(|e| io_error(&path, e))?;
+                Ok(false)
+            }
+            Err(e) => {
+                let backoff = self.backoff(queued.attempts);
+                warn!(id = %queued.id, attempts = queued.attempts, error = %e, "Queued email failed, retrying in {:?}", backoff);
+                queued.last_error = Some(e.to_string());
+                queued.next_attempt_at = Utc::now() + chrono::Duration::from_std(backoff).unwrap_or_default();
+                write_entry(&self.pending, &queued).await?;
+                fs::remove_file(&path).await.map_err(|e| io_error(&path, e))?;
+                Ok(false)
+            }
+        }
+    }
+
+    /// 上次运行在发送中途退出时留下的邮件可能已经发出，移到死信而不是重发
+    async fn recover_interrupted(&self) -> EmailResult<()> {
+        for mut queued in read_entries(&self.sending).await? {
+            warn!(id = %queued.id, "Email was being sent when Sentio stopped, moving to dead letters instead of resending");
+            queued.last_error = Some("Interrupted while sending; delivery unconfirmed".to_string());
+            write_entry(&self.dead, &queued).await?;
+            let path = entry_path(&self.sending, &queued.id);
+            fs::remove_file(&path).await.map_err(|e| io_error(&path, e))?;
+        }
+        Ok(())
+    }
+
+    /// 后台发送任务，按配置的间隔检查队列；同一队列目录只能运行一个
+    pub async fn run(self, client: Box<dyn EmailClient>) {
+        if let Err(e) = self.recover_interrupted().await {
+            warn!(error = %e, "Failed to recover interrupted outbound queue entries");
+        }
+        let mut ticker = tokio::time::interval(Duration::from_secs(self.config.poll_interval.max(1)));
+        loop {
+            ticker.tick().await;
+            if let Err(e) = self.deliver_due(client.as_ref()).await {
+                warn!(error = %e, "Failed to process outbound queue");
+            }
+        }
+    }
+}
+
+/// 工作流通过队列发送邮件：`send` 只负责入队，实际投递由后台任务完成
+#[async_trait]
+impl EmailClient for OutboundQueue {
+    async fn send(&self, message: EmailMessage) -> EmailResult<String> {
+        let id = self.enqueue(message).await?;
+        Ok(format!("Queued as {}", id))
+    }
+}
+
+fn entry_path(dir: &Path, id: &str) -> PathBuf {
+    dir.join(format!("{}.json", id))
+}
+
+/// 先写临时文件再重命名，避免进程中断时留下半个文件
+async fn write_entry(dir: &Path, queued: &QueuedMessage) -> EmailResult<()> {
+    let path = entry_path(dir, &queued.id);
+    let tmp = dir.join(format!(".{}.tmp", queued.id));
+    let content = serde_json::to_string_pretty(queued).map_err(|e| io_error(&path, e))?;
+    fs::write(&tmp, content).await.map_err(|e| io_error(&tmp, e))?;
+    fs::rename(&tmp, &path).await.map_err(|e| io_error(&path, e))
+}
+
+async fn read_entries(dir: &Path) -> EmailResult<Vec<QueuedMessage>> {
+    let mut entries = Vec::new();
+    let mut reader = fs::read_dir(dir).await.map_err(|e| io_error(dir, e))?;
+    while let Some(entry) = reader.next_entry().await.map_err(|e| io_error(dir, e))? {
+        let path = entry.path();
+        if path.extension().and_then(|ext| ext.to_str()) != Some("json") {
+            continue;
+        }
+        let content = fs::read_to_string(&path).await.map_err(|e| io_error(&path, e))?;
+        match serde_json::from_str(&content) {
+            Ok(queued) => entries.push(queued),
+            Err(e) => warn!("Skipping unreadable queue entry {}: {}", path.display(), e),
+        }
+    }
+    entries.sort_by(|a: &QueuedMessage, b| a.created_at.cmp(&b.created_at).then_with(|| a.id.cmp(&b.id)));
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    /// 按顺序返回预设结果的发送端
+    struct ScriptedClient {
+        results: Mutex<Vec<EmailResult<()>>>,
+        sent: Mutex<Vec<String>>,
+    }
+
+    impl ScriptedClient {
+        fn new(results: Vec<EmailResult<()>>) -> Self {
+            Self {
+                results: Mutex::new(results),
+                sent: Mutex::new(Vec::new()),
+            }
+        }
+    }
+
+    #[async_trait]
+    impl EmailClient for ScriptedClient {
+        async fn send(&self, message: EmailMessage) -> EmailResult<String> {
+            self.results.lock().unwrap().remove(0)?;
+            self.sent.lock().unwrap().push(message.subject);
+            Ok("sent".to_string())
+        }
+    }
+
+    fn message(subject: &str) -> EmailMessage {
+        crate::email::client::tests::message("owner@qq.com", subject, "body")
+    }
+
+    fn queue(dir: &Path, initial_backoff: u64) -> OutboundQueue {
+        OutboundQueue::open(QueueConfig {
+            dir: dir.to_path_buf(),
+            max_attempts: 3,
+            initial_backoff,
+            max_backoff: 3600,
+            poll_interval: 1,
+        })
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_transient_failures_back_off_then_dead_letter() {
+        let dir = tempfile::tempdir().unwrap();
+        let queue = queue(dir.path(), 0);
+        queue.send(message("retry")).await.unwrap();
+
+        let client = ScriptedClient::new(vec![
+            Err(EmailError::Send("451 try again".to_string())),
+            Err(EmailError::Connection("reset".to_string())),
+            Err(EmailError::Send("421 busy".to_string())),
+            Ok(()),
+        ]);
+        for attempts in 1..=2 {
+            assert_eq!(queue.deliver_due(&client).await.unwrap(), 0);
+            assert_eq!(queue.pending().await.unwrap()[0].attempts, attempts);
+        }
+
+        // 第三次仍然失败，达到上限后移入死信
+        queue.deliver_due(&client).await.unwrap();
+        assert!(queue.pending().await.unwrap().is_empty());
+        let dead = queue.dead_letters().await.unwrap();
+        assert_eq!(dead[0].last_error.as_deref(), Some("Send error: 421 busy"));
+
+        queue.replay(&dead[0].id).await.unwrap();
+        assert!(queue.dead_letters().await.unwrap().is_empty());
+        assert_eq!(queue.deliver_due(&client).await.unwrap(), 1);
+        assert_eq!(*client.sent.lock().unwrap(), vec!["retry"]);
+    }
+
+    #[tokio::test]
+    async fn test_permanent_failure_skips_retries_and_backoff_delays() {
+        let dir = tempfile::tempdir().unwrap();
+        let queue = queue(dir.path(), 30);
+        queue.enqueue(message("rejected")).await.unwrap();
+        queue.enqueue(message("timed out")).await.unwrap();
+        queue.enqueue(message("later")).await.unwrap();
+
+        let client = ScriptedClient::new(vec![
+            Err(EmailError::Rejected("550 no such user".to_string())),
+            Err(EmailError::Unconfirmed("SMTP session timed out".to_string())),
+            Err(EmailError::Send("451 try again".to_string())),
+        ]);
+        queue.deliver_due(&client).await.unwrap();
+
+        // 被拒绝和可能已送达的邮件都不重试
+        let dead: Vec<String> = queue.dead_letters().await.unwrap().into_iter().map(|queued| queued.message.subject).collect();
+        assert_eq!(dead, ["rejected", "timed out"]);
+        let pending = queue.pending().await.unwrap();
+        assert_eq!(pending[0].message.subject, "later");
+        assert!(pending[0].next_attempt_at > Utc::now() + chrono::Duration::seconds(25));
+
+        // 还没到重试时间，不会再次发送
+        assert_eq!(queue.deliver_due(&client).await.unwrap(), 0);
+        assert_eq!(queue.backoff(5), Duration::from_secs(480));
+        assert_eq!(queue.backoff(20), Duration::from_secs(3600));
+    }
+
+    /// 发送时删掉队列中正在发送的文件，模拟发出后删除失败
+    struct RemovingClient {
+        sending: PathBuf,
+        sent: Mutex<Vec<String>>,
+    }
+
+    #[async_trait]
+    impl EmailClient for RemovingClient {
+        async fn send(&self, message: EmailMessage) -> EmailResult<String> {
+            for entry in std::fs::read_dir(&self.sending).unwrap() {
+                std::fs::remove_file(entry.unwrap().path()).unwrap();
+            }
+            self.sent.lock().unwrap().push(message.subject);
+            Ok("sent".to_string())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_sent_email_is_never_resent() {
+        let dir = tempfile::tempdir().unwrap();
+        let queue = queue(dir.path(), 0);
+        queue.enqueue(message("a")).await.unwrap();
+        queue.enqueue(message("b")).await.unwrap();
+
+        // 删除失败不会中断投递，也不会留在 pending/ 中重发
+        let client = RemovingClient { sending: queue.sending.clone(), sent: Mutex::new(Vec::new()) };
+        assert_eq!(queue.deliver_due(&client).await.unwrap(), 2);
+        assert_eq!(queue.deliver_due(&client).await.unwrap(), 0);
+        assert_eq!(*client.sent.lock().unwrap(), vec!["a", "b"]);
+
+        // 上次在发送中途退出：移到死信，不自动重发
+        let id = queue.enqueue(message("interrupted")).await.unwrap();
+        std::fs::rename(entry_path(&queue.pending, &id), entry_path(&queue.sending, &id)).unwrap();
+        queue.recover_interrupted().await.unwrap();
+        assert!(queue.pending().await.unwrap().is_empty());
+        let dead = queue.dead_letters().await.unwrap();
+        assert_eq!((dead[0].id.as_str(), dead[0].message.subject.as_str()), (id.as_str(), "interrupted"));
+    }
+}
@@ -0,0 +1,135 @@
+use lazy_static::lazy_static;
+use regex::Regex;
+
+lazy_static! {
+    /// Gmail / Apple Mail 等客户端在引用前加的说明行，例如 `On Mon, 1 Jan 2024, Alice <a@example.com> wrote:`
+    static ref ATTRIBUTION: Regex = Regex::new(r"(?i)^\s*On\b.{0,200}\bwrote:\s*$").unwrap();
+    /// 中文客户端的说明行，例如 `在 2024年1月1日 周一 10:00，Alice <a@example.com> 写道：`
+    static ref ATTRIBUTION_ZH: Regex = Regex::new(r"^\s*在.{0,200}写道[:：]\s*$").unwrap();
+    /// Outlook / Foxmail 的分隔行，例如 `-----Original Message-----`、`------------------ 原始邮件 ------------------`
+    static ref ORIGINAL_MESSAGE: Regex = Regex::new(r"(?i)^\s*-{2,}\s*(original message|原始邮件)\s*-{2,}\s*$").unwrap();
+    /// Outlook 引用头的第一行
+    static ref HEADER_FROM: Regex = Regex::new(r"^\s*\*?(From|发件人)\*?\s*[:：]").unwrap();
+    /// 紧跟在 `From:` 之后的发送时间行
+    static ref HEADER_SENT: Regex = Regex::new(r"^\s*\*?(Sent|Date|发送时间|日期|时间)\*?\s*[:：]").unwrap();
+    /// Outlook 网页版在引用头上方加的横线
+    static ref RULE: Regex = Regex::new(r"^\s*_{10,}\s*$").unwrap();
+    /// 手机客户端自动追加的落款，之后的内容按签名处理
+    static ref SIGNOFF: Regex = Regex::new(r"(?i)^\s*(sent from my\b|get outlook for\b|发自我的)").unwrap();
+}
+
+/// `From:` 之后这么多行内出现发送时间才算 Outlook 引用头
+const HEADER_LOOKAHEAD: usize = 4;
+
+/// 邮件正文拆成的几部分
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct BodyParts {
+    /// 发件人这次写的内容
+    pub text: String,
+    /// 引用的历史邮件，包括引用说明行
+    pub quoted: String,
+}
+
+/// `lines[index]` 开始的是否是 Outlook 引用头（`From:` 后面几行内有 `Sent:` / `Date:`）
+fn is_outlook_header(lines: &[&str], index: usize) -> bool {
+    HEADER_FROM.is_match(lines[index])
+        && lines[index + 1..].iter().take(HEADER_LOOKAHEAD).any(|line| HEADER_SENT.is_match(line))
+}
+
+/// 从 `index` 开始是否进入引用的历史邮件，之后的内容全部视为引用
+fn starts_quote(lines: &[&str], index: usize) -> bool {
+    let line = lines[index];
+    if ATTRIBUTION.is_match(line) || ATTRIBUTION_ZH.is_match(line) || ORIGINAL_MESSAGE.is_match(line) {
+        return true;
+    }
+    if RULE.is_match(line) {
+        let next = (index + 1..lines.len()).find(|&next| !lines[next].trim().is_empty());
+        return next.is_some_and(|next| is_outlook_header(lines, next));
+    }
+    is_outlook_header(lines, index)
+}
+
+/// RFC 3676 的签名分隔线 `-- `，空格后面可以再有空白；没有空格的 `--` 常见于命令输出和 diff，不算
+fn is_signature_delimiter(line: &str) -> bool {
+    line.strip_prefix("--").is_some_and(|rest| rest.starts_with([' ', '\t']) && rest.trim().is_empty())
+}
+
+/// 把正文拆成新写的内容和引用的历史邮件，去掉签名
+///
+/// 引用说明行（`On ... wrote:`、`在 ... 写道：`）、Outlook 的分隔行和引用头之后的内容都视为引用；
+/// 其他位置以 `>` 开头的行单独算作引用，夹在引用之间的回复仍属于新内容。签名从引用之前最后一个
+/// `-- ` 分隔线或 `Sent from my iPhone` 这类落款开始，到引用开始为止。
+pub fn split_body(body: &str) -> BodyParts {
+    let lines: Vec<&str> = body.lines().collect();
+    let end = (0..lines.len()).find(|&index| starts_quote(&lines, index)).unwrap_or(lines.len());
+    let signature = [
+        lines[..end].iter().rposition(|line| is_signature_delimiter(line)),
+        lines[..end].iter().position(|line| SIGNOFF.is_match(line)),
+    ]
+    .into_iter()
+    .flatten()
+    .min()
+    .unwrap_or(end);
+
+    let mut text = Vec::new();
+    let mut quoted = Vec::new();
+    for (index, &line) in lines[..end].iter().enumerate() {
+        if line.trim_start().starts_with('>') {
+            quoted.push(line);
+        } else if index < signature {
+            text.push(line);
+        }
+    }
+    quoted.extend_from_slice(&lines[end..]);
+
+    let join = |lines: Vec<&str>| lines.join("\n").trim().to_string();
+    BodyParts {
+        text: join(text),
+        quoted: join(quoted),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_splits_reply_quote_and_signature() {
+        let body = "Sounds good, see you Thursday.\n\n-- \nAlice\nACME Corp\n\nOn Mon, 3 Jun 2024 at 10:00, Bob <bob@example.com> wrote:\n> Can we meet on Thursday?\n>\n> Bob\n";
+        let parts = split_body(body);
+        assert_eq!(parts.text, "Sounds good, see you Thursday.");
+        assert!(parts.quoted.starts_with("On Mon, 3 Jun 2024"));
+        assert!(parts.quoted.ends_with("> Bob"));
+
+        // 夹在引用之间的回复保留
+        let inline = split_body("> 周四可以吗？\n可以。\n> 地点呢？\n三楼会议室。");
+        assert_eq!(inline.text, "可以。\n三楼会议室。");
+        assert_eq!(inline.quoted, "> 周四可以吗？\n> 地点呢？");
+        assert_eq!(split_body("只有正文").quoted, "");
+    }
+
+    #[test]
+    fn test_detects_chinese_and_outlook_quotes() {
+        let chinese = split_body("好的，没问题。\n\n发自我的iPhone\n\n在 2024年6月3日 周一 10:00，Bob <bob@example.com> 写道：\n> 周四可以吗？");
+        assert_eq!(chinese.text, "好的，没问题。");
+        assert_eq!(chinese.quoted, "在 2024年6月3日 周一 10:00，Bob <bob@example.com> 写道：\n> 周四可以吗？");
+
+        let outlook = split_body("Approved.\n\n________________________________\nFrom: Bob <bob@example.com>\nSent: Monday, June 3, 2024 10:00 AM\nTo: Alice\nSubject: Budget\n\nPlease approve the budget.");
+        assert_eq!(outlook.text, "Approved.");
+        assert!(outlook.quoted.starts_with("_____") && outlook.quoted.ends_with("Please approve the budget."));
+
+        let foxmail = split_body("收到。\n\n------------------ 原始邮件 ------------------\n发件人: \"Bob\"<bob@example.com>;\n发送时间: 2024年6月3日(星期一) 上午10:00\n\n请查收附件。");
+        assert_eq!(foxmail.text, "收到。");
+        assert!(foxmail.quoted.ends_with("请查收附件。"));
+
+        // 只有最后一个 `-- ` 之后是签名，不带空格的 `--` 不是分隔线
+        let output = "日志如下：\n--\nERROR disk full\n--\nERROR retry\n\n-- \nAlice";
+        assert_eq!(split_body(output).text, "日志如下：\n--\nERROR disk full\n--\nERROR retry");
+        let example = "签名请按这个格式：\n-- \nBob\n\n谢谢。\n-- \nAlice";
+        assert_eq!(split_body(example).text, "签名请按这个格式：\n-- \nBob\n\n谢谢。");
+
+        // 正文中单独提到发件人不算引用头
+        let plain = "发件人: 财务部\n请在周五前提交报销单。";
+        assert_eq!(split_body(plain).text, plain);
+    }
+}
@@ -0,0 +1,35 @@
+use async_trait::async_trait;
+
+use super::client::{EmailError, EmailResult};
+use super::{ImapReceiver, InboundEmail, MaildirReceiver};
+use crate::config::{EmailConfig, InboundSource};
+
+/// 收到的邮件交给处理方；返回 `Ok` 表示邮件已处理完毕，可以在服务器上标记
+#[async_trait]
+pub trait MessageHandler: Send + Sync {
+    async fn handle(&self, message: &InboundEmail) -> anyhow::Result<()>;
+}
+
+/// 收件来源：持续接收新邮件并交给 `MessageHandler`
+#[async_trait]
+pub trait MailReceiver: Send + Sync {
+    async fn run(&self, handler: &dyn MessageHandler) -> EmailResult<()>;
+}
+
+/// 根据 `[email] inbound` 配置创建收件来源
+pub fn create_receiver(config: &EmailConfig) -> EmailResult<Box<dyn MailReceiver>> {
+    match config.inbound {
+        InboundSource::Imap => {
+            let imap = config.imap.clone().ok_or_else(|| {
+                EmailError::Validation("inbound = \"imap\" requires an [email.imap] section".to_string())
+            })?;
+            Ok(Box::new(ImapReceiver::new(imap)))
+        }
+        InboundSource::Maildir => {
+            let maildir = config.maildir.clone().ok_or_else(|| {
+                EmailError::Validation("inbound = \"maildir\" requires an [email.maildir] section".to_string())
+            })?;
+            Ok(Box::new(MaildirReceiver::new(maildir)))
+        }
+    }
+}
@@ -0,0 +1,116 @@
+//! 用录制的模型响应端到端驱动 `process_incoming_email`，不需要网络
+//!
+//! 录制文件在 `tests/fixtures/llm`。修改提示词或邮件处理流程后，设置
+//! `SENTIO_RECORD_FIXTURES=1` 运行本模块的测试，会改用 `sentio.toml` 中配置的模型重新录制。
+
+use std::path::PathBuf;
+
+use crate::config::{Config, EmailConfig};
+use crate::email::parser::parse_message;
+use crate::email::InboundEmail;
+use crate::llm::client::LlmError;
+use crate::llm::prompt::PromptRegistry;
+use crate::llm::replay::{RecordingClient, ReplayClient};
+use crate::llm::LlmClient;
+use crate::memory::{MemoryStore, MemoryType, MessageDirection};
+use crate::senders::SenderList;
+use crate::workflow::tests::CapturingClient;
+use crate::workflow::EmailWorkflow;
+
+/// 录制时使用的模型名称，参与匹配录制
+const MODEL: &str = "deepseek-chat";
+
+fn recording() -> bool {
+    std::env::var_os("SENTIO_RECORD_FIXTURES").is_some()
+}
+
+fn fixture_llm() -> Box<dyn LlmClient> {
+    let dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/llm");
+    let prompts = PromptRegistry::builtin().unwrap();
+    if recording() {
+        let config = Config::load().expect("recording needs sentio.toml with a working [llm] backend");
+        let client = crate::llm::create_client(&config.llm, &config.prompts).unwrap();
+        return Box::new(RecordingClient::new(client, &dir, MODEL, prompts).unwrap());
+    }
+    Box::new(ReplayClient::open(&dir, MODEL, prompts).unwrap())
+}
+
+async fn workflow(sender: &str) -> (EmailWorkflow, CapturingClient) {
+    MemoryStore::initialize_for_tests().await;
+    let config: EmailConfig = toml::from_str(&format!(
+        "smtp = {{ host = \"smtp.example.com\", port = 587, username = \"sentio@example.com\", password = \"p\" }}\nallowed_sender = \"{}\"",
+        sender
+    ))
+    .unwrap();
+    let client = CapturingClient::default();
+    let workflow = EmailWorkflow::new(fixture_llm(), Box::new(client.clone()), SenderList::from_config(&config).unwrap())
+        .with_history_turns(10);
+    (workflow, client)
+}
+
+fn email(sender: &str, headers: &str, subject: &str, body: &str) -> InboundEmail {
+    let raw = format!(
+        "From: {}\r\nTo: sentio@example.com\r\n{}Subject: {}\r\nContent-Type: text/plain; charset=utf-8\r\n\r\n{}\r\n",
+        sender, headers, subject, body
+    );
+    parse_message(raw.as_bytes()).unwrap()
+}
+
+#[tokio::test]
+async fn test_thread_is_analysed_and_answered_from_recordings() {
+    let sender = "e2e-thread@example.com";
+    let (workflow, client) = workflow(sender).await;
+
+    let first = email(
+        sender,
+        "Message-ID: <e2e-1@example.com>\r\n",
+        "周会改期",
+        "你好，本周的周会能否改到周四下午三点？请确认。",
+    );
+    workflow.process_incoming_email(&first).await.unwrap();
+    let follow_up = email(
+        sender,
+        "Message-ID: <e2e-2@example.com>\r\nIn-Reply-To: <e2e-1@example.com>\r\nReferences: <e2e-1@example.com>\r\n",
+        "Re: 周会改期",
+        "好的，那会议室也请一并预订。\r\n\r\n发自我的iPhone\r\n\r\n在 2024年6月3日 周一 10:00，e2e-thread@example.com 写道：\r\n> 你好，本周的周会能否改到周四下午三点？请确认。",
+    );
+    workflow.process_incoming_email(&follow_up).await.unwrap();
+
+    let sent = client.sent.lock().unwrap().clone();
+    assert_eq!(sent.len(), 2);
+    assert!(sent.iter().all(|reply| reply.to[0].email == sender && !reply.body.trim().is_empty()));
+    assert_eq!(sent[0].in_reply_to.as_deref(), Some("e2e-1@example.com"));
+    assert_eq!(sent[1].references, vec!["e2e-1@example.com".to_string(), "e2e-2@example.com".to_string()]);
+
+    // 两轮往来都记在同一会话中，回复记录标明实际回答的后端
+    let interactions = MemoryStore::get_user_interactions(sender, None, None).await.unwrap();
+    assert_eq!(interactions.len(), 4);
+    assert!(interactions.iter().all(|log| log.session_id == "e2e-1@example.com"));
+    let replies: Vec<_> = interactions.iter().filter(|log| matches!(log.direction, MessageDirection::SystemToUser)).collect();
+    assert_eq!(replies[1].content, sent[1].body);
+    // 引用的上一封邮件和手机落款不重复记录
+    assert!(interactions.iter().any(|log| log.content == "Subject: Re: 周会改期\n\n好的，那会议室也请一并预订。"));
+    assert!(interactions.iter().all(|log| !log.content.contains("写道") && !log.content.contains("iPhone")));
+    if !recording() {
+        assert_eq!(replies[0].metadata["llm_backend"], "replay");
+    }
+
+    let events = MemoryStore::get().get_user_memories(sender).await.unwrap();
+    assert!(events.iter().all(|memory| memory.memory_type == MemoryType::Event));
+    assert!(events[0].content.contains("[工作相关]"), "{}", events[0].content);
+}
+
+#[tokio::test]
+async fn test_unrecorded_request_fails_without_sending() {
+    if recording() {
+        return;
+    }
+    let sender = "e2e-unrecorded@example.com";
+    let (workflow, client) = workflow(sender).await;
+
+    let message = email(sender, "Message-ID: <e2e-3@example.com>\r\n", "没有录制", "这封邮件没有对应的录制。");
+    let err = workflow.process_incoming_email(&message).await.unwrap_err();
+    let missing = err.chain().find_map(|cause| cause.downcast_ref::<LlmError>());
+    assert!(matches!(missing, Some(LlmError::ReplayMissing { prompt_name, .. }) if prompt_name == "email_analysis"), "{:#}", err);
+    assert!(client.sent.lock().unwrap().is_empty());
+}
@@ -0,0 +1,310 @@
+use async_trait::async_trait;
+use reqwest::{Client, StatusCode};
+use serde::{Deserialize, Serialize};
+
+use super::client::{
+    base_url, http_client, render_messages, require_api_key, status_error, RetryPolicy, ChatMessage, LlmClient,
+    LlmError, LlmRequest, LlmResponse, LlmResult, ResponseFormat, TokenUsage, ToolCall,
+};
+use super::prompt::PromptRegistry;
+use crate::config::LlmConfig;
+
+const API_VERSION: &str = "2023-06-01";
+
+/// Messages API 没有 JSON 模式，改为在系统提示中要求
+const JSON_INSTRUCTION: &str = "只输出一个 JSON 对象，不要添加任何其他文字。";
+
+/// Anthropic Messages API（`/v1/messages`）
+pub struct AnthropicClient {
+    client: Client,
+    api_key: String,
+    base_url: String,
+    model: String,
+    retry: RetryPolicy,
+    prompts: PromptRegistry,
+}
+
+impl AnthropicClient {
+    pub fn new(config: &LlmConfig, prompts: PromptRegistry) -> LlmResult<Self> {
+        require_api_key(config)?;
+        Ok(Self {
+            client: http_client(config)?,
+            api_key: config.api_key.clone(),
+            base_url: base_url(config, "https://api.anthropic.com/v1"),
+            model: config.model.clone(),
+            retry: RetryPolicy::from_config(config),
+            prompts,
+        })
+    }
+
+    async fn call_api(&self, request_body: &MessagesRequest<'_>) -> LlmResult<MessagesResponse> {
+        let response = self.client
+            .post(format!("{}/messages", self.base_url))
+            .header("x-api-key", &self.api_key)
+            .header("anthropic-version", API_VERSION)
+            .json(request_body)
+            .send()
+            .await?;
+
+        match response.status() {
+            StatusCode::OK => response.json().await.map_err(|e| LlmError::InvalidApiResponse(e.to_string())),
+            _ => Err(status_error(response).await),
+        }
+    }
+}
+
+/// 系统提示单独放在 `system` 字段；其余消息只能是 user / assistant 且必须交替出现，
+/// 相邻的同角色消息合并为一条，开头不是 user 时丢掉前面的 assistant 消息。
+/// 工具调用是 assistant 消息中的 `tool_use` 块，工具结果是 user 消息中的 `tool_result` 块。
+fn split_messages(messages: Vec<ChatMessage>) -> (String, Vec<Turn>) {
+    let mut system = Vec::new();
+    let mut turns: Vec<Turn> = Vec::new();
+    for message in messages {
+        let (role, blocks) = match message.role.as_str() {
+            "system" => {
+                system.push(message.content);
+                continue;
+            }
+            "tool" => ("user", vec![Block::ToolResult {
+                tool_use_id: message.tool_call_id.unwrap_or_default(),
+                content: message.content,
+            }]),
+            "assistant" => {
+                let text = (!message.content.is_empty()).then_some(Block::Text { text: message.content });
+                let calls = message.tool_calls.into_iter().map(|call| Block::ToolUse {
+                    id: call.id,
+                    name: call.name,
+                    input: call.arguments,
+                });
+                ("assistant", text.into_iter().chain(calls).collect())
+            }
+            _ => ("user", vec![Block::Text { text: message.content }]),
+        };
+        if turns.is_empty() && role != "user" {
+            continue;
+        }
+
+        match turns.last_mut() {
+            Some(last) if last.role == role => {
+                for block in blocks {
+                    match (last.content.last_mut(), block) {
+                        (Some(Block::Text { text }), Block::Text { text: next }) => {
+                            text.push_str("\n\n");
+                            text.push_str(&next);
+                        }
+                        (_, block) => last.content.push(block),
+                    }
+                }
+            }
+            _ => turns.push(Turn { role, content: blocks }),
+        }
+    }
+    (system.join("\n\n"), turns)
+}
+
+#[async_trait]
+impl LlmClient for AnthropicClient {
+    async fn generate_response(&self, request: &LlmRequest) -> LlmResult<LlmResponse> {
+        let (mut system, messages) = split_messages(render_messages(&self.prompts, request)?);
+        if request.response_format == ResponseFormat::Json {
+            system.push_str("\n\n");
+            system.push_str(JSON_INSTRUCTION);
+        }
+        let request_body = MessagesRequest {
+            model: request.model_or(&self.model),
+            system: &system,
+            messages: &messages,
+            tools: request.tools
+                .iter()
+                .map(|tool| Tool {
+                    name: &tool.name,
+                    description: &tool.description,
+                    input_schema: &tool.parameters,
+                })
+                .collect(),
+            // Messages API 不支持 presence / frequency penalty 和 seed
+            max_tokens: request.sampling.max_tokens(),
+            temperature: request.sampling.temperature(),
+            top_p: request.sampling.top_p,
+            stop_sequences: &request.sampling.stop,
+        };
+
+        let response = self.retry.run(|| self.call_api(&request_body)).await?;
+
+        let content: String = response.content
+            .iter()
+            .filter(|block| block.kind == "text")
+            .filter_map(|block| block.text.as_deref())
+            .collect();
+        let tool_calls: Vec<ToolCall> = response.content
+            .into_iter()
+            .filter(|block| block.kind == "tool_use")
+            .map(|block| ToolCall {
+                id: block.id.unwrap_or_default(),
+                name: block.name.unwrap_or_default(),
+                arguments: block.input.unwrap_or_else(|| serde_json::json!({})),
+            })
+            .collect();
+        if content.is_empty() && tool_calls.is_empty() {
+            return Err(LlmError::InvalidApiResponse("Response has no text content".to_string()));
+        }
+        Ok(LlmResponse {
+            request_id: request.id,
+            content,
+            model: response.model,
+            usage: TokenUsage {
+                prompt_tokens: response.usage.input_tokens,
+                completion_tokens: response.usage.output_tokens,
+                total_tokens: response.usage.input_tokens + response.usage.output_tokens,
+            },
+            backend: "anthropic".to_string(),
+            tool_calls,
+        })
+    }
+}
+
+#[derive(Serialize)]
+struct MessagesRequest<'a> {
+    model: &'a str,
+    system: &'a str,
+    messages: &'a [Turn],
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    tools: Vec<Tool<'a>>,
+    max_tokens: u32,
+    temperature: f32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    top_p: Option<f32>,
+    #[serde(skip_serializing_if = "<[String]>::is_empty")]
+    stop_sequences: &'a [String],
+}
+
+#[derive(Debug, Serialize)]
+struct Turn {
+    role: &'static str,
+    content: Vec<Block>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum Block {
+    Text { text: String },
+    ToolUse { id: String, name: String, input: serde_json::Value },
+    ToolResult { tool_use_id: String, content: String },
+}
+
+#[derive(Serialize)]
+struct Tool<'a> {
+    name: &'a str,
+    description: &'a str,
+    input_schema: &'a serde_json::Value,
+}
+
+#[derive(Deserialize)]
+struct MessagesResponse {
+    model: String,
+    content: Vec<ContentBlock>,
+    usage: Usage,
+}
+
+#[derive(Deserialize)]
+struct ContentBlock {
+    #[serde(rename = "type")]
+    kind: String,
+    text: Option<String>,
+    /// 以下为 `tool_use` 块的字段
+    id: Option<String>,
+    name: Option<String>,
+    input: Option<serde_json::Value>,
+}
+
+#[derive(Deserialize)]
+struct Usage {
+    input_tokens: u32,
+    output_tokens: u32,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::llm::client::tests::{mock_server, request, test_config};
+    use crate::llm::client::Sampling;
+    use axum::http::{HeaderMap, StatusCode};
+    use axum::routing::post;
+    use axum::{Json, Router};
+    use std::sync::{Arc, Mutex};
+
+    #[test]
+    fn test_split_messages_merges_roles() {
+        let (system, turns) = split_messages(vec![
+            ChatMessage::system("你是助手"),
+            ChatMessage::assistant("上一封回复"),
+            ChatMessage::user("第一封"),
+            ChatMessage::user("第二封"),
+            ChatMessage::assistant("回复"),
+            ChatMessage::user("本轮"),
+        ]);
+        assert_eq!(system, "你是助手");
+        let roles: Vec<&str> = turns.iter().map(|turn| turn.role).collect();
+        assert_eq!(roles, vec!["user", "assistant", "user"]);
+        assert!(matches!(&turns[0].content[..], [Block::Text { text }] if text == "第一封\n\n第二封"));
+
+        // 工具调用和结果分别归入 assistant 和 user 消息
+        let call = ToolCall { id: "toolu_1".to_string(), name: "search_memory".to_string(), arguments: serde_json::json!({}) };
+        let (_, turns) = split_messages(vec![
+            ChatMessage::user("本轮"),
+            ChatMessage::tool_request("", vec![call]),
+            ChatMessage::tool_result("toolu_1", "[]"),
+        ]);
+        assert!(matches!(&turns[1].content[..], [Block::ToolUse { id, .. }] if id == "toolu_1"));
+        assert_eq!(turns[2].role, "user");
+        assert!(matches!(&turns[2].content[..], [Block::ToolResult { tool_use_id, .. }] if tool_use_id == "toolu_1"));
+    }
+
+    #[tokio::test]
+    async fn test_messages_mapping_and_errors() {
+        let received = Arc::new(Mutex::new(None));
+        let captured = received.clone();
+        let app = Router::new()
+            .route(
+                "/v1/messages",
+                post(move |headers: HeaderMap, Json(body): Json<serde_json::Value>| async move {
+                    *captured.lock().unwrap() = Some((headers, body));
+                    Json(serde_json::json!({
+                        "id": "msg_01",
+                        "type": "message",
+                        "role": "assistant",
+                        "model": "claude-test",
+                        "content": [{ "type": "text", "text": "{\"category\": " }, { "type": "text", "text": "\"work\"}" }],
+                        "stop_reason": "end_turn",
+                        "usage": { "input_tokens": 40, "output_tokens": 9 }
+                    }))
+                }),
+            )
+            .route(
+                "/denied/messages",
+                post(|| async {
+                    (StatusCode::UNAUTHORIZED, Json(serde_json::json!({
+                        "type": "error",
+                        "error": { "type": "authentication_error", "message": "invalid x-api-key" }
+                    })))
+                }),
+            );
+        let base = mock_server(app).await;
+
+        let client = AnthropicClient::new(&test_config("anthropic", &format!("{}/v1", base)), PromptRegistry::builtin().unwrap()).unwrap();
+        let response = client.generate_response(&request().with_response_format(ResponseFormat::Json)).await.unwrap();
+        assert_eq!(response.content, "{\"category\": \"work\"}");
+        assert_eq!((response.model.as_str(), response.usage.total_tokens), ("claude-test", 49));
+
+        let (headers, body) = received.lock().unwrap().take().unwrap();
+        assert_eq!(headers["x-api-key"], "test-key");
+        assert_eq!(headers["anthropic-version"], API_VERSION);
+        assert!(body["system"].as_str().unwrap().ends_with(JSON_INSTRUCTION));
+        assert_eq!(body["messages"][0]["role"], "user");
+        assert_eq!(body["max_tokens"], Sampling::default().max_tokens());
+
+        let denied = AnthropicClient::new(&test_config("anthropic", &format!("{}/denied", base)), PromptRegistry::builtin().unwrap()).unwrap();
+        let err = denied.generate_response(&request()).await.unwrap_err();
+        assert!(matches!(err, LlmError::AuthenticationFailed(ref message) if message.contains("invalid x-api-key")), "{:?}", err);
+    }
+}
@@ -0,0 +1,357 @@
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use futures::stream;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+use tokio::fs;
+use tracing::{debug, warn};
+
+use super::client::{
+    render_messages, ChatMessage, LlmClient, LlmError, LlmRequest, LlmResponse, LlmResult, LlmStream, ResponseFormat,
+    Sampling, StreamChunk, TokenUsage, ToolDefinition,
+};
+use super::prompt::PromptRegistry;
+use crate::config::LlmCacheConfig;
+
+/// 参与计算缓存键的内容：相同的键一定得到相同的请求
+#[derive(Serialize)]
+struct CacheKey<'a> {
+    model: &'a str,
+    messages: &'a [ChatMessage],
+    response_format: ResponseFormat,
+    tools: &'a [ToolDefinition],
+    sampling: Sampling,
+}
+
+/// 请求的内容哈希，缓存和录制回放共用
+pub(super) fn request_key(
+    model: &str,
+    messages: &[ChatMessage],
+    response_format: ResponseFormat,
+    tools: &[ToolDefinition],
+    sampling: &Sampling,
+) -> String {
+    let key = CacheKey {
+        model,
+        messages,
+        response_format,
+        tools,
+        sampling: sampling.resolved(),
+    };
+    let json = serde_json::to_vec(&key).expect("cache keys serialize to JSON");
+    format!("{:x}", Sha256::digest(json))
+}
+
+#[derive(Serialize, Deserialize)]
+struct CacheEntry {
+    created_at: DateTime<Utc>,
+    response: LlmResponse,
+}
+
+struct IndexEntry {
+    size: u64,
+    last_used: SystemTime,
+}
+
+/// 缓存目录：每个响应一个 `<key>.json` 文件，文件修改时间记录最近一次使用
+struct CacheStore {
+    dir: PathBuf,
+    ttl: Duration,
+    max_size: u64,
+    index: Mutex<HashMap<String, IndexEntry>>,
+}
+
+impl CacheStore {
+    fn open(dir: PathBuf, ttl: Duration, max_size: u64) -> LlmResult<Self> {
+        let io_error = |e: std::io::Error| LlmError::ConfigurationError(format!("LLM cache {}: {}", dir.display(), e));
+        std::fs::create_dir_all(&dir).map_err(io_error)?;
+
+        let mut index = HashMap::new();
+        for entry in std::fs::read_dir(&dir).map_err(io_error)? {
+            let path = entry.map_err(io_error)?.path();
+            let (Some(key), Ok(metadata)) = (path.file_stem().and_then(|stem| stem.to_str()), path.metadata()) else {
+                continue;
+            };
+            if path.extension().and_then(|ext| ext.to_str()) == Some("json") {
+                let last_used = metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH);
+                index.insert(key.to_string(), IndexEntry { size: metadata.len(), last_used });
+            }
+        }
+
+        Ok(Self {
+            dir,
+            ttl,
+            max_size,
+            index: Mutex::new(index),
+        })
+    }
+
+    fn path(&self, key: &str) -> PathBuf {
+        self.dir.join(format!("{}.json", key))
+    }
+
+    async fn get(&self, key: &str) -> Option<LlmResponse> {
+        if !self.index.lock().unwrap().contains_key(key) {
+            return None;
+        }
+        let path = self.path(key);
+        let entry = match fs::read(&path).await.map(|bytes| serde_json::from_slice::<CacheEntry>(&bytes)) {
+            Ok(Ok(entry)) => entry,
+            Ok(Err(e)) => {
+                warn!("Discarding unreadable LLM cache entry {}: {}", path.display(), e);
+                self.remove(key).await;
+                return None;
+            }
+            Err(_) => {
+                self.index.lock().unwrap().remove(key);
+                return None;
+            }
+        };
+
+        let age = (Utc::now() - entry.created_at).to_std().unwrap_or(Duration::ZERO);
+        if age >= self.ttl {
+            self.remove(key).await;
+            return None;
+        }
+
+        let now = SystemTime::now();
+        if let Some(indexed) = self.index.lock().unwrap().get_mut(key) {
+            indexed.last_used = now;
+        }
+        // 重启后按修改时间恢复使用顺序
+        if let Err(e) = std::fs::File::options().append(true).open(&path).and_then(|file| file.set_modified(now)) {
+            debug!("Failed to touch LLM cache entry {}: {}", path.display(), e);
+        }
+        Some(entry.response)
+    }
+
+    async fn put(&self, key: &str, response: &LlmResponse) {
+        let entry = CacheEntry {
+            created_at: Utc::now(),
+            response: response.clone(),
+        };
+        let path = self.path(key);
+        let bytes = serde_json::to_vec(&entry).expect("cache entries serialize to JSON");
+        if let Err(e) = fs::write(&path, &bytes).await {
+            warn!("Failed to write LLM cache entry {}: {}", path.display(), e);
+            return;
+        }
+
+        let evicted = {
+            let mut index = self.index.lock().unwrap();
+            index.insert(key.to_string(), IndexEntry { size: bytes.len() as u64, last_used: SystemTime::now() });
+            let mut total: u64 = index.values().map(|entry| entry.size).sum();
+            let mut by_age: Vec<(String, SystemTime, u64)> = index
+                .iter()
+                .filter(|(cached, _)| cached.as_str() != key)
+                .map(|(cached, entry)| (cached.clone(), entry.last_used, entry.size))
+                .collect();
+            by_age.sort_by_key(|(_, last_used, _)| *last_used);
+
+            let mut evicted = Vec::new();
+            for (cached, _, size) in by_age {
+                if total <= self.max_size {
+                    break;
+                }
+                index.remove(&cached);
+                total -= size;
+                evicted.push(cached);
+            }
+            evicted
+        };
+        for cached in evicted {
+            debug!("Evicting LLM cache entry {}", cached);
+            let _ = fs::remove_file(self.path(&cached)).await;
+        }
+    }
+
+    async fn remove(&self, key: &str) {
+        self.index.lock().unwrap().remove(key);
+        let _ = fs::remove_file(self.path(key)).await;
+    }
+}
+
+/// 把响应缓存在磁盘上的客户端
+///
+/// 键为模型、渲染后的消息、输出格式、工具和采样参数的 SHA-256，
+/// 重新处理同一封邮件时直接返回上次的结果。命中的响应 `backend` 为 `cache`，用量为 0。
+pub struct CachingClient {
+    inner: Box<dyn LlmClient>,
+    prompts: PromptRegistry,
+    model: String,
+    bypass: bool,
+    store: Arc<CacheStore>,
+}
+
+impl CachingClient {
+    pub fn new(inner: Box<dyn LlmClient>, config: &LlmCacheConfig, model: &str, prompts: PromptRegistry) -> LlmResult<Self> {
+        let store = CacheStore::open(
+            config.dir.clone(),
+            Duration::from_secs(config.ttl),
+            config.max_size_mb.saturating_mul(1024 * 1024),
+        )?;
+        Ok(Self {
+            inner,
+            prompts,
+            model: model.to_string(),
+            bypass: config.bypass,
+            store: Arc::new(store),
+        })
+    }
+
+    fn key(&self, request: &LlmRequest) -> LlmResult<String> {
+        let messages = render_messages(&self.prompts, request)?;
+        Ok(request_key(request.model_or(&self.model), &messages, request.response_format, &request.tools, &request.sampling))
+    }
+
+    async fn cached(&self, key: &str, request: &LlmRequest) -> Option<LlmResponse> {
+        if self.bypass {
+            return None;
+        }
+        let mut response = self.store.get(key).await?;
+        debug!(prompt = %request.prompt_name, "LLM cache hit");
+        response.request_id = request.id;
+        response.backend = "cache".to_string();
+        response.usage = TokenUsage::default();
+        Some(response)
+    }
+}
+
+#[async_trait]
+impl LlmClient for CachingClient {
+    async fn generate_response(&self, request: &LlmRequest) -> LlmResult<LlmResponse> {
+        let key = self.key(request)?;
+        if let Some(response) = self.cached(&key, request).await {
+            return Ok(response);
+        }
+        let response = self.inner.generate_response(request).await?;
+        self.store.put(&key, &response).await;
+        Ok(response)
+    }
+
+    /// 命中时一次性返回缓存内容；未命中时边转发边收集，完整读完且没有出错才写入缓存
+    async fn stream_response(&self, request: &LlmRequest) -> LlmResult<LlmStream> {
+        let key = self.key(request)?;
+        if let Some(response) = self.cached(&key, request).await {
+            let chunk = StreamChunk {
+                delta: response.content,
+                usage: Some(response.usage),
+                finish_reason: Some("stop".to_string()),
+            };
+            return Ok(LlmStream::new(response.model, response.backend, stream::iter([Ok(chunk)])));
+        }
+
+        let store = self.store.clone();
+        let stream = self.inner.stream_response(request).await?;
+        Ok(stream.on_complete(request.id, move |response| async move { store.put(&key, &response).await }))
+    }
+
+    async fn generate_streamed(&self, request: &LlmRequest) -> LlmResult<LlmResponse> {
+        let key = self.key(request)?;
+        if let Some(response) = self.cached(&key, request).await {
+            return Ok(response);
+        }
+        let response = self.inner.generate_streamed(request).await?;
+        self.store.put(&key, &response).await;
+        Ok(response)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::llm::client::tests::{request, response};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use uuid::Uuid;
+
+    /// 每次调用返回不同内容的模型
+    #[derive(Clone, Default)]
+    struct Counting {
+        calls: Arc<AtomicUsize>,
+    }
+
+    #[async_trait]
+    impl LlmClient for Counting {
+        async fn generate_response(&self, request: &LlmRequest) -> LlmResult<LlmResponse> {
+            let call = self.calls.fetch_add(1, Ordering::SeqCst);
+            Ok(LlmResponse {
+                usage: TokenUsage { prompt_tokens: 10, completion_tokens: 5, total_tokens: 15 },
+                ..response(request, format!("{{\"call\": {}}}", call))
+            })
+        }
+    }
+
+    fn caching(inner: &Counting, dir: &std::path::Path, ttl: Duration, max_size: u64) -> CachingClient {
+        CachingClient {
+            inner: Box::new(inner.clone()),
+            prompts: PromptRegistry::builtin().unwrap(),
+            model: "m".to_string(),
+            bypass: false,
+            store: Arc::new(CacheStore::open(dir.to_path_buf(), ttl, max_size).unwrap()),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_hits_misses_ttl_and_bypass() {
+        let dir = tempfile::tempdir().unwrap();
+        let inner = Counting::default();
+        let client = caching(&inner, dir.path(), Duration::from_secs(3600), u64::MAX);
+
+        let first = client.generate_response(&request()).await.unwrap();
+        let second = client.generate_response(&request()).await.unwrap();
+        assert_eq!((first.content.as_str(), second.content.as_str()), ("{\"call\": 0}", "{\"call\": 0}"));
+        assert_eq!((second.backend.as_str(), second.usage.total_tokens), ("cache", 0));
+
+        // 模型不同或提示词内容不同都不命中；流式请求与普通请求共用缓存
+        client.generate_response(&request().with_model("other")).await.unwrap();
+        let streamed = client.stream_response(&request()).await.unwrap().into_response(Uuid::nil()).await.unwrap();
+        assert_eq!(streamed.content, "{\"call\": 0}");
+        assert_eq!(inner.calls.load(Ordering::SeqCst), 2);
+
+        // 重新打开后仍然命中
+        let reopened = caching(&inner, dir.path(), Duration::from_secs(3600), u64::MAX);
+        assert_eq!(reopened.generate_response(&request()).await.unwrap().backend, "cache");
+
+        let bypass = CachingClient { bypass: true, ..caching(&inner, dir.path(), Duration::from_secs(3600), u64::MAX) };
+        assert_eq!(bypass.generate_response(&request()).await.unwrap().content, "{\"call\": 2}");
+        assert_eq!(reopened.generate_response(&request()).await.unwrap().content, "{\"call\": 2}");
+
+        let expired = caching(&inner, dir.path(), Duration::ZERO, u64::MAX);
+        assert_eq!(expired.generate_response(&request()).await.unwrap().content, "{\"call\": 3}");
+    }
+
+    #[tokio::test]
+    async fn test_evicts_least_recently_used() {
+        let dir = tempfile::tempdir().unwrap();
+        let inner = Counting::default();
+        let entry_size = {
+            let probe = tempfile::tempdir().unwrap();
+            let client = caching(&inner, probe.path(), Duration::from_secs(3600), u64::MAX);
+            client.generate_response(&request()).await.unwrap();
+            std::fs::read_dir(probe.path()).unwrap().next().unwrap().unwrap().metadata().unwrap().len()
+        };
+        // 空间只够两条
+        let client = caching(&inner, dir.path(), Duration::from_secs(3600), entry_size * 2 + entry_size / 2);
+        let with_email = |content: &str| {
+            let mut request = request();
+            request.context.insert("email_content".to_string(), serde_json::json!(content));
+            request
+        };
+
+        client.generate_response(&with_email("a")).await.unwrap();
+        client.generate_response(&with_email("b")).await.unwrap();
+        client.generate_response(&with_email("a")).await.unwrap();
+        client.generate_response(&with_email("c")).await.unwrap();
+        let calls = inner.calls.load(Ordering::SeqCst);
+
+        assert_eq!(client.generate_response(&with_email("a")).await.unwrap().backend, "cache");
+        assert_eq!(client.generate_response(&with_email("c")).await.unwrap().backend, "cache");
+        assert_ne!(client.generate_response(&with_email("b")).await.unwrap().backend, "cache");
+        assert_eq!(inner.calls.load(Ordering::SeqCst), calls + 1);
+        assert!(std::fs::read_dir(dir.path()).unwrap().count() <= 2);
+    }
+}
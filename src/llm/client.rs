@@ -1,12 +1,33 @@
 use async_trait::async_trait;
-use reqwest::{Client, StatusCode};
+use futures::future;
+use futures::stream::{self, Stream, StreamExt};
+use reqwest::{Client, Response};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
 use std::time::Duration;
 use thiserror::Error;
+use tokio::time::Instant;
+use tracing::warn;
 use uuid::Uuid;
 
-use crate::config;
+use super::anthropic::AnthropicClient;
+use super::cache::CachingClient;
+use super::fallback::FallbackClient;
+use super::ollama::OllamaClient;
+use super::openai::OpenAiClient;
+use super::prompt::PromptRegistry;
+use super::replay::{RecordingClient, ReplayClient};
+use super::settings::SettingsClient;
+use crate::config::{LlmConfig, PromptConfig, ReplayMode};
+
+/// 生成时的默认采样温度
+const DEFAULT_TEMPERATURE: f32 = 0.7;
+/// 单次生成的默认最大 token 数
+const DEFAULT_MAX_TOKENS: u32 = 2000;
 
 #[derive(Error, Debug)]
 pub enum LlmError {
@@ -25,17 +46,152 @@ pub enum LlmError {
     #[error("Network error: {0}")]
     NetworkError(#[from] reqwest::Error),
     
-    #[error("Rate limited, retry after {0} seconds")]
-    RateLimited(u64),
+    #[error("Rate limited{}", .0.map(|delay| format!(", retry after {}s", delay.as_secs())).unwrap_or_default())]
+    RateLimited(Option<Duration>),
+
+    /// 500 / 502 / 503 / 504 等服务端临时错误
+    #[error("Server error {status}: {message}")]
+    ServerError {
+        status: u16,
+        message: String,
+        retry_after: Option<Duration>,
+    },
+
+    /// 流在完成前断开或长时间没有数据，已收到的内容不完整
+    #[error("Stream interrupted: {0}")]
+    StreamInterrupted(String),
+
+    #[error("Prompt template error: {0}")]
+    TemplateError(String),
+
+    /// 回放模式下没有与请求内容匹配的录制
+    #[error("No recorded response for prompt '{prompt_name}' (key {key}) in {dir}; record it with [llm.replay] mode = \"record\"")]
+    ReplayMissing {
+        prompt_name: String,
+        key: String,
+        dir: String,
+    },
 }
 
 pub type LlmResult<T> = Result<T, LlmError>;
 
+impl LlmError {
+    /// 网络错误（含超时）、限流、服务端临时错误和中断的流可以重试，其他错误重试也不会成功
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            Self::NetworkError(e) => !e.is_builder(),
+            Self::RateLimited(_) | Self::ServerError { .. } | Self::StreamInterrupted(_) => true,
+            _ => false,
+        }
+    }
+
+    /// 服务端通过 Retry-After 要求的等待时间
+    pub fn retry_after(&self) -> Option<Duration> {
+        match self {
+            Self::RateLimited(retry_after) | Self::ServerError { retry_after, .. } => *retry_after,
+            _ => None,
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct LlmRequest {
     pub id: Uuid,
     pub prompt_name: String,
     pub context: HashMap<String, serde_json::Value>,
+    /// 之前的对话轮次，按时间顺序放在系统提示和本轮用户消息之间
+    #[serde(default)]
+    pub history: Vec<ChatMessage>,
+    #[serde(default)]
+    pub response_format: ResponseFormat,
+    /// 模型可以调用的工具
+    #[serde(default)]
+    pub tools: Vec<ToolDefinition>,
+    /// 本轮用户消息之后的工具调用和工具结果，按时间顺序排列
+    #[serde(default)]
+    pub tool_messages: Vec<ChatMessage>,
+    /// 替换后端配置的模型，例如超出预算时改用便宜的模型
+    #[serde(default)]
+    pub model: Option<String>,
+    #[serde(default)]
+    pub sampling: Sampling,
+}
+
+/// 采样参数；未设置的温度和最大 token 数使用默认值，其他参数不发送，由后端决定
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Sampling {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub temperature: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub top_p: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_tokens: Option<u32>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub stop: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub presence_penalty: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub frequency_penalty: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub seed: Option<u64>,
+}
+
+impl Sampling {
+    pub fn temperature(&self) -> f32 {
+        self.temperature.unwrap_or(DEFAULT_TEMPERATURE)
+    }
+
+    pub fn max_tokens(&self) -> u32 {
+        self.max_tokens.unwrap_or(DEFAULT_MAX_TOKENS)
+    }
+
+    /// 逐项合并，已设置的参数优先
+    pub fn or(self, defaults: &Sampling) -> Sampling {
+        Sampling {
+            temperature: self.temperature.or(defaults.temperature),
+            top_p: self.top_p.or(defaults.top_p),
+            max_tokens: self.max_tokens.or(defaults.max_tokens),
+            stop: if self.stop.is_empty() { defaults.stop.clone() } else { self.stop },
+            presence_penalty: self.presence_penalty.or(defaults.presence_penalty),
+            frequency_penalty: self.frequency_penalty.or(defaults.frequency_penalty),
+            seed: self.seed.or(defaults.seed),
+        }
+    }
+
+    /// 填上默认温度和最大 token 数，作为缓存键时等价的请求得到相同的值
+    pub(super) fn resolved(&self) -> Sampling {
+        Sampling {
+            temperature: Some(self.temperature()),
+            max_tokens: Some(self.max_tokens()),
+            ..self.clone()
+        }
+    }
+}
+
+/// 提供给模型的工具，`parameters` 为 JSON Schema
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ToolDefinition {
+    pub name: String,
+    pub description: String,
+    pub parameters: serde_json::Value,
+}
+
+/// 模型请求的一次工具调用
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ToolCall {
+    pub id: String,
+    pub name: String,
+    pub arguments: serde_json::Value,
+}
+
+/// 期望的输出格式；`Json` 会在 API 支持时开启 JSON 模式
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ResponseFormat {
+    #[default]
+    Text,
+    Json,
 }
 
 impl LlmRequest {
@@ -44,8 +200,39 @@ impl LlmRequest {
             id: Uuid::new_v4(),
             prompt_name,
             context,
+            history: Vec::new(),
+            response_format: ResponseFormat::Text,
+            tools: Vec::new(),
+            tool_messages: Vec::new(),
+            model: None,
+            sampling: Sampling::default(),
         }
     }
+
+    pub fn with_history(mut self, history: Vec<ChatMessage>) -> Self {
+        self.history = history;
+        self
+    }
+
+    pub fn with_response_format(mut self, response_format: ResponseFormat) -> Self {
+        self.response_format = response_format;
+        self
+    }
+
+    pub fn with_tools(mut self, tools: Vec<ToolDefinition>) -> Self {
+        self.tools = tools;
+        self
+    }
+
+    pub fn with_model(mut self, model: impl Into<String>) -> Self {
+        self.model = Some(model.into());
+        self
+    }
+
+    /// 本次请求使用的模型：请求中指定的优先，否则为后端配置的模型
+    pub fn model_or<'a>(&'a self, default: &'a str) -> &'a str {
+        self.model.as_deref().unwrap_or(default)
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -54,190 +241,503 @@ pub struct LlmResponse {
     pub content: String,
     pub model: String,
     pub usage: TokenUsage,
+    /// 实际给出回答的后端，配置了备用后端时为其名称
+    #[serde(default)]
+    pub backend: String,
+    /// 模型要求先执行的工具调用，此时 `content` 可能为空
+    #[serde(default)]
+    pub tool_calls: Vec<ToolCall>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
 pub struct TokenUsage {
     pub prompt_tokens: u32,
     pub completion_tokens: u32,
     pub total_tokens: u32,
 }
 
-#[async_trait]
-pub trait LlmClient: Send + Sync {
-    async fn generate_response(&self, request: &LlmRequest) -> LlmResult<LlmResponse>;
+/// 流式生成中的一段增量；用量和结束原因只出现在最后几段
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct StreamChunk {
+    pub delta: String,
+    pub usage: Option<TokenUsage>,
+    pub finish_reason: Option<String>,
 }
 
-pub struct DeepSeekClient {
-    client: Client,
-    api_key: String,
-    base_url: String,
-    model: String,
-    max_retries: u32,
+/// 流式生成的结果，按顺序产出 `StreamChunk`
+pub struct LlmStream {
+    pub model: String,
+    /// 实际给出回答的后端，含义同 `LlmResponse::backend`
+    pub backend: String,
+    chunks: Pin<Box<dyn Stream<Item = LlmResult<StreamChunk>> + Send>>,
 }
 
-impl DeepSeekClient {
-    pub fn new() -> LlmResult<Self> {
-        let config = config::get();
-        
-        if config.llm.api_key.is_empty() {
-            tracing::warn!("LLM API key not configured. Set SENTIO_LLM_API_KEY environment variable or add it to sentio.toml");
-            return Err(LlmError::ConfigurationError(
-                "LLM API key not configured".to_string()
-            ));
-        }
-
-        let client = Client::builder()
-            .timeout(Duration::from_secs(config.llm.timeout))
-            .build()
-            .map_err(|e| LlmError::ConfigurationError(e.to_string()))?;
-
-        Ok(Self {
-            client,
-            api_key: config.llm.api_key.clone(),
-            base_url: config.llm.base_url.clone(),
-            model: config.llm.model.clone(),
-            max_retries: config.llm.max_retries,
+impl LlmStream {
+    pub fn new(
+        model: impl Into<String>,
+        backend: impl Into<String>,
+        chunks: impl Stream<Item = LlmResult<StreamChunk>> + Send + 'static,
+    ) -> Self {
+        Self {
+            model: model.into(),
+            backend: backend.into(),
+            chunks: Box::pin(chunks),
+        }
+    }
+
+    /// 读完整个流，拼成完整的回复
+    pub async fn into_response(mut self, request_id: Uuid) -> LlmResult<LlmResponse> {
+        let mut content = String::new();
+        let mut usage = TokenUsage::default();
+        let mut finished = false;
+        while let Some(chunk) = self.chunks.next().await {
+            let chunk = chunk?;
+            content.push_str(&chunk.delta);
+            if let Some(final_usage) = chunk.usage {
+                usage = final_usage;
+            }
+            finished |= chunk.finish_reason.is_some();
+        }
+        // 连接中途断开时已有的内容只是回复的一部分，不能当作完整回复发出
+        if !finished {
+            return Err(LlmError::StreamInterrupted("stream ended before the response was complete".to_string()));
+        }
+        if content.trim().is_empty() {
+            return Err(LlmError::InvalidApiResponse("Stream produced no content".to_string()));
+        }
+        Ok(LlmResponse {
+            request_id,
+            content,
+            model: self.model,
+            usage,
+            backend: self.backend,
+            tool_calls: Vec::new(),
         })
     }
 
-    async fn call_api(&self, messages: Vec<ChatMessage>) -> LlmResult<DeepSeekResponse> {
-        let request_body = DeepSeekRequest {
-            model: self.model.clone(),
-            messages,
-            temperature: 0.7,
-            max_tokens: 2000,
-        };
+    /// 转发流的同时收集内容；流完整读完、收到结束标记、没有出错且内容不为空时用拼好的回复调用 `f`
+    pub fn on_complete<F, Fut>(self, request_id: Uuid, f: F) -> Self
+    where
+        F: FnOnce(LlmResponse) -> Fut + Send + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        let (model, backend) = (self.model.clone(), self.backend.clone());
+        let collected = Arc::new(Mutex::new(Some((
+            LlmResponse {
+                request_id,
+                content: String::new(),
+                model: self.model,
+                usage: TokenUsage::default(),
+                backend: self.backend,
+                tool_calls: Vec::new(),
+            },
+            false,
+        ))));
 
-        let response = self.client
-            .post(format!("{}/chat/completions", self.base_url))
-            .bearer_auth(&self.api_key)
-            .json(&request_body)
-            .send()
-            .await?;
-
-        match response.status() {
-            StatusCode::OK => {
-                let api_response: DeepSeekResponse = response.json().await
-                    .map_err(|e| LlmError::InvalidApiResponse(e.to_string()))?;
-                Ok(api_response)
-            }
-            StatusCode::UNAUTHORIZED => {
-                Err(LlmError::AuthenticationFailed("Invalid API key".to_string()))
+        let collector = collected.clone();
+        let forwarded = self.chunks.inspect(move |chunk| {
+            let mut collected = collector.lock().unwrap();
+            match (chunk, collected.as_mut()) {
+                (Ok(chunk), Some((response, finished))) => {
+                    response.content.push_str(&chunk.delta);
+                    if let Some(usage) = &chunk.usage {
+                        response.usage = usage.clone();
+                    }
+                    *finished |= chunk.finish_reason.is_some();
+                }
+                (Err(_), _) => *collected = None,
+                (Ok(_), None) => {}
             }
-            StatusCode::TOO_MANY_REQUESTS => {
-                Err(LlmError::RateLimited(60))
+        });
+        let finish = stream::once(async move {
+            let response = collected.lock().unwrap().take();
+            if let Some((response, _)) = response.filter(|(response, finished)| *finished && !response.content.trim().is_empty()) {
+                f(response).await;
             }
-            status => {
-                let error_text = response.text().await.unwrap_or_default();
-                Err(LlmError::ApiRequestFailed(
-                    format!("API returned status {}: {}", status, error_text)
-                ))
+        })
+        .filter_map(|_| future::ready(None));
+
+        Self::new(model, backend, forwarded.chain(finish))
+    }
+
+    /// 流读完时调用 `f`：收到结束标记且没有出错为 true，出错或中途断开为 false；流被提前丢弃时不调用
+    pub fn on_finish<F>(self, f: F) -> Self
+    where
+        F: FnOnce(bool) + Send + 'static,
+    {
+        // None 表示已经出错
+        let outcome = Arc::new(Mutex::new(Some(false)));
+        let tracker = outcome.clone();
+        let forwarded = self.chunks.inspect(move |chunk| {
+            let mut outcome = tracker.lock().unwrap();
+            match (chunk, outcome.as_mut()) {
+                (Ok(chunk), Some(finished)) => *finished |= chunk.finish_reason.is_some(),
+                (Err(_), _) => *outcome = None,
+                (Ok(_), None) => {}
             }
-        }
+        });
+        let finish = stream::once(async move {
+            let completed = outcome.lock().unwrap().unwrap_or(false);
+            f(completed);
+        })
+        .filter_map(|_| future::ready(None));
+
+        Self::new(self.model, self.backend, forwarded.chain(finish))
     }
+}
 
-    fn render_prompt(&self, template: &str, context: &HashMap<String, serde_json::Value>) -> String {
-        let mut result = template.to_string();
-        for (key, value) in context {
-            let placeholder = format!("{{{{{}}}}}", key);
-            let replacement = match value {
-                serde_json::Value::String(s) => s.clone(),
-                other => other.to_string(),
-            };
-            result = result.replace(&placeholder, &replacement);
-        }
-        result
+impl Stream for LlmStream {
+    type Item = LlmResult<StreamChunk>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.chunks.as_mut().poll_next(cx)
     }
 }
 
 #[async_trait]
-impl LlmClient for DeepSeekClient {
-    async fn generate_response(&self, request: &LlmRequest) -> LlmResult<LlmResponse> {
-        let config = config::get();
-        
-        let (system_prompt, user_prompt_template) = match request.prompt_name.as_str() {
-            "email_analysis" => config.get_email_analysis_prompt(),
-            "email_reply" => config.get_email_reply_prompt(),
-            _ => ("You are a helpful assistant.", "{{content}}"),
+pub trait LlmClient: Send + Sync {
+    async fn generate_response(&self, request: &LlmRequest) -> LlmResult<LlmResponse>;
+
+    /// 逐段返回生成内容；不支持流式的后端一次性返回完整结果
+    async fn stream_response(&self, request: &LlmRequest) -> LlmResult<LlmStream> {
+        let response = self.generate_response(request).await?;
+        let chunk = StreamChunk {
+            delta: response.content,
+            usage: Some(response.usage),
+            finish_reason: Some("stop".to_string()),
         };
+        Ok(LlmStream::new(response.model, response.backend, stream::iter([Ok(chunk)])))
+    }
+
+    /// 通过流式接口生成完整回复，超时只限制两段输出之间的间隔
+    ///
+    /// 流在完成前中断时整个请求重试或换后端，不会拼接两次输出；
+    /// 包装其他客户端的实现应转发到内层的 `generate_streamed`，重试和回退才能生效。
+    async fn generate_streamed(&self, request: &LlmRequest) -> LlmResult<LlmResponse> {
+        self.stream_response(request).await?.into_response(request.id).await
+    }
+}
 
-        let user_prompt = self.render_prompt(user_prompt_template, &request.context);
+/// 根据 `[llm] provider` 创建客户端；配置了备用后端时用带熔断的回退链包装，开启缓存时再包一层缓存
+pub fn create_client(config: &LlmConfig, prompts: &PromptConfig) -> LlmResult<Box<dyn LlmClient>> {
+    let client: Box<dyn LlmClient> = match config.replay.mode {
+        ReplayMode::Replay => Box::new(ReplayClient::open(&config.replay.dir, &config.model, PromptRegistry::from_config(prompts)?)?),
+        ReplayMode::Off => create_chain(config, prompts)?,
+        // 录制在缓存外面，命中缓存的请求同样留下录制
+        ReplayMode::Record => Box::new(RecordingClient::new(
+            create_chain(config, prompts)?,
+            &config.replay.dir,
+            &config.model,
+            PromptRegistry::from_config(prompts)?,
+        )?),
+    };
 
-        let messages = vec![
-            ChatMessage {
-                role: "system".to_string(),
-                content: system_prompt.to_string(),
-            },
-            ChatMessage {
-                role: "user".to_string(),
-                content: user_prompt,
-            },
-        ];
+    if prompts.settings.is_empty() {
+        return Ok(client);
+    }
+    // 在最外层补上每个提示词的参数，缓存和录制的键都包含它们
+    Ok(Box::new(SettingsClient::new(client, prompts.settings.clone())))
+}
 
+/// 主后端和备用后端组成的回退链，开启缓存时包上缓存
+fn create_chain(config: &LlmConfig, prompts: &PromptConfig) -> LlmResult<Box<dyn LlmClient>> {
+    let primary = create_backend(config, PromptRegistry::from_config(prompts)?)?;
+    let client = if config.fallbacks.is_empty() {
+        primary
+    } else {
+        let mut chain = FallbackClient::new(&config.circuit_breaker).with_backend(config.backend_name(), primary);
+        for fallback in &config.fallbacks {
+            let name = fallback.name.clone().unwrap_or_else(|| fallback.provider.to_lowercase());
+            let client = create_backend(&config.fallback_config(fallback), PromptRegistry::from_config(prompts)?)?;
+            chain = chain.with_backend_serving(name, client, fallback.models.clone());
+        }
+        Box::new(chain)
+    };
+
+    if !config.cache.enabled {
+        return Ok(client);
+    }
+    Ok(Box::new(CachingClient::new(client, &config.cache, &config.model, PromptRegistry::from_config(prompts)?)?))
+}
+
+fn create_backend(config: &LlmConfig, prompts: PromptRegistry) -> LlmResult<Box<dyn LlmClient>> {
+    match config.provider.to_lowercase().as_str() {
+        "deepseek" | "openai" | "openai_compatible" | "vllm" | "llamacpp" | "llama.cpp" => {
+            Ok(Box::new(OpenAiClient::new(config, prompts)?))
+        }
+        "ollama" => Ok(Box::new(OllamaClient::new(config, prompts)?)),
+        "anthropic" => Ok(Box::new(AnthropicClient::new(config, prompts)?)),
+        other => Err(LlmError::ConfigurationError(format!("Unknown LLM provider '{}'", other))),
+    }
+}
+
+/// 各提供方共用的 HTTP 客户端，超时覆盖整次请求
+pub(super) fn http_client(config: &LlmConfig) -> LlmResult<Client> {
+    Client::builder()
+        .timeout(Duration::from_secs(config.timeout))
+        .build()
+        .map_err(|e| LlmError::ConfigurationError(e.to_string()))
+}
+
+/// 流式请求用的 HTTP 客户端：生成可能持续很久，只限制连接时间，读取超时由调用方按段控制
+pub(super) fn stream_http_client(config: &LlmConfig) -> LlmResult<Client> {
+    Client::builder()
+        .connect_timeout(Duration::from_secs(config.timeout))
+        .build()
+        .map_err(|e| LlmError::ConfigurationError(e.to_string()))
+}
+
+/// 未配置 `base_url` 时使用提供方的默认地址
+pub(super) fn base_url(config: &LlmConfig, default: &str) -> String {
+    let url = if config.base_url.is_empty() { default } else { config.base_url.as_str() };
+    url.trim_end_matches('/').to_string()
+}
+
+/// 托管服务需要 API key，本地服务可以不设置
+pub(super) fn require_api_key(config: &LlmConfig) -> LlmResult<()> {
+    if config.api_key.is_empty() {
+        tracing::warn!("LLM API key not configured. Set SENTIO_LLM_API_KEY environment variable or add it to sentio.toml");
+        return Err(LlmError::ConfigurationError(format!("LLM API key not configured for provider '{}'", config.provider)));
+    }
+    Ok(())
+}
+
+/// 渲染提示词，得到系统提示、历史对话和本轮用户消息
+pub(super) fn render_messages(prompts: &PromptRegistry, request: &LlmRequest) -> LlmResult<Vec<ChatMessage>> {
+    let prompt = prompts.render(&request.prompt_name, &request.context)?;
+
+    let mut messages = Vec::with_capacity(request.history.len() + 2);
+    messages.push(ChatMessage::system(prompt.system));
+    messages.extend(request.history.iter().cloned());
+    messages.push(ChatMessage::user(prompt.user));
+    messages.extend(request.tool_messages.iter().cloned());
+    Ok(messages)
+}
+
+/// 把非 2xx 响应归类为对应的错误
+pub(super) async fn status_error(response: Response) -> LlmError {
+    let status = response.status();
+    let retry_after = response
+        .headers()
+        .get(reqwest::header::RETRY_AFTER)
+        .and_then(|value| value.to_str().ok())
+        .and_then(parse_retry_after);
+    let body = response.text().await.unwrap_or_default();
+    match status.as_u16() {
+        401 | 403 => LlmError::AuthenticationFailed(format!("API returned status {}: {}", status, body)),
+        429 => LlmError::RateLimited(retry_after),
+        // 529 为 Anthropic 的过载状态
+        500 | 502 | 503 | 504 | 529 => LlmError::ServerError {
+            status: status.as_u16(),
+            message: body,
+            retry_after,
+        },
+        _ => LlmError::ApiRequestFailed(format!("API returned status {}: {}", status, body)),
+    }
+}
+
+/// Retry-After 的值可以是秒数或 HTTP 日期
+pub(super) fn parse_retry_after(value: &str) -> Option<Duration> {
+    let value = value.trim();
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+    let date = chrono::DateTime::parse_from_rfc2822(value).ok()?;
+    Some((date.with_timezone(&chrono::Utc) - chrono::Utc::now()).to_std().unwrap_or(Duration::ZERO))
+}
+
+/// 重试策略：有 Retry-After 时按其等待，否则按带抖动的指数退避，总耗时不超过 `deadline`
+#[derive(Debug, Clone)]
+pub(super) struct RetryPolicy {
+    max_retries: u32,
+    deadline: Duration,
+    base_delay: Duration,
+    max_delay: Duration,
+}
+
+impl RetryPolicy {
+    pub(super) fn from_config(config: &LlmConfig) -> Self {
+        Self {
+            max_retries: config.max_retries,
+            deadline: Duration::from_secs(config.retry_deadline),
+            base_delay: Duration::from_secs(1),
+            max_delay: Duration::from_secs(60),
+        }
+    }
+
+    /// 第 `retry` 次重试前的等待：退避上限的一半加上随机的另一半，避免多个请求同时重试
+    fn backoff(&self, retry: u32) -> Duration {
+        let ceiling = self.base_delay.saturating_mul(1 << retry.saturating_sub(1).min(16)).min(self.max_delay);
+        let half = ceiling / 2;
+        half + half.mul_f64(rand::random::<f64>())
+    }
+
+    pub(super) async fn run<T, F, Fut>(&self, mut call: F) -> LlmResult<T>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = LlmResult<T>>,
+    {
+        let started = Instant::now();
         let mut retries = 0;
         loop {
-            match self.call_api(messages.clone()).await {
-                Ok(response) => {
-                    return Ok(LlmResponse {
-                        request_id: request.id,
-                        content: response.choices[0].message.content.clone(),
-                        model: response.model,
-                        usage: TokenUsage {
-                            prompt_tokens: response.usage.prompt_tokens,
-                            completion_tokens: response.usage.completion_tokens,
-                            total_tokens: response.usage.total_tokens,
-                        },
-                    });
-                }
-                Err(e) => {
-                    if retries >= self.max_retries {
-                        return Err(e);
-                    }
-                    
-                    match &e {
-                        LlmError::NetworkError(_) | 
-                        LlmError::RateLimited(_) => {
-                            retries += 1;
-                            tokio::time::sleep(Duration::from_secs(2u64.pow(retries))).await;
-                        }
-                        _ => return Err(e),
-                    }
-                }
+            let error = match call().await {
+                Ok(result) => return Ok(result),
+                Err(e) => e,
+            };
+            if !error.is_retryable() || retries >= self.max_retries {
+                return Err(error);
             }
+
+            retries += 1;
+            let delay = error.retry_after().unwrap_or_else(|| self.backoff(retries));
+            if started.elapsed() + delay > self.deadline {
+                warn!(error = %error, "Giving up on LLM request: waiting {:?} would exceed the {:?} retry deadline", delay, self.deadline);
+                return Err(error);
+            }
+            warn!(error = %error, "LLM request failed, retry {}/{} in {:?}", retries, self.max_retries, delay);
+            tokio::time::sleep(delay).await;
         }
     }
 }
 
-#[derive(Serialize)]
-struct DeepSeekRequest {
-    model: String,
-    messages: Vec<ChatMessage>,
-    temperature: f32,
-    max_tokens: u32,
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ChatMessage {
+    pub role: String,
+    pub content: String,
+    /// assistant 消息中模型发起的工具调用
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tool_calls: Vec<ToolCall>,
+    /// tool 消息对应的调用 ID
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tool_call_id: Option<String>,
 }
 
-#[derive(Clone, Serialize, Deserialize)]
-struct ChatMessage {
-    role: String,
-    content: String,
-}
+impl ChatMessage {
+    pub fn system(content: impl Into<String>) -> Self {
+        Self {
+            role: "system".to_string(),
+            content: content.into(),
+            ..Default::default()
+        }
+    }
 
-#[derive(Deserialize)]
-struct DeepSeekResponse {
-    model: String,
-    choices: Vec<Choice>,
-    usage: Usage,
-}
+    pub fn user(content: impl Into<String>) -> Self {
+        Self {
+            role: "user".to_string(),
+            content: content.into(),
+            ..Default::default()
+        }
+    }
 
-#[derive(Deserialize)]
-struct Choice {
-    message: ChatMessage,
+    pub fn assistant(content: impl Into<String>) -> Self {
+        Self {
+            role: "assistant".to_string(),
+            content: content.into(),
+            ..Default::default()
+        }
+    }
+
+    /// 模型请求调用工具的 assistant 消息
+    pub fn tool_request(content: impl Into<String>, tool_calls: Vec<ToolCall>) -> Self {
+        Self {
+            tool_calls,
+            ..Self::assistant(content)
+        }
+    }
+
+    /// 一次工具调用的结果
+    pub fn tool_result(tool_call_id: impl Into<String>, content: impl Into<String>) -> Self {
+        Self {
+            role: "tool".to_string(),
+            content: content.into(),
+            tool_call_id: Some(tool_call_id.into()),
+            ..Default::default()
+        }
+    }
 }
 
-#[derive(Deserialize)]
-struct Usage {
-    prompt_tokens: u32,
-    completion_tokens: u32,
-    total_tokens: u32,
-}
\ No newline at end of file
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// 在本机随机端口上启动模拟的 API 服务，返回根地址
+    pub(crate) async fn mock_server(app: axum::Router) -> String {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        base
+    }
+
+    pub(crate) fn test_config(provider: &str, base_url: &str) -> LlmConfig {
+        LlmConfig {
+            provider: provider.to_string(),
+            api_key: "test-key".to_string(),
+            base_url: base_url.to_string(),
+            model: "test-model".to_string(),
+            timeout: 5,
+            max_retries: 0,
+            retry_deadline: 5,
+            history_turns: 0,
+            fallbacks: Vec::new(),
+            circuit_breaker: Default::default(),
+            cache: Default::default(),
+            replay: Default::default(),
+            context: Default::default(),
+        }
+    }
+
+    pub(crate) fn request() -> LlmRequest {
+        let mut context = HashMap::new();
+        context.insert("email_content".to_string(), serde_json::json!("Subject: 周报"));
+        context.insert("quoted_content".to_string(), serde_json::json!(""));
+        LlmRequest::new("email_analysis".to_string(), context)
+    }
+
+    /// 测试用模型的回复：模型为请求指定的模型或 `m`，后端为 `scripted`，用量为零
+    pub(crate) fn response(request: &LlmRequest, content: impl Into<String>) -> LlmResponse {
+        LlmResponse {
+            request_id: request.id,
+            content: content.into(),
+            model: request.model_or("m").to_string(),
+            usage: TokenUsage::default(),
+            backend: "scripted".to_string(),
+            tool_calls: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_retry_after_and_backoff() {
+        assert_eq!(parse_retry_after(" 120 "), Some(Duration::from_secs(120)));
+        let date = (chrono::Utc::now() + chrono::Duration::seconds(30)).format("%a, %d %b %Y %H:%M:%S GMT").to_string();
+        let delay = parse_retry_after(&date).unwrap();
+        assert!(delay > Duration::from_secs(25) && delay <= Duration::from_secs(30), "{:?}", delay);
+        assert_eq!(parse_retry_after("Sun, 06 Nov 1994 08:49:37 GMT"), Some(Duration::ZERO));
+        assert_eq!(parse_retry_after("soon"), None);
+
+        let policy = RetryPolicy::from_config(&test_config("deepseek", ""));
+        for (retry, low, high) in [(1, 500, 1000), (3, 2000, 4000), (30, 30_000, 60_000)] {
+            let delay = policy.backoff(retry);
+            assert!(delay >= Duration::from_millis(low) && delay <= Duration::from_millis(high), "{} {:?}", retry, delay);
+        }
+    }
+
+    #[test]
+    fn test_create_client_by_provider() {
+        let prompts = PromptConfig::default();
+        for provider in ["deepseek", "vLLM", "ollama", "anthropic"] {
+            assert!(create_client(&test_config(provider, "http://localhost:1"), &prompts).is_ok(), "{}", provider);
+        }
+        assert!(matches!(
+            create_client(&test_config("gemini", ""), &prompts),
+            Err(LlmError::ConfigurationError(_))
+        ));
+
+        // 托管服务需要 key，自建服务需要地址
+        let mut no_key = test_config("deepseek", "");
+        no_key.api_key.clear();
+        assert!(create_client(&no_key, &prompts).is_err());
+        assert!(create_client(&test_config("vllm", ""), &prompts).is_err());
+        no_key.provider = "ollama".to_string();
+        assert!(create_client(&no_key, &prompts).is_ok());
+    }
+}
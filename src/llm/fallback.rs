@@ -0,0 +1,400 @@
+use async_trait::async_trait;
+use futures::future::BoxFuture;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tracing::{debug, info, warn};
+
+use super::client::{LlmClient, LlmError, LlmRequest, LlmResponse, LlmResult, LlmStream};
+use crate::config::CircuitBreakerConfig;
+
+/// 单个后端的熔断器状态
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum BreakerState {
+    Closed { failures: u32 },
+    Open { until: Instant },
+    /// 冷却结束后正在用一个请求试探；试探请求丢失时超过熔断时长可以再次试探
+    HalfOpen { since: Instant },
+}
+
+/// 单个后端的熔断器；流式输出结束时才知道请求是否成功，因此由流共享
+struct Breaker {
+    backend: String,
+    state: Mutex<BreakerState>,
+    failure_threshold: u32,
+    open_duration: Duration,
+}
+
+struct Backend {
+    name: String,
+    client: Box<dyn LlmClient>,
+    breaker: Arc<Breaker>,
+    /// 这个后端接受的模型覆盖，None 表示接受任何模型
+    models: Option<Vec<String>>,
+}
+
+impl Backend {
+    /// 请求指定的模型不由这个后端提供时去掉，改用后端自己的默认模型
+    fn prepare(&self, request: &LlmRequest) -> LlmRequest {
+        let mut request = request.clone();
+        if let (Some(model), Some(models)) = (&request.model, &self.models) {
+            if !models.contains(model) {
+                debug!(backend = %self.name, model = %model, "Model not served by LLM backend, using its default model");
+                request.model = None;
+            }
+        }
+        request
+    }
+}
+
+/// 按顺序尝试多个后端的客户端
+///
+/// 后端连续失败 `failure_threshold` 次后熔断，在 `open_duration` 内直接跳过；
+/// 冷却结束后放行一个试探请求，成功则恢复，失败则重新熔断。
+/// 提示词设置和预算指定的模型名是写给主后端的，只有备用后端声明提供该模型时才一并转给它。
+pub struct FallbackClient {
+    backends: Vec<Backend>,
+    failure_threshold: u32,
+    open_duration: Duration,
+}
+
+impl FallbackClient {
+    pub fn new(config: &CircuitBreakerConfig) -> Self {
+        Self {
+            backends: Vec::new(),
+            failure_threshold: config.failure_threshold.max(1),
+            open_duration: Duration::from_secs(config.open_duration),
+        }
+    }
+
+    /// 添加接受任何模型覆盖的后端，通常是主后端
+    pub fn with_backend(self, name: impl Into<String>, client: Box<dyn LlmClient>) -> Self {
+        self.push(name.into(), client, None)
+    }
+
+    /// 添加只接受 `models` 中模型覆盖的后端，其他模型名改用后端的默认模型
+    pub fn with_backend_serving(self, name: impl Into<String>, client: Box<dyn LlmClient>, models: Vec<String>) -> Self {
+        self.push(name.into(), client, Some(models))
+    }
+
+    fn push(mut self, name: String, client: Box<dyn LlmClient>, models: Option<Vec<String>>) -> Self {
+        self.backends.push(Backend {
+            breaker: Arc::new(Breaker {
+                backend: name.clone(),
+                state: Mutex::new(BreakerState::Closed { failures: 0 }),
+                failure_threshold: self.failure_threshold,
+                open_duration: self.open_duration,
+            }),
+            name,
+            client,
+            models,
+        });
+        self
+    }
+
+    /// 依次让未熔断的后端回答，返回第一个成功的结果
+    async fn first_answer<'a, F>(&'a self, request: &LlmRequest, call: F) -> LlmResult<LlmResponse>
+    where
+        F: Fn(&'a dyn LlmClient, LlmRequest) -> BoxFuture<'a, LlmResult<LlmResponse>> + Send,
+    {
+        let mut last_error = None;
+
+        for backend in &self.backends {
+            if !backend.breaker.try_acquire() {
+                debug!(backend = %backend.name, "Circuit open, skipping LLM backend");
+                continue;
+            }
+
+            match call(backend.client.as_ref(), backend.prepare(request)).await {
+                Ok(mut response) => {
+                    backend.breaker.record_success();
+                    response.backend = backend.name.clone();
+                    return Ok(response);
+                }
+                // 请求本身的问题（其他 4xx、提示词渲染失败等）换后端也一样，不计入熔断
+                Err(e) if !is_backend_failure(&e) => return Err(e),
+                Err(e) => {
+                    warn!(backend = %backend.name, error = %e, "LLM backend failed, trying next");
+                    backend.breaker.record_failure();
+                    last_error = Some(e);
+                }
+            }
+        }
+
+        Err(last_error.unwrap_or_else(|| {
+            LlmError::ApiRequestFailed("All LLM backends are unavailable (circuit open)".to_string())
+        }))
+    }
+}
+
+/// 后端本身的故障：可重试的临时错误，以及只影响这个后端的认证失败
+fn is_backend_failure(error: &LlmError) -> bool {
+    error.is_retryable() || matches!(error, LlmError::AuthenticationFailed(_))
+}
+
+impl Breaker {
+    /// 是否可以向这个后端发送请求
+    fn try_acquire(&self) -> bool {
+        let mut state = self.state.lock().unwrap();
+        let now = Instant::now();
+        match *state {
+            BreakerState::Closed { .. } => true,
+            BreakerState::Open { until } if now >= until => {
+                info!(backend = %self.backend, "Circuit half-open, probing LLM backend");
+                *state = BreakerState::HalfOpen { since: now };
+                true
+            }
+            BreakerState::HalfOpen { since } if now >= since + self.open_duration => {
+                *state = BreakerState::HalfOpen { since: now };
+                true
+            }
+            BreakerState::Open { .. } | BreakerState::HalfOpen { .. } => false,
+        }
+    }
+
+    fn record_success(&self) {
+        let mut state = self.state.lock().unwrap();
+        if matches!(*state, BreakerState::HalfOpen { .. }) {
+            info!(backend = %self.backend, "LLM backend recovered, closing circuit");
+        }
+        *state = BreakerState::Closed { failures: 0 };
+    }
+
+    fn record_failure(&self) {
+        let mut state = self.state.lock().unwrap();
+        *state = match *state {
+            BreakerState::Closed { failures } if failures + 1 < self.failure_threshold => {
+                BreakerState::Closed { failures: failures + 1 }
+            }
+            _ => {
+                warn!(backend = %self.backend, "Opening circuit for {:?}", self.open_duration);
+                BreakerState::Open { until: Instant::now() + self.open_duration }
+            }
+        };
+    }
+}
+
+#[async_trait]
+impl LlmClient for FallbackClient {
+    async fn generate_response(&self, request: &LlmRequest) -> LlmResult<LlmResponse> {
+        self.first_answer(request, |client, request| Box::pin(async move { client.generate_response(&request).await }))
+            .await
+    }
+
+    /// 每个后端的流都读完才算成功，中途断开时换下一个后端重新生成
+    async fn generate_streamed(&self, request: &LlmRequest) -> LlmResult<LlmResponse> {
+        self.first_answer(request, |client, request| Box::pin(async move { client.generate_streamed(&request).await }))
+            .await
+    }
+
+    /// 只在建立流时回退；开始输出后的错误直接交给调用方，避免拼接两个后端的内容。
+    /// 熔断器在流结束时才记录结果，总是中途断开的后端同样会被熔断
+    async fn stream_response(&self, request: &LlmRequest) -> LlmResult<LlmStream> {
+        let mut last_error = None;
+
+        for backend in &self.backends {
+            if !backend.breaker.try_acquire() {
+                debug!(backend = %backend.name, "Circuit open, skipping LLM backend");
+                continue;
+            }
+
+            match backend.client.stream_response(&backend.prepare(request)).await {
+                Ok(mut stream) => {
+                    stream.backend = backend.name.clone();
+                    let breaker = backend.breaker.clone();
+                    return Ok(stream.on_finish(move |completed| {
+                        if completed {
+                            breaker.record_success();
+                        } else {
+                            breaker.record_failure();
+                        }
+                    }));
+                }
+                Err(e) if !is_backend_failure(&e) => return Err(e),
+                Err(e) => {
+                    warn!(backend = %backend.name, error = %e, "LLM backend failed to stream, trying next");
+                    backend.breaker.record_failure();
+                    last_error = Some(e);
+                }
+            }
+        }
+
+        Err(last_error.unwrap_or_else(|| {
+            LlmError::ApiRequestFailed("All LLM backends are unavailable (circuit open)".to_string())
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::llm::client::tests::{request, response};
+    use crate::llm::client::StreamChunk;
+    use futures::stream;
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    /// 可以随时切换成功或失败的后端，记录调用次数
+    #[derive(Clone, Default)]
+    struct Switchable {
+        healthy: Arc<AtomicBool>,
+        calls: Arc<AtomicUsize>,
+        /// 建立流成功，但输出一段后就断开
+        drops_streams: Arc<AtomicBool>,
+        /// 每次请求指定的模型
+        models: Arc<Mutex<Vec<Option<String>>>>,
+        /// 以 400 拒绝请求本身
+        rejects: Arc<AtomicBool>,
+    }
+
+    #[async_trait]
+    impl LlmClient for Switchable {
+        async fn generate_response(&self, request: &LlmRequest) -> LlmResult<LlmResponse> {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            self.models.lock().unwrap().push(request.model.clone());
+            if self.rejects.load(Ordering::SeqCst) {
+                return Err(LlmError::ApiRequestFailed("API returned status 400: context length exceeded".to_string()));
+            }
+            if !self.healthy.load(Ordering::SeqCst) {
+                return Err(LlmError::ServerError {
+                    status: 503,
+                    message: "Service Unavailable".to_string(),
+                    retry_after: None,
+                });
+            }
+            Ok(response(request, "ok"))
+        }
+
+        async fn stream_response(&self, request: &LlmRequest) -> LlmResult<LlmStream> {
+            if !self.drops_streams.load(Ordering::SeqCst) {
+                let response = self.generate_response(request).await?;
+                let chunk = StreamChunk { delta: response.content, finish_reason: Some("stop".to_string()), ..Default::default() };
+                return Ok(LlmStream::new(response.model, "", stream::iter([Ok(chunk)])));
+            }
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            let chunk = StreamChunk { delta: "Dear".to_string(), ..Default::default() };
+            Ok(LlmStream::new("m", "", stream::iter([Ok(chunk)])))
+        }
+    }
+
+    fn chain(primary: &Switchable, secondary: &Switchable, open_duration: Duration) -> FallbackClient {
+        let mut client = FallbackClient::new(&CircuitBreakerConfig { failure_threshold: 2, open_duration: 0 });
+        client.open_duration = open_duration;
+        client
+            .with_backend("deepseek", Box::new(primary.clone()))
+            .with_backend("ollama", Box::new(secondary.clone()))
+    }
+
+    #[tokio::test]
+    async fn test_opens_circuit_then_probes_half_open() {
+        let (primary, secondary) = (Switchable::default(), Switchable::default());
+        secondary.healthy.store(true, Ordering::SeqCst);
+        let client = chain(&primary, &secondary, Duration::from_millis(50));
+
+        // 连续失败两次后熔断，之后不再调用主后端
+        for _ in 0..3 {
+            assert_eq!(client.generate_response(&request()).await.unwrap().backend, "ollama");
+        }
+        assert_eq!(primary.calls.load(Ordering::SeqCst), 2);
+
+        // 冷却结束后试探失败，重新熔断
+        tokio::time::sleep(Duration::from_millis(60)).await;
+        client.generate_response(&request()).await.unwrap();
+        client.generate_response(&request()).await.unwrap();
+        assert_eq!(primary.calls.load(Ordering::SeqCst), 3);
+
+        // 恢复后试探成功，熔断关闭
+        primary.healthy.store(true, Ordering::SeqCst);
+        tokio::time::sleep(Duration::from_millis(60)).await;
+        assert_eq!(client.generate_response(&request()).await.unwrap().backend, "deepseek");
+        assert_eq!(client.generate_response(&request()).await.unwrap().backend, "deepseek");
+        assert_eq!(secondary.calls.load(Ordering::SeqCst), 5);
+    }
+
+    #[tokio::test]
+    async fn test_all_backends_down_returns_error() {
+        let (primary, secondary) = (Switchable::default(), Switchable::default());
+        let client = chain(&primary, &secondary, Duration::from_secs(60));
+
+        for _ in 0..2 {
+            let err = client.generate_response(&request()).await.unwrap_err();
+            assert!(err.to_string().contains("503"), "{}", err);
+        }
+        let err = client.generate_response(&request()).await.unwrap_err();
+        assert!(err.to_string().contains("circuit open"), "{}", err);
+        assert_eq!((primary.calls.load(Ordering::SeqCst), secondary.calls.load(Ordering::SeqCst)), (2, 2));
+    }
+
+    #[tokio::test]
+    async fn test_rejected_requests_are_returned_without_opening_the_circuit() {
+        let (primary, secondary) = (Switchable::default(), Switchable::default());
+        primary.rejects.store(true, Ordering::SeqCst);
+        secondary.healthy.store(true, Ordering::SeqCst);
+        let client = chain(&primary, &secondary, Duration::from_secs(60));
+
+        // 400 是请求本身的问题：直接返回，不换后端，也不计入熔断
+        for _ in 0..3 {
+            let err = client.generate_response(&request()).await.unwrap_err();
+            assert!(err.to_string().contains("400"), "{}", err);
+        }
+        assert_eq!(secondary.calls.load(Ordering::SeqCst), 0);
+
+        primary.rejects.store(false, Ordering::SeqCst);
+        primary.healthy.store(true, Ordering::SeqCst);
+        assert_eq!(client.generate_response(&request()).await.unwrap().backend, "deepseek");
+    }
+
+    #[tokio::test]
+    async fn test_streams_that_drop_open_the_circuit() {
+        let (primary, secondary) = (Switchable::default(), Switchable::default());
+        primary.healthy.store(true, Ordering::SeqCst);
+        primary.drops_streams.store(true, Ordering::SeqCst);
+        secondary.healthy.store(true, Ordering::SeqCst);
+        let client = chain(&primary, &secondary, Duration::from_secs(60));
+
+        // 流建立成功不算成功，读完才知道结果；中途断开两次后熔断
+        for _ in 0..2 {
+            let stream = client.stream_response(&request()).await.unwrap();
+            assert_eq!(stream.backend, "deepseek");
+            assert!(stream.into_response(request().id).await.is_err());
+        }
+        let stream = client.stream_response(&request()).await.unwrap();
+        assert_eq!(stream.backend, "ollama");
+        assert_eq!(stream.into_response(request().id).await.unwrap().content, "ok");
+        assert_eq!(primary.calls.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_streamed_reply_falls_back_when_stream_drops() {
+        let (primary, secondary) = (Switchable::default(), Switchable::default());
+        primary.healthy.store(true, Ordering::SeqCst);
+        primary.drops_streams.store(true, Ordering::SeqCst);
+        secondary.healthy.store(true, Ordering::SeqCst);
+        let client = chain(&primary, &secondary, Duration::from_secs(60));
+
+        for _ in 0..3 {
+            let response = client.generate_streamed(&request()).await.unwrap();
+            assert_eq!((response.backend.as_str(), response.content.as_str()), ("ollama", "ok"));
+        }
+        // 两次中断后熔断，之后不再尝试主后端
+        assert_eq!(primary.calls.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_model_override_only_reaches_backends_that_serve_it() {
+        let (primary, local, mirror) = (Switchable::default(), Switchable::default(), Switchable::default());
+        mirror.healthy.store(true, Ordering::SeqCst);
+        let client = FallbackClient::new(&CircuitBreakerConfig { failure_threshold: 5, open_duration: 60 })
+            .with_backend("deepseek", Box::new(primary.clone()))
+            .with_backend_serving("ollama", Box::new(local.clone()), vec!["qwen2.5:7b".to_string()])
+            .with_backend_serving("mirror", Box::new(mirror.clone()), vec!["deepseek-chat".to_string()]);
+
+        client.generate_response(&request().with_model("deepseek-chat")).await.unwrap();
+        client.generate_response(&request()).await.unwrap();
+
+        // 主后端总是收到指定的模型；没有声明这个模型的备用后端改用自己的默认模型
+        let cheap = Some("deepseek-chat".to_string());
+        assert_eq!(*primary.models.lock().unwrap(), vec![cheap.clone(), None]);
+        assert_eq!(*local.models.lock().unwrap(), vec![None, None]);
+        assert_eq!(*mirror.models.lock().unwrap(), vec![cheap, None]);
+    }
+}
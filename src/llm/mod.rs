@@ -1,3 +1,12 @@
+pub mod anthropic;
+pub mod cache;
 pub mod client;
+pub mod fallback;
+pub mod ollama;
+pub mod openai;
+pub mod prompt;
+pub mod replay;
+pub mod settings;
+pub mod sse;
 
-pub use client::{LlmClient, DeepSeekClient, LlmRequest};
\ No newline at end of file
+pub use client::{create_client, LlmClient, LlmRequest, ChatMessage, ResponseFormat};
@@ -0,0 +1,233 @@
+use async_trait::async_trait;
+use reqwest::{Client, StatusCode};
+use serde::{Deserialize, Serialize};
+
+use super::client::{
+    base_url, http_client, render_messages, status_error, RetryPolicy, ChatMessage, LlmClient, LlmError, LlmRequest,
+    LlmResponse, LlmResult, ResponseFormat, TokenUsage, ToolCall,
+};
+use super::openai::ApiTool;
+use super::prompt::PromptRegistry;
+use crate::config::LlmConfig;
+
+/// Ollama 原生的 `/api/chat` 接口
+pub struct OllamaClient {
+    client: Client,
+    base_url: String,
+    model: String,
+    retry: RetryPolicy,
+    prompts: PromptRegistry,
+}
+
+impl OllamaClient {
+    pub fn new(config: &LlmConfig, prompts: PromptRegistry) -> LlmResult<Self> {
+        Ok(Self {
+            client: http_client(config)?,
+            base_url: base_url(config, "http://localhost:11434"),
+            model: config.model.clone(),
+            retry: RetryPolicy::from_config(config),
+            prompts,
+        })
+    }
+
+    async fn call_api(&self, messages: &[ChatMessage], request: &LlmRequest) -> LlmResult<ChatResponse> {
+        let request_body = ChatRequest {
+            model: request.model_or(&self.model),
+            messages: messages.iter().map(OllamaMessage::from).collect(),
+            tools: request.tools.iter().map(ApiTool::from).collect(),
+            stream: false,
+            format: match request.response_format {
+                ResponseFormat::Text => None,
+                ResponseFormat::Json => Some("json"),
+            },
+            options: Options {
+                temperature: request.sampling.temperature(),
+                num_predict: request.sampling.max_tokens(),
+                top_p: request.sampling.top_p,
+                stop: &request.sampling.stop,
+                presence_penalty: request.sampling.presence_penalty,
+                frequency_penalty: request.sampling.frequency_penalty,
+                seed: request.sampling.seed,
+            },
+        };
+
+        let response = self.client
+            .post(format!("{}/api/chat", self.base_url))
+            .json(&request_body)
+            .send()
+            .await?;
+
+        match response.status() {
+            StatusCode::OK => response.json().await.map_err(|e| LlmError::InvalidApiResponse(e.to_string())),
+            // 模型未下载时返回 404
+            StatusCode::NOT_FOUND => {
+                let body = response.text().await.unwrap_or_default();
+                Err(LlmError::ConfigurationError(format!("Ollama model '{}' not available: {}", request.model_or(&self.model), body)))
+            }
+            _ => Err(status_error(response).await),
+        }
+    }
+}
+
+#[async_trait]
+impl LlmClient for OllamaClient {
+    async fn generate_response(&self, request: &LlmRequest) -> LlmResult<LlmResponse> {
+        let messages = render_messages(&self.prompts, request)?;
+        let response = self.retry.run(|| self.call_api(&messages, request)).await?;
+
+        let message = response.message
+            .filter(|message| !message.content.trim().is_empty() || !message.tool_calls.is_empty())
+            .ok_or_else(|| LlmError::InvalidApiResponse("Ollama response has no message content".to_string()))?;
+        // Ollama 不返回调用 ID，按顺序编号
+        let tool_calls = message.tool_calls
+            .into_iter()
+            .enumerate()
+            .map(|(index, call)| ToolCall {
+                id: format!("call_{}", index),
+                name: call.function.name,
+                arguments: call.function.arguments,
+            })
+            .collect();
+        let prompt_tokens = response.prompt_eval_count.unwrap_or(0);
+        let completion_tokens = response.eval_count.unwrap_or(0);
+        Ok(LlmResponse {
+            request_id: request.id,
+            content: message.content,
+            model: response.model,
+            usage: TokenUsage {
+                prompt_tokens,
+                completion_tokens,
+                total_tokens: prompt_tokens + completion_tokens,
+            },
+            backend: "ollama".to_string(),
+            tool_calls,
+        })
+    }
+}
+
+#[derive(Serialize)]
+struct ChatRequest<'a> {
+    model: &'a str,
+    messages: Vec<OllamaMessage>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    tools: Vec<ApiTool<'a>>,
+    stream: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    format: Option<&'static str>,
+    options: Options<'a>,
+}
+
+#[derive(Serialize)]
+struct Options<'a> {
+    temperature: f32,
+    num_predict: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    top_p: Option<f32>,
+    #[serde(skip_serializing_if = "<[String]>::is_empty")]
+    stop: &'a [String],
+    #[serde(skip_serializing_if = "Option::is_none")]
+    presence_penalty: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    frequency_penalty: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    seed: Option<u64>,
+}
+
+/// Ollama 的消息格式：工具调用没有 ID，参数直接是 JSON 对象
+#[derive(Serialize, Deserialize)]
+struct OllamaMessage {
+    role: String,
+    #[serde(default)]
+    content: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    tool_calls: Vec<OllamaToolCall>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct OllamaToolCall {
+    function: OllamaFunctionCall,
+}
+
+#[derive(Serialize, Deserialize)]
+struct OllamaFunctionCall {
+    name: String,
+    #[serde(default)]
+    arguments: serde_json::Value,
+}
+
+impl From<&ChatMessage> for OllamaMessage {
+    fn from(message: &ChatMessage) -> Self {
+        Self {
+            role: message.role.clone(),
+            content: message.content.clone(),
+            tool_calls: message.tool_calls
+                .iter()
+                .map(|call| OllamaToolCall {
+                    function: OllamaFunctionCall {
+                        name: call.name.clone(),
+                        arguments: call.arguments.clone(),
+                    },
+                })
+                .collect(),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct ChatResponse {
+    model: String,
+    message: Option<OllamaMessage>,
+    /// 提示词命中缓存时 Ollama 不返回这两个字段
+    prompt_eval_count: Option<u32>,
+    eval_count: Option<u32>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::llm::client::tests::{mock_server, request, test_config};
+    use crate::llm::client::Sampling;
+    use axum::http::StatusCode;
+    use axum::routing::post;
+    use axum::{Json, Router};
+    use std::sync::{Arc, Mutex};
+
+    #[tokio::test]
+    async fn test_chat_mapping_and_missing_model() {
+        let received = Arc::new(Mutex::new(None));
+        let captured = received.clone();
+        let app = Router::new()
+            .route(
+                "/api/chat",
+                post(move |Json(body): Json<serde_json::Value>| async move {
+                    *captured.lock().unwrap() = Some(body);
+                    Json(serde_json::json!({
+                        "model": "qwen2.5:7b",
+                        "created_at": "2025-07-11T04:00:00Z",
+                        "message": { "role": "assistant", "content": "你好" },
+                        "done": true,
+                        "prompt_eval_count": 30,
+                        "eval_count": 4
+                    }))
+                }),
+            )
+            .route(
+                "/missing/api/chat",
+                post(|| async { (StatusCode::NOT_FOUND, Json(serde_json::json!({ "error": "model 'x' not found" }))) }),
+            );
+        let base = mock_server(app).await;
+
+        let client = OllamaClient::new(&test_config("ollama", &base), PromptRegistry::builtin().unwrap()).unwrap();
+        let response = client.generate_response(&request().with_response_format(ResponseFormat::Json)).await.unwrap();
+        assert_eq!(response.content, "你好");
+        assert_eq!((response.usage.prompt_tokens, response.usage.total_tokens), (30, 34));
+
+        let body = received.lock().unwrap().take().unwrap();
+        assert_eq!((body["stream"].as_bool(), body["format"].as_str()), (Some(false), Some("json")));
+        assert_eq!(body["options"]["num_predict"], Sampling::default().max_tokens());
+
+        let missing = OllamaClient::new(&test_config("ollama", &format!("{}/missing", base)), PromptRegistry::builtin().unwrap()).unwrap();
+        let err = missing.generate_response(&request()).await.unwrap_err();
+        assert!(matches!(err, LlmError::ConfigurationError(_)), "{:?}", err);
+    }
+}
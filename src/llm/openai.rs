@@ -0,0 +1,600 @@
+use async_trait::async_trait;
+use futures::{future, StreamExt};
+use reqwest::{Client, Response, StatusCode};
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+use super::client::{
+    base_url, http_client, render_messages, require_api_key, status_error, stream_http_client, RetryPolicy,
+    ChatMessage, LlmClient, LlmError, LlmRequest, LlmResponse, LlmResult, LlmStream, ResponseFormat, StreamChunk,
+    TokenUsage, ToolCall, ToolDefinition,
+};
+use super::prompt::PromptRegistry;
+use super::sse;
+use crate::config::LlmConfig;
+
+/// OpenAI 兼容的 `/chat/completions` 接口：DeepSeek、OpenAI、vLLM、llama.cpp server 等
+pub struct OpenAiClient {
+    provider: String,
+    client: Client,
+    stream_client: Client,
+    /// 流式响应两段数据之间允许的最长间隔
+    idle_timeout: Duration,
+    api_key: String,
+    base_url: String,
+    model: String,
+    retry: RetryPolicy,
+    prompts: PromptRegistry,
+}
+
+impl OpenAiClient {
+    pub fn new(config: &LlmConfig, prompts: PromptRegistry) -> LlmResult<Self> {
+        let default_url = match config.provider.to_lowercase().as_str() {
+            "deepseek" => "https://api.deepseek.com/v1",
+            "openai" => "https://api.openai.com/v1",
+            _ => "",
+        };
+        // 自建的 vLLM / llama.cpp 服务通常不需要 key，但必须给出地址
+        if !default_url.is_empty() {
+            require_api_key(config)?;
+        }
+        let base_url = base_url(config, default_url);
+        if base_url.is_empty() {
+            return Err(LlmError::ConfigurationError(format!("llm.base_url is required for provider '{}'", config.provider)));
+        }
+
+        Ok(Self {
+            provider: config.provider.to_lowercase(),
+            client: http_client(config)?,
+            stream_client: stream_http_client(config)?,
+            idle_timeout: Duration::from_secs(config.timeout),
+            api_key: config.api_key.clone(),
+            base_url,
+            model: config.model.clone(),
+            retry: RetryPolicy::from_config(config),
+            prompts,
+        })
+    }
+
+    async fn send(&self, client: &Client, request_body: &CompletionRequest<'_>) -> LlmResult<Response> {
+        let mut request = client
+            .post(format!("{}/chat/completions", self.base_url))
+            .json(request_body);
+        if !self.api_key.is_empty() {
+            request = request.bearer_auth(&self.api_key);
+        }
+        let response = request.send().await?;
+
+        match response.status() {
+            StatusCode::OK => Ok(response),
+            _ => Err(status_error(response).await),
+        }
+    }
+
+    async fn call_api(&self, messages: &[ChatMessage], request: &LlmRequest) -> LlmResult<CompletionResponse> {
+        let request_body = CompletionRequest::new(request.model_or(&self.model), messages, request, false);
+        let response = self.send(&self.client, &request_body).await?;
+        response.json().await.map_err(|e| LlmError::InvalidApiResponse(e.to_string()))
+    }
+
+    /// 发起一次流式请求，不重试
+    async fn open_stream(&self, messages: &[ChatMessage], request: &LlmRequest) -> LlmResult<LlmStream> {
+        let request_body = CompletionRequest::new(request.model_or(&self.model), messages, request, true);
+        let response = self.send(&self.stream_client, &request_body).await?;
+
+        // `[DONE]` 转成带结束原因的一段，之后的事件忽略；没有收到它或 finish_reason 时流不算完整
+        let chunks = sse::events(response.bytes_stream(), self.idle_timeout)
+            .scan(false, |done, event| {
+                let item = match event {
+                    _ if *done => None,
+                    Ok(data) if data.trim() == "[DONE]" => {
+                        *done = true;
+                        Some(Ok(Some(StreamChunk { finish_reason: Some("stop".to_string()), ..Default::default() })))
+                    }
+                    event => Some(event.and_then(|data| parse_stream_event(&data))),
+                };
+                future::ready(item)
+            })
+            .filter_map(|item| future::ready(item.transpose()));
+        Ok(LlmStream::new(request.model_or(&self.model), self.provider.clone(), chunks))
+    }
+}
+
+/// 解析一个流式事件；只有角色等信息、没有内容的事件返回 `None`
+fn parse_stream_event(data: &str) -> LlmResult<Option<StreamChunk>> {
+    let event: StreamResponse = serde_json::from_str(data)
+        .map_err(|e| LlmError::InvalidApiResponse(format!("Invalid stream event: {}", e)))?;
+    let choice = event.choices.into_iter().next();
+    let chunk = StreamChunk {
+        delta: choice.as_ref().and_then(|choice| choice.delta.content.clone()).unwrap_or_default(),
+        usage: event.usage.map(TokenUsage::from),
+        finish_reason: choice.and_then(|choice| choice.finish_reason),
+    };
+    Ok((chunk != StreamChunk::default()).then_some(chunk))
+}
+
+#[async_trait]
+impl LlmClient for OpenAiClient {
+    async fn generate_response(&self, request: &LlmRequest) -> LlmResult<LlmResponse> {
+        let messages = render_messages(&self.prompts, request)?;
+        let response = self.retry.run(|| self.call_api(&messages, request)).await?;
+
+        let choice = response.choices.into_iter().next()
+            .ok_or_else(|| LlmError::InvalidApiResponse("Completion has no choices".to_string()))?;
+        let content = choice.message.content.unwrap_or_default();
+        let tool_calls = choice.message.tool_calls
+            .unwrap_or_default()
+            .into_iter()
+            .map(ToolCall::try_from)
+            .collect::<LlmResult<Vec<_>>>()?;
+        if content.trim().is_empty() && tool_calls.is_empty() {
+            return Err(LlmError::InvalidApiResponse("Completion content is empty".to_string()));
+        }
+        Ok(LlmResponse {
+            request_id: request.id,
+            content,
+            model: response.model,
+            usage: response.usage.map(TokenUsage::from).unwrap_or_default(),
+            backend: self.provider.clone(),
+            tool_calls,
+        })
+    }
+
+    async fn stream_response(&self, request: &LlmRequest) -> LlmResult<LlmStream> {
+        let messages = render_messages(&self.prompts, request)?;
+        // 只重试建立连接，开始输出后出错直接交给调用方
+        self.retry.run(|| self.open_stream(&messages, request)).await
+    }
+
+    /// 中断的流整体重试，已收到的部分内容丢弃
+    async fn generate_streamed(&self, request: &LlmRequest) -> LlmResult<LlmResponse> {
+        let messages = render_messages(&self.prompts, request)?;
+        self.retry
+            .run(|| async { self.open_stream(&messages, request).await?.into_response(request.id).await })
+            .await
+    }
+}
+
+#[derive(Serialize)]
+struct CompletionRequest<'a> {
+    model: &'a str,
+    messages: Vec<ApiMessage<'a>>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    tools: Vec<ApiTool<'a>>,
+    temperature: f32,
+    max_tokens: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    top_p: Option<f32>,
+    #[serde(skip_serializing_if = "<[String]>::is_empty")]
+    stop: &'a [String],
+    #[serde(skip_serializing_if = "Option::is_none")]
+    presence_penalty: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    frequency_penalty: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    seed: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    response_format: Option<ApiResponseFormat>,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    stream: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stream_options: Option<StreamOptions>,
+}
+
+impl<'a> CompletionRequest<'a> {
+    fn new(model: &'a str, messages: &'a [ChatMessage], request: &'a LlmRequest, stream: bool) -> Self {
+        Self {
+            model,
+            messages: messages.iter().map(ApiMessage::from).collect(),
+            tools: request.tools.iter().map(ApiTool::from).collect(),
+            temperature: request.sampling.temperature(),
+            max_tokens: request.sampling.max_tokens(),
+            top_p: request.sampling.top_p,
+            stop: &request.sampling.stop,
+            presence_penalty: request.sampling.presence_penalty,
+            frequency_penalty: request.sampling.frequency_penalty,
+            seed: request.sampling.seed,
+            response_format: match request.response_format {
+                ResponseFormat::Text => None,
+                ResponseFormat::Json => Some(ApiResponseFormat { kind: "json_object" }),
+            },
+            stream,
+            // 让最后一个事件带上用量
+            stream_options: stream.then_some(StreamOptions { include_usage: true }),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct StreamOptions {
+    include_usage: bool,
+}
+
+#[derive(Serialize)]
+struct ApiMessage<'a> {
+    role: &'a str,
+    content: &'a str,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    tool_calls: Vec<ApiToolCall>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tool_call_id: Option<&'a str>,
+}
+
+impl<'a> From<&'a ChatMessage> for ApiMessage<'a> {
+    fn from(message: &'a ChatMessage) -> Self {
+        Self {
+            role: &message.role,
+            content: &message.content,
+            tool_calls: message.tool_calls.iter().map(ApiToolCall::from).collect(),
+            tool_call_id: message.tool_call_id.as_deref(),
+        }
+    }
+}
+
+/// 工具定义的格式，Ollama 也使用这个格式
+#[derive(Serialize)]
+pub(super) struct ApiTool<'a> {
+    #[serde(rename = "type")]
+    kind: &'static str,
+    function: ApiToolFunction<'a>,
+}
+
+#[derive(Serialize)]
+struct ApiToolFunction<'a> {
+    name: &'a str,
+    description: &'a str,
+    parameters: &'a serde_json::Value,
+}
+
+impl<'a> From<&'a ToolDefinition> for ApiTool<'a> {
+    fn from(tool: &'a ToolDefinition) -> Self {
+        Self {
+            kind: "function",
+            function: ApiToolFunction {
+                name: &tool.name,
+                description: &tool.description,
+                parameters: &tool.parameters,
+            },
+        }
+    }
+}
+
+/// 工具调用的参数在 API 中是 JSON 字符串
+#[derive(Serialize, Deserialize)]
+struct ApiToolCall {
+    id: String,
+    #[serde(rename = "type")]
+    kind: String,
+    function: ApiFunctionCall,
+}
+
+#[derive(Serialize, Deserialize)]
+struct ApiFunctionCall {
+    name: String,
+    #[serde(default)]
+    arguments: String,
+}
+
+impl From<&ToolCall> for ApiToolCall {
+    fn from(call: &ToolCall) -> Self {
+        Self {
+            id: call.id.clone(),
+            kind: "function".to_string(),
+            function: ApiFunctionCall {
+                name: call.name.clone(),
+                arguments: call.arguments.to_string(),
+            },
+        }
+    }
+}
+
+impl TryFrom<ApiToolCall> for ToolCall {
+    type Error = LlmError;
+
+    fn try_from(call: ApiToolCall) -> LlmResult<Self> {
+        let arguments = match call.function.arguments.trim() {
+            "" => serde_json::json!({}),
+            raw => serde_json::from_str(raw).map_err(|e| {
+                LlmError::InvalidApiResponse(format!("Invalid arguments for tool '{}': {}", call.function.name, e))
+            })?,
+        };
+        Ok(Self {
+            id: call.id,
+            name: call.function.name,
+            arguments,
+        })
+    }
+}
+
+#[derive(Serialize)]
+struct ApiResponseFormat {
+    #[serde(rename = "type")]
+    kind: &'static str,
+}
+
+#[derive(Deserialize)]
+struct CompletionResponse {
+    model: String,
+    choices: Vec<Choice>,
+    /// 部分本地服务不返回用量
+    usage: Option<Usage>,
+}
+
+#[derive(Deserialize)]
+struct Choice {
+    message: ResponseMessage,
+}
+
+#[derive(Deserialize)]
+struct ResponseMessage {
+    content: Option<String>,
+    tool_calls: Option<Vec<ApiToolCall>>,
+}
+
+#[derive(Deserialize)]
+struct StreamResponse {
+    #[serde(default)]
+    choices: Vec<StreamChoice>,
+    usage: Option<Usage>,
+}
+
+#[derive(Deserialize)]
+struct StreamChoice {
+    #[serde(default)]
+    delta: Delta,
+    finish_reason: Option<String>,
+}
+
+#[derive(Deserialize, Default)]
+struct Delta {
+    content: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct Usage {
+    prompt_tokens: u32,
+    completion_tokens: u32,
+    total_tokens: u32,
+}
+
+impl From<Usage> for TokenUsage {
+    fn from(usage: Usage) -> Self {
+        Self {
+            prompt_tokens: usage.prompt_tokens,
+            completion_tokens: usage.completion_tokens,
+            total_tokens: usage.total_tokens,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::llm::client::tests::{mock_server, request, test_config};
+    use axum::http::StatusCode;
+    use axum::routing::post;
+    use axum::{Json, Router};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::{Arc, Mutex};
+    use uuid::Uuid;
+
+    #[tokio::test]
+    async fn test_chat_completion_mapping() {
+        let received = Arc::new(Mutex::new(None));
+        let captured = received.clone();
+        let app = Router::new().route(
+            "/v1/chat/completions",
+            post(move |headers: axum::http::HeaderMap, Json(body): Json<serde_json::Value>| async move {
+                *captured.lock().unwrap() = Some((headers.get("authorization").cloned(), body));
+                Json(serde_json::json!({
+                    "model": "deepseek-chat",
+                    "choices": [{ "index": 0, "message": { "role": "assistant", "content": "{\"ok\": true}" } }],
+                    "usage": { "prompt_tokens": 12, "completion_tokens": 5, "total_tokens": 17 }
+                }))
+            }),
+        );
+        let base = mock_server(app).await;
+
+        let client = OpenAiClient::new(&test_config("deepseek", &format!("{}/v1", base)), PromptRegistry::builtin().unwrap()).unwrap();
+        let response = client.generate_response(&request().with_response_format(ResponseFormat::Json)).await.unwrap();
+        assert_eq!(response.content, "{\"ok\": true}");
+        assert_eq!((response.model.as_str(), response.usage.total_tokens), ("deepseek-chat", 17));
+
+        let (auth, body) = received.lock().unwrap().take().unwrap();
+        assert_eq!(auth.unwrap(), "Bearer test-key");
+        assert_eq!(body["model"], "test-model");
+        assert_eq!(body["response_format"]["type"], "json_object");
+        assert_eq!(body["messages"][0]["role"], "system");
+        assert_eq!(body["messages"][1]["role"], "user");
+    }
+
+    #[tokio::test]
+    async fn test_tool_definitions_and_calls() {
+        let received = Arc::new(Mutex::new(None));
+        let captured = received.clone();
+        let app = Router::new().route(
+            "/chat/completions",
+            post(move |Json(body): Json<serde_json::Value>| async move {
+                *captured.lock().unwrap() = Some(body);
+                Json(serde_json::json!({
+                    "model": "m",
+                    "choices": [{ "message": { "role": "assistant", "content": null, "tool_calls": [{
+                        "id": "call_2", "type": "function",
+                        "function": { "name": "add_task", "arguments": "{\"content\":\"回电话\"}" }
+                    }] } }]
+                }))
+            }),
+        );
+        let base = mock_server(app).await;
+
+        let tool = ToolDefinition {
+            name: "add_task".to_string(),
+            description: "记录任务".to_string(),
+            parameters: serde_json::json!({ "type": "object" }),
+        };
+        let previous = ToolCall { id: "call_1".to_string(), name: "search_memory".to_string(), arguments: serde_json::json!({ "query": "电话" }) };
+        let mut tool_request = request().with_tools(vec![tool]);
+        tool_request.tool_messages = vec![ChatMessage::tool_request("", vec![previous]), ChatMessage::tool_result("call_1", "[]")];
+
+        let client = OpenAiClient::new(&test_config("vllm", &base), PromptRegistry::builtin().unwrap()).unwrap();
+        let response = client.generate_response(&tool_request).await.unwrap();
+        assert_eq!(response.tool_calls[0].name, "add_task");
+        assert_eq!(response.tool_calls[0].arguments["content"], "回电话");
+
+        let body = received.lock().unwrap().take().unwrap();
+        assert_eq!(body["tools"][0]["type"], "function");
+        assert_eq!(body["tools"][0]["function"]["name"], "add_task");
+        assert_eq!(body["messages"][2]["tool_calls"][0]["function"]["arguments"], "{\"query\":\"电话\"}");
+        assert_eq!((body["messages"][3]["role"].as_str(), body["messages"][3]["tool_call_id"].as_str()), (Some("tool"), Some("call_1")));
+    }
+
+    #[tokio::test]
+    async fn test_stream_parses_deltas_and_usage() {
+        let received = Arc::new(Mutex::new(None));
+        let captured = received.clone();
+        let app = Router::new().route(
+            "/v1/chat/completions",
+            post(move |Json(body): Json<serde_json::Value>| async move {
+                *captured.lock().unwrap() = Some(body);
+                let events = concat!(
+                    "data: {\"choices\":[{\"index\":0,\"delta\":{\"role\":\"assistant\"}}]}\n\n",
+                    ": keep-alive\n\n",
+                    "data: {\"choices\":[{\"index\":0,\"delta\":{\"content\":\"您好\"}}]}\n\n",
+                    "data: {\"choices\":[{\"index\":0,\"delta\":{\"content\":\"，收到\"},\"finish_reason\":\"stop\"}]}\n\n",
+                    "data: {\"choices\":[],\"usage\":{\"prompt_tokens\":20,\"completion_tokens\":4,\"total_tokens\":24}}\n\n",
+                    "data: [DONE]\n\n",
+                    "data: {\"choices\":[{\"index\":0,\"delta\":{\"content\":\"不应出现\"}}]}\n\n",
+                );
+                // 按 7 字节分块发送，会切在事件和中文字符中间
+                let chunks: Vec<Result<Vec<u8>, std::io::Error>> = events.as_bytes().chunks(7).map(|chunk| Ok(chunk.to_vec())).collect();
+                axum::body::Body::from_stream(futures::stream::iter(chunks))
+            }),
+        );
+        let base = mock_server(app).await;
+
+        let client = OpenAiClient::new(&test_config("deepseek", &format!("{}/v1", base)), PromptRegistry::builtin().unwrap()).unwrap();
+        let chunks: Vec<StreamChunk> = client
+            .stream_response(&request())
+            .await
+            .unwrap()
+            .map(|chunk| chunk.unwrap())
+            .collect()
+            .await;
+        let deltas: Vec<&str> = chunks.iter().map(|chunk| chunk.delta.as_str()).collect();
+        assert_eq!(deltas, vec!["您好", "，收到", "", ""]);
+        assert_eq!(chunks[1].finish_reason.as_deref(), Some("stop"));
+        assert_eq!(chunks[2].usage.as_ref().map(|usage| usage.total_tokens), Some(24));
+
+        let response = client.stream_response(&request()).await.unwrap().into_response(Uuid::nil()).await.unwrap();
+        assert_eq!((response.content.as_str(), response.usage.total_tokens), ("您好，收到", 24));
+
+        let body = received.lock().unwrap().take().unwrap();
+        assert_eq!((body["stream"].as_bool(), body["stream_options"]["include_usage"].as_bool()), (Some(true), Some(true)));
+    }
+
+    #[tokio::test]
+    async fn test_stream_closed_mid_generation_is_an_error() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let counter = calls.clone();
+        let app = Router::new().route(
+            "/v1/chat/completions",
+            post(move || async move {
+                // 前三次没有 finish_reason 和 [DONE]，连接就断开了
+                let complete = counter.fetch_add(1, Ordering::SeqCst) >= 3;
+                let mut events = String::from(concat!(
+                    "data: {\"choices\":[{\"index\":0,\"delta\":{\"content\":\"周四下午\"}}]}\n\n",
+                    "data: {\"choices\":[{\"index\":0,\"delta\":{\"content\":\"三点我\"}}]}\n\n",
+                ));
+                if complete {
+                    events.push_str("data: {\"choices\":[{\"index\":0,\"delta\":{\"content\":\"有空\"},\"finish_reason\":\"stop\"}]}\n\ndata: [DONE]\n\n");
+                }
+                axum::body::Body::from(events)
+            }),
+        );
+        let base = mock_server(app).await;
+
+        let mut config = test_config("deepseek", &format!("{}/v1", base));
+        config.max_retries = 1;
+        let client = OpenAiClient::new(&config, PromptRegistry::builtin().unwrap()).unwrap();
+        let err = client.stream_response(&request()).await.unwrap().into_response(Uuid::nil()).await.unwrap_err();
+        assert!(matches!(err, LlmError::StreamInterrupted(ref message) if message.contains("before the response was complete")), "{:?}", err);
+
+        // 不完整的回复也不会交给缓存或录制
+        let saved = Arc::new(Mutex::new(false));
+        let flag = saved.clone();
+        let stream = client.stream_response(&request()).await.unwrap().on_complete(Uuid::nil(), move |_| async move {
+            *flag.lock().unwrap() = true;
+        });
+        let _: Vec<_> = stream.collect().await;
+        assert!(!*saved.lock().unwrap());
+
+        // 生成完整回复时中断的流整体重试，不拼接前一次的内容
+        let response = client.generate_streamed(&request()).await.unwrap();
+        assert_eq!(response.content, "周四下午三点我有空");
+        assert_eq!(calls.load(Ordering::SeqCst), 4);
+    }
+
+    #[tokio::test]
+    async fn test_errors_are_classified() {
+        let app = Router::new()
+            .route("/unauthorized/chat/completions", post(|| async { (StatusCode::UNAUTHORIZED, "bad key") }))
+            .route("/limited/chat/completions", post(|| async { (StatusCode::TOO_MANY_REQUESTS, "slow down") }))
+            .route("/empty/chat/completions", post(|| async { Json(serde_json::json!({ "model": "m", "choices": [] })) }))
+            .route(
+                "/malformed/chat/completions",
+                post(|| async { Json(serde_json::json!({ "model": "m", "choices": [{ "message": { "role": "assistant", "content": null } }] })) }),
+            );
+        let base = mock_server(app).await;
+
+        let generate = |path: &str| {
+            let client = OpenAiClient::new(&test_config("vllm", &format!("{}/{}", base, path)), PromptRegistry::builtin().unwrap()).unwrap();
+            async move { client.generate_response(&request()).await.unwrap_err() }
+        };
+        assert!(matches!(generate("unauthorized").await, LlmError::AuthenticationFailed(_)));
+        assert!(matches!(generate("limited").await, LlmError::RateLimited(_)));
+        assert!(matches!(generate("empty").await, LlmError::InvalidApiResponse(_)));
+        assert!(matches!(generate("malformed").await, LlmError::InvalidApiResponse(_)));
+    }
+
+    #[tokio::test]
+    async fn test_retries_server_errors_within_deadline() {
+        let calls = Arc::new(Mutex::new(0));
+        let counter = calls.clone();
+        let app = Router::new()
+            .route(
+                "/flaky/chat/completions",
+                post(move || async move {
+                    let mut calls = counter.lock().unwrap();
+                    *calls += 1;
+                    match *calls {
+                        1 => (StatusCode::SERVICE_UNAVAILABLE, [("retry-after", "0")], Json(serde_json::json!({}))),
+                        2 => (StatusCode::TOO_MANY_REQUESTS, [("retry-after", "0")], Json(serde_json::json!({}))),
+                        _ => (
+                            StatusCode::OK,
+                            [("retry-after", "0")],
+                            Json(serde_json::json!({ "model": "m", "choices": [{ "message": { "role": "assistant", "content": "ok" } }] })),
+                        ),
+                    }
+                }),
+            )
+            .route(
+                "/busy/chat/completions",
+                post(|| async { (StatusCode::TOO_MANY_REQUESTS, [("retry-after", "120")], "slow down") }),
+            );
+        let base = mock_server(app).await;
+
+        let mut config = test_config("vllm", &format!("{}/flaky", base));
+        config.max_retries = 3;
+        let client = OpenAiClient::new(&config, PromptRegistry::builtin().unwrap()).unwrap();
+        assert_eq!(client.generate_response(&request()).await.unwrap().content, "ok");
+        assert_eq!(*calls.lock().unwrap(), 3);
+
+        // 等待时间超过重试期限时立即放弃
+        config.base_url = format!("{}/busy", base);
+        let client = OpenAiClient::new(&config, PromptRegistry::builtin().unwrap()).unwrap();
+        let started = std::time::Instant::now();
+        let err = client.generate_response(&request()).await.unwrap_err();
+        assert_eq!(err.retry_after(), Some(std::time::Duration::from_secs(120)));
+        assert!(started.elapsed() < std::time::Duration::from_secs(1));
+    }
+}
@@ -0,0 +1,220 @@
+use minijinja::{Environment, ErrorKind, UndefinedBehavior};
+use std::collections::HashMap;
+use std::path::Path;
+
+use super::client::{LlmError, LlmResult};
+use crate::config::{PromptConfig, PromptTemplate};
+
+/// 内置提示词，与仓库 `prompts/` 目录中的文件保持一致
+const BUILTIN_PROMPTS: [(&str, &str); 4] = [
+    ("email_analysis", include_str!("../../prompts/email_analysis.toml")),
+    ("email_analysis_repair", include_str!("../../prompts/email_analysis_repair.toml")),
+    ("email_chunk_summary", include_str!("../../prompts/email_chunk_summary.toml")),
+    ("email_reply", include_str!("../../prompts/email_reply.toml")),
+];
+
+/// 渲染后的提示词
+#[derive(Debug, Clone)]
+pub struct RenderedPrompt {
+    pub system: String,
+    pub user: String,
+}
+
+/// 具名提示词模板集合
+///
+/// 模板使用 Jinja 语法（`{{ var }}`、`{% if %}`、`{% for %}`），
+/// 引用未提供的变量时渲染直接报错，不会把占位符原样发给模型。
+pub struct PromptRegistry {
+    env: Environment<'static>,
+}
+
+impl PromptRegistry {
+    /// 只包含内置提示词
+    pub fn builtin() -> LlmResult<Self> {
+        let mut env = Environment::new();
+        env.set_undefined_behavior(UndefinedBehavior::Strict);
+        env.set_trim_blocks(true);
+        env.set_lstrip_blocks(true);
+
+        let mut registry = Self { env };
+        for (name, source) in BUILTIN_PROMPTS {
+            registry.insert(name, &parse_prompt_file(name, source)?)?;
+        }
+        Ok(registry)
+    }
+
+    /// 依次加载内置提示词、`dir` 目录下的 `<name>.toml` 文件和配置中的 `templates`，后者覆盖前者
+    pub fn from_config(config: &PromptConfig) -> LlmResult<Self> {
+        let mut registry = Self::builtin()?;
+
+        if config.dir.is_dir() {
+            registry.load_dir(&config.dir)?;
+        } else {
+            tracing::debug!("Prompt directory {} not found, using built-in prompts", config.dir.display());
+        }
+
+        for (name, template) in &config.templates {
+            registry.insert(name, template)?;
+        }
+        Ok(registry)
+    }
+
+    fn load_dir(&mut self, dir: &Path) -> LlmResult<()> {
+        let entries = std::fs::read_dir(dir)
+            .map_err(|e| LlmError::TemplateError(format!("Failed to read {}: {}", dir.display(), e)))?;
+
+        for entry in entries {
+            let path = entry.map_err(|e| LlmError::TemplateError(e.to_string()))?.path();
+            if path.extension().and_then(|ext| ext.to_str()) != Some("toml") {
+                continue;
+            }
+            let Some(name) = path.file_stem().and_then(|stem| stem.to_str()) else {
+                continue;
+            };
+
+            let source = std::fs::read_to_string(&path)
+                .map_err(|e| LlmError::TemplateError(format!("Failed to read {}: {}", path.display(), e)))?;
+            self.insert(name, &parse_prompt_file(name, &source)?)?;
+            tracing::debug!("Loaded prompt '{}' from {}", name, path.display());
+        }
+        Ok(())
+    }
+
+    /// 注册或覆盖一个提示词，模板语法错误会在这里报出
+    pub fn insert(&mut self, name: &str, template: &PromptTemplate) -> LlmResult<()> {
+        for (part, source) in [("system", &template.system), ("user", &template.user)] {
+            self.env
+                .add_template_owned(format!("{}.{}", name, part), source.trim().to_string())
+                .map_err(|e| LlmError::TemplateError(format!("Invalid {} template for prompt '{}': {}", part, name, e)))?;
+        }
+        Ok(())
+    }
+
+    pub fn render(&self, name: &str, context: &HashMap<String, serde_json::Value>) -> LlmResult<RenderedPrompt> {
+        Ok(RenderedPrompt {
+            system: self.render_part(name, "system", context)?,
+            user: self.render_part(name, "user", context)?,
+        })
+    }
+
+    fn render_part(&self, name: &str, part: &str, context: &HashMap<String, serde_json::Value>) -> LlmResult<String> {
+        let template = self.env.get_template(&format!("{}.{}", name, part)).map_err(|e| match e.kind() {
+            ErrorKind::TemplateNotFound => LlmError::TemplateError(format!("Unknown prompt '{}'", name)),
+            _ => LlmError::TemplateError(e.to_string()),
+        })?;
+
+        template
+            .render(context)
+            .map_err(|e| LlmError::TemplateError(format!("Failed to render {} prompt '{}': {}", part, name, e)))
+    }
+}
+
+fn parse_prompt_file(name: &str, source: &str) -> LlmResult<PromptTemplate> {
+    toml::from_str(source).map_err(|e| LlmError::TemplateError(format!("Invalid prompt file for '{}': {}", name, e)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn context(pairs: &[(&str, serde_json::Value)]) -> HashMap<String, serde_json::Value> {
+        pairs.iter().map(|(k, v)| (k.to_string(), v.clone())).collect()
+    }
+
+    #[test]
+    fn test_builtin_prompts_substitute_variables() {
+        let registry = PromptRegistry::builtin().unwrap();
+
+        let analysis = registry
+            .render(
+                "email_analysis",
+                &context(&[
+                    ("email_content", json!("Subject: 项目进展\n\n最新进展如何？")),
+                    ("quoted_content", json!("> 上周已经完成设计评审。")),
+                ]),
+            )
+            .unwrap();
+        assert!(analysis.user.contains("最新进展如何？"));
+        assert!(analysis.user.contains("背景参考") && analysis.user.contains("> 上周已经完成设计评审。"));
+        assert!(!analysis.user.contains("{{"));
+
+        let reply = registry
+            .render(
+                "email_reply",
+                &context(&[
+                    ("original_email", json!("原文")),
+                    ("quoted_email", json!("")),
+                    ("analysis_result", json!("工作相关")),
+                    ("memories", json!(["喜欢简短回复", "项目 A 负责人"])),
+                    ("persona", json!("项目经理")),
+                    ("language", json!("English")),
+                ]),
+            )
+            .unwrap();
+        assert!(reply.user.contains("- 喜欢简短回复\n- 项目 A 负责人"));
+        assert!(reply.system.contains("项目经理"));
+        assert!(reply.user.contains("请使用English回复。"));
+
+        let without_memories = registry
+            .render(
+                "email_reply",
+                &context(&[
+                    ("original_email", json!("原文")),
+                    ("quoted_email", json!("")),
+                    ("analysis_result", json!("工作相关")),
+                    ("memories", json!([])),
+                    ("persona", json!(null)),
+                    ("language", json!(null)),
+                ]),
+            )
+            .unwrap();
+        assert!(!without_memories.user.contains("已知信息"));
+        assert!(!without_memories.user.contains("引用的历史邮件"));
+        assert!(!without_memories.user.contains("请使用"));
+    }
+
+    #[test]
+    fn test_missing_variable_and_unknown_prompt_fail() {
+        let registry = PromptRegistry::builtin().unwrap();
+
+        let err = registry.render("email_reply", &context(&[("original_email", json!("原文"))])).unwrap_err();
+        assert!(matches!(err, LlmError::TemplateError(_)));
+
+        let err = registry.render("no_such_prompt", &HashMap::new()).unwrap_err();
+        assert!(err.to_string().contains("Unknown prompt 'no_such_prompt'"));
+    }
+
+    #[test]
+    fn test_directory_and_config_overrides() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(
+            dir.path().join("email_analysis.toml"),
+            "system = \"from dir\"\nuser = \"{{ email_content }}\"\n",
+        )
+        .unwrap();
+        std::fs::write(dir.path().join("greeting.toml"), "system = \"from dir\"\nuser = \"hi {{ name }}\"\n").unwrap();
+
+        let mut templates = HashMap::new();
+        templates.insert(
+            "greeting".to_string(),
+            PromptTemplate {
+                system: "from config".to_string(),
+                user: "hello {{ name }}".to_string(),
+            },
+        );
+        let registry = PromptRegistry::from_config(&PromptConfig {
+            dir: dir.path().to_path_buf(),
+            templates,
+            settings: HashMap::new(),
+        })
+        .unwrap();
+
+        let analysis = registry.render("email_analysis", &context(&[("email_content", json!("x"))])).unwrap();
+        assert_eq!(analysis.system, "from dir");
+
+        let greeting = registry.render("greeting", &context(&[("name", json!("张三"))])).unwrap();
+        assert_eq!(greeting.system, "from config");
+        assert_eq!(greeting.user, "hello 张三");
+    }
+}
@@ -0,0 +1,220 @@
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tracing::{debug, warn};
+use uuid::Uuid;
+
+use super::cache::request_key;
+use super::client::{
+    render_messages, ChatMessage, LlmClient, LlmError, LlmRequest, LlmResponse, LlmResult, LlmStream, ResponseFormat,
+    Sampling, ToolDefinition,
+};
+use super::prompt::PromptRegistry;
+
+/// 一次录制的模型调用
+///
+/// 回放时按文件内容重新计算键，手工修改过的录制同样可以匹配。
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Fixture {
+    prompt_name: String,
+    model: String,
+    #[serde(default)]
+    response_format: ResponseFormat,
+    #[serde(default)]
+    tools: Vec<ToolDefinition>,
+    #[serde(default)]
+    sampling: Sampling,
+    messages: Vec<ChatMessage>,
+    response: LlmResponse,
+}
+
+impl Fixture {
+    fn key(&self) -> String {
+        request_key(&self.model, &self.messages, self.response_format, &self.tools, &self.sampling)
+    }
+
+    /// 录制文件名：提示词名称加键的前缀，便于在目录中查找
+    fn file_name(&self) -> String {
+        format!("{}-{}.json", self.prompt_name, &self.key()[..16])
+    }
+}
+
+/// 调用模型并把请求和响应写入录制目录的客户端
+pub struct RecordingClient {
+    inner: Box<dyn LlmClient>,
+    prompts: PromptRegistry,
+    model: String,
+    dir: Arc<PathBuf>,
+}
+
+impl RecordingClient {
+    pub fn new(inner: Box<dyn LlmClient>, dir: &Path, model: &str, prompts: PromptRegistry) -> LlmResult<Self> {
+        std::fs::create_dir_all(dir)
+            .map_err(|e| LlmError::ConfigurationError(format!("LLM recording directory {}: {}", dir.display(), e)))?;
+        Ok(Self {
+            inner,
+            prompts,
+            model: model.to_string(),
+            dir: Arc::new(dir.to_path_buf()),
+        })
+    }
+
+    /// 还没有响应的录制，响应到达后补上
+    fn fixture(&self, request: &LlmRequest) -> LlmResult<Fixture> {
+        Ok(Fixture {
+            prompt_name: request.prompt_name.clone(),
+            model: request.model_or(&self.model).to_string(),
+            response_format: request.response_format,
+            tools: request.tools.clone(),
+            sampling: request.sampling.clone(),
+            messages: render_messages(&self.prompts, request)?,
+            response: LlmResponse {
+                request_id: Uuid::nil(),
+                content: String::new(),
+                model: String::new(),
+                usage: Default::default(),
+                backend: String::new(),
+                tool_calls: Vec::new(),
+            },
+        })
+    }
+}
+
+async fn save(dir: &Path, mut fixture: Fixture, response: LlmResponse) {
+    // 请求 ID 每次都不同，录制中固定为空，重新录制时文件只在内容变化时改变
+    fixture.response = LlmResponse { request_id: Uuid::nil(), ..response };
+    let path = dir.join(fixture.file_name());
+    let json = serde_json::to_string_pretty(&fixture).expect("fixtures serialize to JSON");
+    match tokio::fs::write(&path, json + "\n").await {
+        Ok(()) => debug!(prompt = %fixture.prompt_name, "Recorded LLM response to {}", path.display()),
+        Err(e) => warn!("Failed to write LLM recording {}: {}", path.display(), e),
+    }
+}
+
+#[async_trait]
+impl LlmClient for RecordingClient {
+    async fn generate_response(&self, request: &LlmRequest) -> LlmResult<LlmResponse> {
+        let fixture = self.fixture(request)?;
+        let response = self.inner.generate_response(request).await?;
+        save(&self.dir, fixture, response.clone()).await;
+        Ok(response)
+    }
+
+    async fn stream_response(&self, request: &LlmRequest) -> LlmResult<LlmStream> {
+        let fixture = self.fixture(request)?;
+        let dir = self.dir.clone();
+        let stream = self.inner.stream_response(request).await?;
+        Ok(stream.on_complete(request.id, move |response| async move { save(&dir, fixture, response).await }))
+    }
+
+    async fn generate_streamed(&self, request: &LlmRequest) -> LlmResult<LlmResponse> {
+        let fixture = self.fixture(request)?;
+        let response = self.inner.generate_streamed(request).await?;
+        save(&self.dir, fixture, response.clone()).await;
+        Ok(response)
+    }
+}
+
+/// 只返回录制响应的客户端，不访问网络
+///
+/// 请求渲染后的消息、模型、输出格式、工具和采样参数都相同才算匹配；找不到录制时返回
+/// `LlmError::ReplayMissing`，不会退回到真实模型。回放的响应 `backend` 为 `replay`。
+pub struct ReplayClient {
+    prompts: PromptRegistry,
+    model: String,
+    dir: PathBuf,
+    fixtures: HashMap<String, Fixture>,
+}
+
+impl ReplayClient {
+    pub fn open(dir: &Path, model: &str, prompts: PromptRegistry) -> LlmResult<Self> {
+        let error = |e: String| LlmError::ConfigurationError(format!("LLM recordings {}: {}", dir.display(), e));
+        let mut fixtures = HashMap::new();
+        for entry in std::fs::read_dir(dir).map_err(|e| error(e.to_string()))? {
+            let path = entry.map_err(|e| error(e.to_string()))?.path();
+            if path.extension().and_then(|ext| ext.to_str()) != Some("json") {
+                continue;
+            }
+            let content = std::fs::read_to_string(&path).map_err(|e| error(e.to_string()))?;
+            let fixture: Fixture = serde_json::from_str(&content)
+                .map_err(|e| error(format!("invalid recording {}: {}", path.display(), e)))?;
+            fixtures.insert(fixture.key(), fixture);
+        }
+        debug!("Loaded {} LLM recordings from {}", fixtures.len(), dir.display());
+
+        Ok(Self {
+            prompts,
+            model: model.to_string(),
+            dir: dir.to_path_buf(),
+            fixtures,
+        })
+    }
+}
+
+#[async_trait]
+impl LlmClient for ReplayClient {
+    async fn generate_response(&self, request: &LlmRequest) -> LlmResult<LlmResponse> {
+        let messages = render_messages(&self.prompts, request)?;
+        let key = request_key(request.model_or(&self.model), &messages, request.response_format, &request.tools, &request.sampling);
+        let Some(fixture) = self.fixtures.get(&key) else {
+            return Err(LlmError::ReplayMissing {
+                prompt_name: request.prompt_name.clone(),
+                key,
+                dir: self.dir.display().to_string(),
+            });
+        };
+        Ok(LlmResponse {
+            request_id: request.id,
+            backend: "replay".to_string(),
+            ..fixture.response.clone()
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::llm::client::tests::{request, response};
+    use crate::llm::client::TokenUsage;
+
+    /// 回显用户消息的模型
+    struct Echo;
+
+    #[async_trait]
+    impl LlmClient for Echo {
+        async fn generate_response(&self, request: &LlmRequest) -> LlmResult<LlmResponse> {
+            Ok(LlmResponse {
+                usage: TokenUsage { prompt_tokens: 7, completion_tokens: 3, total_tokens: 10 },
+                ..response(request, format!("echo: {}", request.context["email_content"]))
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_recorded_responses_are_replayed() {
+        let dir = tempfile::tempdir().unwrap();
+        let with_email = |content: &str| {
+            let mut request = request();
+            request.context.insert("email_content".to_string(), serde_json::json!(content));
+            request
+        };
+
+        let recorder = RecordingClient::new(Box::new(Echo), dir.path(), "m", PromptRegistry::builtin().unwrap()).unwrap();
+        recorder.generate_response(&with_email("a")).await.unwrap();
+        recorder.stream_response(&with_email("b")).await.unwrap().into_response(Uuid::nil()).await.unwrap();
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 2);
+
+        let replay = ReplayClient::open(dir.path(), "m", PromptRegistry::builtin().unwrap()).unwrap();
+        let request = with_email("b");
+        let response = replay.generate_response(&request).await.unwrap();
+        assert_eq!(response.content, "echo: \"b\"");
+        assert_eq!((response.request_id, response.backend.as_str(), response.usage.total_tokens), (request.id, "replay", 10));
+
+        // 内容或模型不同都必须明确失败
+        let err = replay.generate_response(&with_email("c")).await.unwrap_err();
+        assert!(matches!(err, LlmError::ReplayMissing { ref prompt_name, .. } if prompt_name == "email_analysis"), "{:?}", err);
+        assert!(replay.generate_response(&with_email("a").with_model("other")).await.is_err());
+    }
+}
@@ -0,0 +1,120 @@
+use async_trait::async_trait;
+use std::collections::HashMap;
+
+use super::client::{LlmClient, LlmRequest, LlmResponse, LlmResult, LlmStream};
+use crate::config::PromptSettings;
+
+/// 按提示词名称补上配置的模型和采样参数
+///
+/// 请求中已经设置的值优先，例如超出预算时指定的便宜模型。
+pub struct SettingsClient {
+    inner: Box<dyn LlmClient>,
+    settings: HashMap<String, PromptSettings>,
+}
+
+impl SettingsClient {
+    pub fn new(inner: Box<dyn LlmClient>, settings: HashMap<String, PromptSettings>) -> Self {
+        Self { inner, settings }
+    }
+
+    fn prepare(&self, request: &LlmRequest) -> LlmRequest {
+        let mut request = request.clone();
+        if let Some(settings) = self.settings.get(&request.prompt_name) {
+            request.sampling = request.sampling.or(&settings.sampling);
+            if request.model.is_none() {
+                request.model = settings.model.clone();
+            }
+        }
+        request
+    }
+}
+
+#[async_trait]
+impl LlmClient for SettingsClient {
+    async fn generate_response(&self, request: &LlmRequest) -> LlmResult<LlmResponse> {
+        self.inner.generate_response(&self.prepare(request)).await
+    }
+
+    async fn stream_response(&self, request: &LlmRequest) -> LlmResult<LlmStream> {
+        self.inner.stream_response(&self.prepare(request)).await
+    }
+
+    async fn generate_streamed(&self, request: &LlmRequest) -> LlmResult<LlmResponse> {
+        self.inner.generate_streamed(&self.prepare(request)).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::PromptConfig;
+    use crate::llm::client::tests::{mock_server, request, test_config};
+    use crate::llm::openai::OpenAiClient;
+    use crate::llm::prompt::PromptRegistry;
+    use axum::routing::post;
+    use axum::{Json, Router};
+    use std::sync::{Arc, Mutex};
+
+    #[tokio::test]
+    async fn test_prompt_settings_reach_request_body() {
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let captured = received.clone();
+        let app = Router::new().route(
+            "/chat/completions",
+            post(move |Json(body): Json<serde_json::Value>| async move {
+                captured.lock().unwrap().push(body);
+                Json(serde_json::json!({
+                    "model": "m",
+                    "choices": [{ "message": { "role": "assistant", "content": "{}" } }]
+                }))
+            }),
+        );
+        let base = mock_server(app).await;
+
+        let prompts: PromptConfig = toml::from_str(
+            r#"
+            [settings.email_analysis]
+            model = "classifier"
+            temperature = 0.0
+            max_tokens = 300
+            stop = ["END"]
+            seed = 42
+            frequency_penalty = 0.5
+            "#,
+        )
+        .unwrap();
+        let inner = OpenAiClient::new(&test_config("vllm", &base), PromptRegistry::builtin().unwrap()).unwrap();
+        let client = SettingsClient::new(Box::new(inner), prompts.settings);
+
+        client.generate_response(&request()).await.unwrap();
+        let mut overridden = request().with_model("cheap");
+        overridden.sampling.max_tokens = Some(50);
+        client.generate_response(&overridden).await.unwrap();
+        let mut reply = request();
+        reply.prompt_name = "email_reply".to_string();
+        reply.context = HashMap::from([
+            ("original_email".to_string(), serde_json::json!("x")),
+            ("quoted_email".to_string(), serde_json::json!("")),
+            ("analysis_result".to_string(), serde_json::json!("{}")),
+            ("memories".to_string(), serde_json::json!([])),
+            ("persona".to_string(), serde_json::Value::Null),
+            ("language".to_string(), serde_json::Value::Null),
+        ]);
+        client.generate_response(&reply).await.unwrap();
+
+        let bodies = received.lock().unwrap();
+        assert_eq!(bodies[0]["model"], "classifier");
+        assert_eq!((bodies[0]["temperature"].as_f64(), bodies[0]["max_tokens"].as_u64()), (Some(0.0), Some(300)));
+        assert_eq!((bodies[0]["stop"][0].as_str(), bodies[0]["seed"].as_u64()), (Some("END"), Some(42)));
+        assert_eq!(bodies[0]["frequency_penalty"], 0.5);
+        assert!(bodies[0].get("top_p").is_none() && bodies[0].get("presence_penalty").is_none());
+
+        // 请求中设置的值优先
+        assert_eq!((bodies[1]["model"].as_str(), bodies[1]["max_tokens"].as_u64()), (Some("cheap"), Some(50)));
+        assert_eq!(bodies[1]["seed"], 42);
+
+        // 没有配置的提示词使用后端默认值
+        assert_eq!(bodies[2]["model"], "test-model");
+        assert!(bodies[2].get("seed").is_none());
+    }
+}
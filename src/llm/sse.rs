@@ -0,0 +1,125 @@
+use futures::stream::{self, Stream, StreamExt};
+use std::collections::VecDeque;
+use std::time::Duration;
+
+use super::client::{LlmError, LlmResult};
+
+/// 增量解析 server-sent events，只保留 `data` 字段
+///
+/// 网络分块可能切在事件甚至 UTF-8 字符中间，未结束的部分留在缓冲区等待下一块。
+#[derive(Debug, Default)]
+pub struct SseParser {
+    buffer: Vec<u8>,
+}
+
+impl SseParser {
+    /// 追加一块数据，返回其中已完整的事件
+    pub fn push(&mut self, chunk: &[u8]) -> Vec<String> {
+        self.buffer.extend_from_slice(chunk);
+        let mut events = Vec::new();
+        while let Some((end, separator)) = find_event_end(&self.buffer) {
+            let raw: Vec<u8> = self.buffer.drain(..end + separator).collect();
+            if let Some(data) = event_data(&raw[..end]) {
+                events.push(data);
+            }
+        }
+        events
+    }
+
+    /// 连接关闭时处理缓冲区中最后一个没有空行结尾的事件
+    pub fn finish(&mut self) -> Option<String> {
+        let raw = std::mem::take(&mut self.buffer);
+        event_data(&raw)
+    }
+}
+
+/// 事件以空行结束，返回事件长度和分隔符长度
+fn find_event_end(buffer: &[u8]) -> Option<(usize, usize)> {
+    (0..buffer.len()).find_map(|i| {
+        [&b"\r\n\r\n"[..], b"\n\n", b"\r\r"]
+            .iter()
+            .find(|separator| buffer[i..].starts_with(separator))
+            .map(|separator| (i, separator.len()))
+    })
+}
+
+fn event_data(raw: &[u8]) -> Option<String> {
+    let text = String::from_utf8_lossy(raw);
+    let data: Vec<&str> = text
+        .lines()
+        .filter_map(|line| line.strip_prefix("data:"))
+        .map(|value| value.strip_prefix(' ').unwrap_or(value))
+        .collect();
+    (!data.is_empty()).then(|| data.join("\n"))
+}
+
+/// 把响应体转换为事件数据流；超过 `idle_timeout` 没有收到数据时以错误结束
+pub fn events<S, B, E>(body: S, idle_timeout: Duration) -> impl Stream<Item = LlmResult<String>> + Send
+where
+    S: Stream<Item = Result<B, E>> + Send + 'static,
+    B: AsRef<[u8]>,
+    E: Into<LlmError>,
+{
+    struct State<S> {
+        body: std::pin::Pin<Box<S>>,
+        parser: SseParser,
+        pending: VecDeque<String>,
+        closed: bool,
+    }
+
+    let state = State {
+        body: Box::pin(body),
+        parser: SseParser::default(),
+        pending: VecDeque::new(),
+        closed: false,
+    };
+
+    stream::unfold(state, move |mut state| async move {
+        loop {
+            if let Some(data) = state.pending.pop_front() {
+                return Some((Ok(data), state));
+            }
+            if state.closed {
+                return None;
+            }
+
+            match tokio::time::timeout(idle_timeout, state.body.next()).await {
+                Err(_) => {
+                    state.closed = true;
+                    let error = LlmError::StreamInterrupted(format!("no data from stream for {:?}", idle_timeout));
+                    return Some((Err(error), state));
+                }
+                Ok(Some(Err(e))) => {
+                    state.closed = true;
+                    return Some((Err(e.into()), state));
+                }
+                Ok(Some(Ok(chunk))) => state.pending.extend(state.parser.push(chunk.as_ref())),
+                Ok(None) => {
+                    state.closed = true;
+                    state.pending.extend(state.parser.finish());
+                }
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parser_handles_split_chunks() {
+        let mut parser = SseParser::default();
+        let body = "data: {\"a\":\"你好\"}\n\n: keep-alive\n\nevent: message\r\ndata: line1\r\ndata: line2\r\n\r\ndata: [DONE]";
+        let bytes = body.as_bytes();
+
+        // 每次只送入 3 个字节，会切在中文字符中间
+        let mut events = Vec::new();
+        for chunk in bytes.chunks(3) {
+            events.extend(parser.push(chunk));
+        }
+        events.extend(parser.finish());
+
+        assert_eq!(events, vec!["{\"a\":\"你好\"}", "line1\nline2", "[DONE]"]);
+    }
+}
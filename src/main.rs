@@ -1,12 +1,27 @@
 use anyhow::Result;
 use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
 
+mod analysis;
+mod approval;
+mod auth;
 mod config;
+mod context;
+mod digest;
 mod telemetry;
+mod tools;
+mod usage;
 mod workflow;
 mod email;
 mod llm;
 mod memory;
+mod routing;
+mod senders;
+mod server;
+
+#[cfg(test)]
+mod integration_tests;
 
 use crate::memory::MemoryStore;
 
@@ -19,8 +34,16 @@ async fn main() -> Result<()> {
     config::initialize().await?;
     eprintln!("✅ 配置初始化完成");
 
-    // 初始化日志
     let config = config::get();
+
+    // 发件队列管理命令：sentio queue list | sentio queue replay <id>|all
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.first().map(String::as_str) == Some("queue") {
+        let queue = email::OutboundQueue::open(config.email.queue.clone())?;
+        return queue_command(&queue, &args[1..]).await;
+    }
+
+    // 初始化日志
     let log_dir = PathBuf::from("logs");
     telemetry::init(&config.telemetry, Some(&log_dir))?;
 
@@ -34,18 +57,87 @@ async fn main() -> Result<()> {
     tracing::info!(
         log_level = ?config.telemetry.log_level,
         llm_provider = %config.llm.provider,
-        allowed_sender = %config.email.allowed_sender,
+        allowed_senders = config.email.senders.len() + usize::from(!config.email.allowed_sender.is_empty()),
         "Configuration loaded successfully. System starting."
     );
 
+    // 工作流和后台投递共用同一个发件队列，只有这里启动的任务负责投递
+    let queue = email::OutboundQueue::open(config.email.queue.clone())?;
+
     // 创建工作流
-    let _workflow = workflow::create_workflow().await?;
-    tracing::info!("Email workflow created. Waiting for emails from {}", config.email.allowed_sender);
+    let workflow = Arc::new(workflow::create_workflow(&queue).await?);
+    tracing::info!("Email workflow created. Waiting for emails.");
 
-    // TODO: 实现邮件监听逻辑
-    // 目前只是创建了工作流，实际的邮件接收需要通过IMAP或其他方式实现
+    // 后台投递发件队列
+    let smtp_client = Box::new(email::SmtpClient::new(config.email.smtp.clone())?);
+    tokio::spawn(queue.clone().run(smtp_client));
+
+    // 定期发送摘要
+    if let Some(digest) = workflow.digest() {
+        let owner = config.email.owner_address()
+            .ok_or_else(|| anyhow::anyhow!("email.owner must be set to receive the routing digest"))?;
+        tokio::spawn(digest::run_digest_sender(
+            digest,
+            Box::new(queue.clone()),
+            email::EmailAddress::new(&config.email.smtp.username),
+            email::EmailAddress::new(owner),
+            Duration::from_secs(config.routing.digest.interval),
+        ));
+    }
+
+    // 人工确认模式：清理过期草稿，并提供确认草稿的 HTTP 接口
+    if let Some(drafts) = workflow.drafts() {
+        let token = server::api_token(&config.server)?;
+        tokio::spawn(approval::run_expiry(drafts, Duration::from_secs(60)));
+        let workflow = workflow.clone();
+        tokio::spawn(async move {
+            if let Err(e) = server::serve(&config.server, token, workflow).await {
+                tracing::error!("Draft approval API stopped: {}", e);
+            }
+        });
+    }
+
+    // 监听收件箱，直到收到退出信号
+    let receiver = email::create_receiver(&config.email)?;
+    tokio::select! {
+        result = receiver.run(workflow.as_ref()) => result?,
+        _ = tokio::signal::ctrl_c() => tracing::info!("Shutdown signal received."),
+    }
 
     // 程序正常退出
     tracing::info!("System shutdown completed.");
     Ok(())
-}
\ No newline at end of file
+}
+
+async fn queue_command(queue: &email::OutboundQueue, args: &[String]) -> Result<()> {
+    match args.first().map(String::as_str) {
+        Some("list") => {
+            for (state, entries) in [("pending", queue.pending().await?), ("dead", queue.dead_letters().await?)] {
+                for entry in entries {
+                    println!(
+                        "{}\t{}\tattempts={}\tto={}\tsubject={}\t{}",
+                        state,
+                        entry.id,
+                        entry.attempts,
+                        entry.message.to.iter().map(|to| to.email.as_str()).collect::<Vec<_>>().join(","),
+                        entry.message.subject,
+                        entry.last_error.unwrap_or_default()
+                    );
+                }
+            }
+        }
+        Some("replay") => {
+            let ids: Vec<String> = match args.get(1).map(String::as_str) {
+                Some("all") => queue.dead_letters().await?.into_iter().map(|entry| entry.id).collect(),
+                Some(id) => vec![id.to_string()],
+                None => anyhow::bail!("Usage: sentio queue replay <id>|all"),
+            };
+            for id in ids {
+                queue.replay(&id).await?;
+                println!("Requeued {}", id);
+            }
+        }
+        _ => anyhow::bail!("Usage: sentio queue list | sentio queue replay <id>|all"),
+    }
+    Ok(())
+}
@@ -8,6 +8,8 @@ use tokio::fs;
 use tokio::sync::RwLock;
 use uuid::Uuid;
 
+use crate::config::RuleAction;
+
 static MEMORY_STORE: tokio::sync::OnceCell<Arc<MemoryStore>> = tokio::sync::OnceCell::const_new();
 
 #[derive(Debug)]
@@ -20,6 +22,9 @@ pub struct MemoryStore {
 struct MemoryData {
     memories: HashMap<String, Vec<Memory>>,
     interactions: HashMap<String, Vec<InteractionLog>>,
+    /// 每封邮件（按 Message-ID）已经完成的路由动作，邮件重新处理时跳过
+    #[serde(default)]
+    completed_actions: HashMap<String, Vec<RuleAction>>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -31,7 +36,7 @@ pub struct Memory {
     pub created_at: DateTime<Utc>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 #[serde(rename_all = "snake_case")]
 pub enum MemoryType {
     Event,
@@ -66,6 +71,13 @@ impl MemoryStore {
         Ok(())
     }
 
+    /// 测试用：在临时目录初始化一次全局存储
+    #[cfg(test)]
+    pub async fn initialize_for_tests() {
+        let path = std::env::temp_dir().join(format!("sentio-test-memory-{}.json", std::process::id()));
+        let _ = Self::initialize(path).await;
+    }
+
     pub fn get() -> &'static Arc<MemoryStore> {
         MEMORY_STORE.get().expect("Memory store not initialized")
     }
@@ -92,26 +104,29 @@ impl MemoryStore {
     }
 
     pub async fn add_memory(&self, user_id: &str, memory_type: MemoryType, content: String) -> Result<String> {
-        let memory = Memory {
-            id: Uuid::new_v4().to_string(),
+        self.add_memory_once(&Uuid::new_v4().to_string(), user_id, memory_type, content).await
+    }
+
+    /// 以指定的 id 添加记忆；同一 id 已经存在时不再重复添加，重新处理同一封邮件时使用
+    pub async fn add_memory_once(&self, id: &str, user_id: &str, memory_type: MemoryType, content: String) -> Result<String> {
+        let mut data = self.data.write().await;
+        let memories = data.memories.entry(user_id.to_string()).or_default();
+        if memories.iter().any(|memory| memory.id == id) {
+            return Ok(id.to_string());
+        }
+        memories.push(Memory {
+            id: id.to_string(),
             user_id: user_id.to_string(),
             memory_type,
             content,
             created_at: Utc::now(),
-        };
-
-        let mut data = self.data.write().await;
-        data.memories
-            .entry(user_id.to_string())
-            .or_default()
-            .push(memory.clone());
+        });
         drop(data);
 
         self.save().await?;
-        Ok(memory.id)
+        Ok(id.to_string())
     }
 
-    #[allow(dead_code)]
     pub async fn get_user_memories(&self, user_id: &str) -> Result<Vec<Memory>> {
         let data = self.data.read().await;
         Ok(data.memories
@@ -120,6 +135,24 @@ impl MemoryStore {
             .unwrap_or_default())
     }
 
+    /// 这封邮件已经完成的路由动作
+    pub async fn completed_actions(&self, message_id: &str) -> Vec<RuleAction> {
+        let data = self.data.read().await;
+        data.completed_actions.get(message_id).cloned().unwrap_or_default()
+    }
+
+    pub async fn complete_action(&self, message_id: &str, action: &RuleAction) -> Result<()> {
+        let mut data = self.data.write().await;
+        data.completed_actions
+            .entry(message_id.to_string())
+            .or_default()
+            .push(action.clone());
+        drop(data);
+
+        self.save().await
+    }
+
+    /// 记录一次交互；指定了 id 且同一 id 的记录已经存在时不再重复写入
     pub async fn log_interaction(interaction: &InteractionLog) -> Result<String> {
         let store = Self::get();
         let id = interaction.id.clone()
@@ -129,17 +162,19 @@ impl MemoryStore {
         log.id = Some(id.clone());
 
         let mut data = store.data.write().await;
-        data.interactions
+        let interactions = data.interactions
             .entry(interaction.user_id.clone())
-            .or_default()
-            .push(log);
+            .or_default();
+        if interactions.iter().any(|existing| existing.id.as_deref() == Some(id.as_str())) {
+            return Ok(id);
+        }
+        interactions.push(log);
         drop(data);
 
         store.save().await?;
         Ok(id)
     }
 
-    #[allow(dead_code)]
     pub async fn get_user_interactions(user_id: &str, limit: Option<usize>, offset: Option<usize>) -> Result<Vec<InteractionLog>> {
         let store = Self::get();
         let data = store.data.read().await;
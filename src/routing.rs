@@ -0,0 +1,160 @@
+use anyhow::{Context, Result};
+use regex::{Regex, RegexBuilder};
+
+use crate::analysis::{EmailAnalysis, EmailCategory};
+use crate::config::{RoutingConfig, RoutingRule, RuleAction};
+use crate::email::InboundEmail;
+
+struct CompiledRule {
+    name: String,
+    categories: Vec<EmailCategory>,
+    sender: Option<Regex>,
+    subject: Option<Regex>,
+    needs_reply: Option<bool>,
+    actions: Vec<RuleAction>,
+}
+
+/// 根据分类、发件人和主题决定对邮件执行哪些动作
+pub struct Router {
+    rules: Vec<CompiledRule>,
+    default_actions: Vec<RuleAction>,
+}
+
+impl Default for Router {
+    /// 不配置规则时保持原有行为：总是回复
+    fn default() -> Self {
+        Self {
+            rules: Vec::new(),
+            default_actions: vec![RuleAction::Reply],
+        }
+    }
+}
+
+impl Router {
+    pub fn from_config(config: &RoutingConfig) -> Result<Self> {
+        let rules = config
+            .rules
+            .iter()
+            .enumerate()
+            .map(|(index, rule)| compile_rule(index, rule))
+            .collect::<Result<_>>()?;
+
+        Ok(Self {
+            rules,
+            default_actions: config.default_actions.clone(),
+        })
+    }
+
+    /// 返回命中规则的名称与动作；没有规则命中时返回默认动作
+    pub fn route(&self, message: &InboundEmail, analysis: &EmailAnalysis) -> (&str, &[RuleAction]) {
+        self.rules
+            .iter()
+            .find(|rule| rule.matches(message, analysis))
+            .map(|rule| (rule.name.as_str(), rule.actions.as_slice()))
+            .unwrap_or(("default", self.default_actions.as_slice()))
+    }
+
+    /// 是否有规则（含默认动作）会产生摘要条目
+    pub fn uses_digest(&self) -> bool {
+        self.rules
+            .iter()
+            .flat_map(|rule| rule.actions.iter())
+            .chain(self.default_actions.iter())
+            .any(|action| *action == RuleAction::Digest)
+    }
+}
+
+impl CompiledRule {
+    fn matches(&self, message: &InboundEmail, analysis: &EmailAnalysis) -> bool {
+        (self.categories.is_empty() || self.categories.contains(&analysis.category))
+            && self.sender.as_ref().is_none_or(|re| re.is_match(&message.from.email))
+            && self.subject.as_ref().is_none_or(|re| re.is_match(&message.subject))
+            && self.needs_reply.is_none_or(|needs_reply| needs_reply == analysis.needs_reply)
+    }
+}
+
+fn compile_rule(index: usize, rule: &RoutingRule) -> Result<CompiledRule> {
+    let name = rule.name.clone().unwrap_or_else(|| format!("rule #{}", index + 1));
+    let compile = |pattern: &Option<String>, field: &str| -> Result<Option<Regex>> {
+        pattern
+            .as_deref()
+            .map(|pattern| {
+                RegexBuilder::new(pattern)
+                    .case_insensitive(true)
+                    .build()
+                    .with_context(|| format!("Invalid {} pattern in routing {}", field, name))
+            })
+            .transpose()
+    };
+
+    Ok(CompiledRule {
+        sender: compile(&rule.sender, "sender")?,
+        subject: compile(&rule.subject, "subject")?,
+        categories: rule.categories.clone(),
+        needs_reply: rule.needs_reply,
+        actions: rule.actions.clone(),
+        name,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::email::parser::parse_message;
+
+    fn config(toml_rules: &str) -> RoutingConfig {
+        toml::from_str(toml_rules).unwrap()
+    }
+
+    fn analysis(category: EmailCategory) -> EmailAnalysis {
+        EmailAnalysis {
+            category,
+            ..EmailAnalysis::default()
+        }
+    }
+
+    #[test]
+    fn test_first_matching_rule_wins() {
+        let router = Router::from_config(&config(
+            r#"
+            [[rules]]
+            name = "spam"
+            categories = ["垃圾邮件", "marketing"]
+            actions = [{ type = "ignore" }]
+
+            [[rules]]
+            name = "alerts"
+            sender = "@monitor\\.example\\.com$"
+            subject = "^\\[alert\\]"
+            actions = [{ type = "forward", to = "ops@example.com" }, { type = "digest" }]
+            "#,
+        ))
+        .unwrap();
+
+        let alert = parse_message(b"From: bot@Monitor.example.com\r\nSubject: [ALERT] disk full\r\n\r\nx").unwrap();
+        let normal = parse_message(b"From: owner@qq.com\r\nSubject: hello\r\n\r\nx").unwrap();
+
+        let (name, actions) = router.route(&normal, &analysis(EmailCategory::Marketing));
+        assert_eq!((name, actions), ("spam", [RuleAction::Ignore].as_slice()));
+
+        let (name, actions) = router.route(&alert, &analysis(EmailCategory::Notification));
+        assert_eq!(name, "alerts");
+        assert_eq!(actions[0], RuleAction::Forward { to: "ops@example.com".to_string() });
+        assert!(router.uses_digest());
+
+        let (name, actions) = router.route(&normal, &analysis(EmailCategory::Work));
+        assert_eq!((name, actions), ("default", [RuleAction::Reply].as_slice()));
+    }
+
+    #[test]
+    fn test_invalid_pattern_is_rejected() {
+        let result = Router::from_config(&config(
+            r#"
+            [[rules]]
+            subject = "("
+            actions = [{ type = "ignore" }]
+            "#,
+        ));
+        assert!(result.is_err());
+    }
+}
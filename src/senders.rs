@@ -0,0 +1,191 @@
+use anyhow::{Context, Result};
+use regex::{Regex, RegexBuilder};
+
+use crate::config::{ActionKind, EmailConfig, RuleAction, SenderConfig};
+
+/// 未指定 `reply_prompt` 时使用的提示词
+const DEFAULT_REPLY_PROMPT: &str = "email_reply";
+
+/// 规范化邮件地址：去掉空白、`mailto:` 前缀和尖括号，并转为小写
+pub fn normalize_address(address: &str) -> String {
+    let address = address.trim();
+    let address = address.strip_prefix("mailto:").unwrap_or(address);
+    address
+        .trim_start_matches('<')
+        .trim_end_matches('>')
+        .trim()
+        .to_lowercase()
+}
+
+enum AddressMatcher {
+    Exact(String),
+    Pattern(Regex),
+}
+
+impl AddressMatcher {
+    fn parse(address: &str) -> Result<Self> {
+        let address = address.trim();
+
+        if let Some(pattern) = address.strip_prefix('/').and_then(|rest| rest.strip_suffix('/')) {
+            let regex = RegexBuilder::new(pattern)
+                .case_insensitive(true)
+                .build()
+                .with_context(|| format!("Invalid sender pattern {}", address))?;
+            return Ok(Self::Pattern(regex));
+        }
+
+        // `@example.com` 是 `*@example.com` 的简写
+        let address = match address.strip_prefix('@') {
+            Some(domain) => format!("*@{}", domain),
+            None => address.to_string(),
+        };
+        if !address.contains(['*', '?']) {
+            return Ok(Self::Exact(normalize_address(&address)));
+        }
+
+        let mut pattern = String::from("^");
+        for c in normalize_address(&address).chars() {
+            match c {
+                '*' => pattern.push_str(".*"),
+                '?' => pattern.push('.'),
+                c => pattern.push_str(&regex::escape(&c.to_string())),
+            }
+        }
+        pattern.push('$');
+        Ok(Self::Pattern(Regex::new(&pattern)?))
+    }
+
+    fn matches(&self, address: &str) -> bool {
+        match self {
+            Self::Exact(expected) => expected == address,
+            Self::Pattern(regex) => regex.is_match(address),
+        }
+    }
+}
+
+/// 某个发件人的回复策略
+#[derive(Debug, Clone)]
+pub struct SenderPolicy {
+    pub reply_prompt: String,
+    pub persona: Option<String>,
+    pub language: Option<String>,
+    allowed_actions: Option<Vec<ActionKind>>,
+}
+
+impl Default for SenderPolicy {
+    fn default() -> Self {
+        Self {
+            reply_prompt: DEFAULT_REPLY_PROMPT.to_string(),
+            persona: None,
+            language: None,
+            allowed_actions: None,
+        }
+    }
+}
+
+impl SenderPolicy {
+    fn from_config(config: &SenderConfig) -> Self {
+        Self {
+            reply_prompt: config.reply_prompt.clone().unwrap_or_else(|| DEFAULT_REPLY_PROMPT.to_string()),
+            persona: config.persona.clone(),
+            language: config.language.clone(),
+            allowed_actions: config.allowed_actions.clone(),
+        }
+    }
+
+    pub fn allows(&self, action: &RuleAction) -> bool {
+        self.allowed_actions
+            .as_ref()
+            .is_none_or(|allowed| allowed.contains(&action.kind()))
+    }
+}
+
+/// 允许的发件人列表，按配置顺序匹配，第一条命中的条目生效
+pub struct SenderList {
+    entries: Vec<(AddressMatcher, SenderPolicy)>,
+}
+
+impl SenderList {
+    /// `senders` 中的条目优先，`allowed_sender` 作为最后一条精确匹配
+    pub fn from_config(config: &EmailConfig) -> Result<Self> {
+        let mut entries = config
+            .senders
+            .iter()
+            .map(|sender| Ok((AddressMatcher::parse(&sender.address)?, SenderPolicy::from_config(sender))))
+            .collect::<Result<Vec<_>>>()?;
+
+        if !config.allowed_sender.trim().is_empty() {
+            entries.push((AddressMatcher::parse(&config.allowed_sender)?, SenderPolicy::default()));
+        }
+        if entries.is_empty() {
+            anyhow::bail!("No allowed senders configured: set email.allowed_sender or add [[email.senders]] entries");
+        }
+
+        Ok(Self { entries })
+    }
+
+    /// 查找发件人对应的策略；不在列表中时返回 None
+    pub fn find(&self, address: &str) -> Option<&SenderPolicy> {
+        let address = normalize_address(address);
+        self.entries
+            .iter()
+            .find(|(matcher, _)| matcher.matches(&address))
+            .map(|(_, policy)| policy)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn email_config(toml_email: &str) -> EmailConfig {
+        let smtp = r#"smtp = { host = "smtp.example.com", port = 587, username = "u", password = "p", use_tls = true }"#;
+        toml::from_str(&format!("{}\n{}", smtp, toml_email)).unwrap()
+    }
+
+    #[test]
+    fn test_exact_wildcard_and_regex_senders() {
+        let senders = SenderList::from_config(&email_config(
+            r#"
+            allowed_sender = "Owner@QQ.com"
+
+            [[senders]]
+            address = '/^ops-[a-z]+@example\.com$/'
+            allowed_actions = ["task"]
+
+            [[senders]]
+            address = "*@example.com"
+            persona = "项目经理"
+            language = "English"
+            reply_prompt = "team_reply"
+
+            [[senders]]
+            address = "@partner.org"
+            "#,
+        ))
+        .unwrap();
+
+        assert!(senders.find("<owner@qq.com>").is_some());
+        assert!(senders.find(" OWNER@qq.COM ").is_some());
+        assert!(senders.find("other@qq.com").is_none());
+
+        let ops = senders.find("Ops-Alerts@Example.com").unwrap();
+        assert!(ops.allows(&RuleAction::Task));
+        assert!(!ops.allows(&RuleAction::Reply));
+
+        let team = senders.find("alice@example.com").unwrap();
+        assert_eq!(team.reply_prompt, "team_reply");
+        assert_eq!(team.language.as_deref(), Some("English"));
+        assert!(team.allows(&RuleAction::Forward { to: "x@example.com".to_string() }));
+
+        assert_eq!(senders.find("bob@partner.org").unwrap().reply_prompt, "email_reply");
+        assert!(senders.find("alice@sub.example.com").is_none());
+        assert!(senders.find("alice@example.com.evil.org").is_none());
+    }
+
+    #[test]
+    fn test_empty_or_invalid_sender_list_is_rejected() {
+        assert!(SenderList::from_config(&email_config("")).is_err());
+        assert!(SenderList::from_config(&email_config("[[senders]]\naddress = \"/(/\"")).is_err());
+    }
+}
@@ -0,0 +1,174 @@
+use anyhow::Result;
+use axum::extract::{Path, Request, State};
+use axum::http::{header, StatusCode};
+use axum::middleware::{self, Next};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::sync::Arc;
+use tokio::net::TcpListener;
+use tracing::{info, warn};
+
+use crate::approval::{Decision, Draft};
+use crate::config::ServerConfig;
+use crate::workflow::EmailWorkflow;
+
+#[derive(Clone)]
+struct AppState {
+    workflow: Arc<EmailWorkflow>,
+    token: String,
+}
+
+/// 接口错误，以 `{"error": "..."}` 返回
+struct ApiError(StatusCode, String);
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        (self.0, Json(serde_json::json!({ "error": self.1 }))).into_response()
+    }
+}
+
+impl From<anyhow::Error> for ApiError {
+    fn from(e: anyhow::Error) -> Self {
+        warn!("Draft API request failed: {}", e);
+        Self(StatusCode::INTERNAL_SERVER_ERROR, e.to_string())
+    }
+}
+
+#[derive(Deserialize)]
+struct EditRequest {
+    body: String,
+}
+
+/// 草稿确认接口：
+/// `GET /drafts`、`POST /drafts/{id}/approve`、`POST /drafts/{id}/reject`、`POST /drafts/{id}/edit`（`{"body": "..."}`）
+pub fn router(workflow: Arc<EmailWorkflow>, token: String) -> Router {
+    let state = AppState { workflow, token };
+    Router::new()
+        .route("/drafts", get(list_drafts))
+        .route("/drafts/{id}/approve", post(approve))
+        .route("/drafts/{id}/reject", post(reject))
+        .route("/drafts/{id}/edit", post(edit))
+        .layer(middleware::from_fn_with_state(state.clone(), authorize))
+        .with_state(state)
+}
+
+/// 接口可以批准并发出草稿，没有令牌时拒绝启动
+pub fn api_token(config: &ServerConfig) -> Result<String> {
+    config
+        .api_token
+        .clone()
+        .filter(|token| !token.trim().is_empty())
+        .ok_or_else(|| anyhow::anyhow!("server.api_token must be set when approval mode is enabled"))
+}
+
+pub async fn serve(config: &ServerConfig, token: String, workflow: Arc<EmailWorkflow>) -> Result<()> {
+    let listener = TcpListener::bind((config.host.as_str(), config.port)).await?;
+    info!("Draft approval API listening on {}", listener.local_addr()?);
+    axum::serve(listener, router(workflow, token)).await?;
+    Ok(())
+}
+
+/// 比较两边的 SHA-256，耗时与令牌内容和长度无关
+fn token_matches(provided: &str, expected: &str) -> bool {
+    let (provided, expected) = (Sha256::digest(provided), Sha256::digest(expected));
+    provided.iter().zip(expected.iter()).fold(0u8, |diff, (a, b)| diff | (a ^ b)) == 0
+}
+
+async fn authorize(State(state): State<AppState>, request: Request, next: Next) -> Result<Response, ApiError> {
+    let provided = request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "));
+    if !provided.is_some_and(|provided| token_matches(provided, &state.token)) {
+        return Err(ApiError(StatusCode::UNAUTHORIZED, "invalid or missing API token".to_string()));
+    }
+    Ok(next.run(request).await)
+}
+
+async fn list_drafts(State(state): State<AppState>) -> Result<Json<Vec<Draft>>, ApiError> {
+    let drafts = state
+        .workflow
+        .drafts()
+        .ok_or_else(|| ApiError(StatusCode::NOT_FOUND, "approval mode is not enabled".to_string()))?;
+    Ok(Json(drafts.list().await?))
+}
+
+async fn resolve(state: &AppState, id: &str, decision: Decision) -> Result<Json<Draft>, ApiError> {
+    match state.workflow.resolve_draft(id, decision).await? {
+        Some(draft) => Ok(Json(draft)),
+        None => Err(ApiError(StatusCode::NOT_FOUND, format!("draft {} not found or expired", id))),
+    }
+}
+
+async fn approve(State(state): State<AppState>, Path(id): Path<String>) -> Result<Json<Draft>, ApiError> {
+    resolve(&state, &id, Decision::Approve).await
+}
+
+async fn reject(State(state): State<AppState>, Path(id): Path<String>) -> Result<Json<Draft>, ApiError> {
+    resolve(&state, &id, Decision::Reject).await
+}
+
+async fn edit(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    Json(request): Json<EditRequest>,
+) -> Result<Json<Draft>, ApiError> {
+    if request.body.trim().is_empty() {
+        return Err(ApiError(StatusCode::BAD_REQUEST, "body must not be empty".to_string()));
+    }
+    resolve(&state, &id, Decision::Edit(request.body)).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::approval::DraftStore;
+    use crate::workflow::tests::{approval_workflow, create_draft};
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn test_draft_endpoints_require_token_and_resolve_drafts() {
+        let dir = tempfile::tempdir().unwrap();
+        let drafts = Arc::new(DraftStore::new(dir.path().join("drafts.json"), Duration::from_secs(3600)));
+        let (workflow, client) = approval_workflow(drafts.clone()).await;
+        let first = create_draft(&drafts).await;
+        let second = create_draft(&drafts).await;
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            axum::serve(listener, router(Arc::new(workflow), "secret".to_string())).await.unwrap();
+        });
+
+        let http = reqwest::Client::new();
+        let post = |path: String| http.post(format!("{}{}", base, path)).bearer_auth("secret");
+
+        let unauthorized = http.get(format!("{}/drafts", base)).send().await.unwrap();
+        assert_eq!(unauthorized.status().as_u16(), StatusCode::UNAUTHORIZED.as_u16());
+        let wrong = http.get(format!("{}/drafts", base)).bearer_auth("secreT").send().await.unwrap();
+        assert_eq!(wrong.status().as_u16(), StatusCode::UNAUTHORIZED.as_u16());
+        let listed: Vec<Draft> = http.get(format!("{}/drafts", base)).bearer_auth("secret").send().await.unwrap().json().await.unwrap();
+        assert_eq!(listed.len(), 2);
+
+        let edited = post(format!("/drafts/{}/edit", first.id)).json(&serde_json::json!({ "body": "明天答复" })).send().await.unwrap();
+        assert_eq!(edited.status().as_u16(), StatusCode::OK.as_u16());
+        assert_eq!(client.sent.lock().unwrap()[0].body, "明天答复\n\n> Hi");
+
+        let rejected = post(format!("/drafts/{}/reject", second.id)).send().await.unwrap();
+        assert_eq!(rejected.status().as_u16(), StatusCode::OK.as_u16());
+        let missing = post(format!("/drafts/{}/approve", second.id)).send().await.unwrap();
+        assert_eq!(missing.status().as_u16(), StatusCode::NOT_FOUND.as_u16());
+
+        assert_eq!(client.sent.lock().unwrap().len(), 1);
+        assert!(drafts.list().await.unwrap().is_empty());
+
+        let mut config: ServerConfig = toml::from_str("host = \"127.0.0.1\"\nport = 0\nworkers = 1").unwrap();
+        assert!(api_token(&config).is_err());
+        config.api_token = Some("secret".to_string());
+        assert_eq!(api_token(&config).unwrap(), "secret");
+    }
+}
@@ -0,0 +1,233 @@
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+
+use crate::email::OutboundQueue;
+use crate::llm::client::{ToolCall, ToolDefinition};
+use crate::memory::{MemoryStore, MemoryType, MessageDirection};
+use crate::senders::normalize_address;
+
+/// 查询类工具最多返回的条数
+const RESULT_LIMIT: usize = 10;
+
+/// 生成回复前模型可以调用的工具，作用范围限定在当前发件人和邮件线程
+#[derive(Default)]
+pub struct Toolbox {
+    queue: Option<OutboundQueue>,
+}
+
+#[derive(Deserialize)]
+struct SearchArgs {
+    #[serde(default)]
+    query: String,
+    memory_type: Option<MemoryType>,
+}
+
+#[derive(Deserialize)]
+struct AddArgs {
+    content: String,
+}
+
+#[derive(Deserialize)]
+struct HistoryArgs {
+    limit: Option<usize>,
+}
+
+impl Toolbox {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 提供 `check_outbound_queue` 工具
+    pub fn with_queue(mut self, queue: OutboundQueue) -> Self {
+        self.queue = Some(queue);
+        self
+    }
+
+    pub fn definitions(&self) -> Vec<ToolDefinition> {
+        let tool = |name: &str, description: &str, parameters: Value| ToolDefinition {
+            name: name.to_string(),
+            description: description.to_string(),
+            parameters,
+        };
+        let content = json!({
+            "type": "object",
+            "properties": { "content": { "type": "string" } },
+            "required": ["content"]
+        });
+
+        let mut tools = vec![
+            tool(
+                "search_memory",
+                "按关键词搜索关于发件人的已知信息（事件、知识、任务、关系），query 为空时返回最近的记录",
+                json!({
+                    "type": "object",
+                    "properties": {
+                        "query": { "type": "string", "description": "空格分隔的关键词，全部命中才返回" },
+                        "memory_type": { "type": "string", "enum": ["event", "knowledge", "task", "relationship"] }
+                    }
+                }),
+            ),
+            tool("add_task", "为发件人记录一条待办任务", content.clone()),
+            tool("add_knowledge", "记录一条关于发件人的长期知识，例如偏好或身份", content),
+            tool(
+                "get_thread_history",
+                "读取当前邮件线程中之前的往来邮件，按时间顺序",
+                json!({
+                    "type": "object",
+                    "properties": { "limit": { "type": "integer", "minimum": 1 } }
+                }),
+            ),
+        ];
+        if self.queue.is_some() {
+            tools.push(tool(
+                "check_outbound_queue",
+                "查看发给这个发件人、还在发件队列中等待发送或发送失败的邮件",
+                json!({ "type": "object", "properties": {} }),
+            ));
+        }
+        tools
+    }
+
+    /// 执行一次工具调用，返回交给模型的结果
+    ///
+    /// `message_id` 为正在回复的邮件；同一封邮件重新处理时，内容相同的任务和知识只记录一次。
+    pub async fn execute(&self, call: &ToolCall, user_id: &str, session_id: &str, message_id: Option<&str>) -> Result<Value> {
+        let memory_store = MemoryStore::get();
+        match call.name.as_str() {
+            "search_memory" => {
+                let args: SearchArgs = parse_args(call)?;
+                let keywords: Vec<String> = args.query.split_whitespace().map(str::to_lowercase).collect();
+                let memories = memory_store.get_user_memories(user_id).await?;
+                let found: Vec<Value> = memories
+                    .iter()
+                    .rev()
+                    .filter(|memory| args.memory_type.is_none_or(|kind| memory.memory_type == kind))
+                    .filter(|memory| {
+                        let content = memory.content.to_lowercase();
+                        keywords.iter().all(|keyword| content.contains(keyword))
+                    })
+                    .take(RESULT_LIMIT)
+                    .map(|memory| json!({
+                        "type": memory.memory_type,
+                        "content": memory.content,
+                        "created_at": memory.created_at,
+                    }))
+                    .collect();
+                Ok(json!(found))
+            }
+            "add_task" | "add_knowledge" => {
+                let args: AddArgs = parse_args(call)?;
+                if args.content.trim().is_empty() {
+                    anyhow::bail!("content must not be empty");
+                }
+                let memory_type = if call.name == "add_task" { MemoryType::Task } else { MemoryType::Knowledge };
+                let id = match message_id {
+                    Some(message_id) => {
+                        let key = Sha256::digest(format!("{}\n{}", call.name, args.content));
+                        let id = format!("{}:{:x}", message_id, key);
+                        memory_store.add_memory_once(&id, user_id, memory_type, args.content).await?
+                    }
+                    None => memory_store.add_memory(user_id, memory_type, args.content).await?,
+                };
+                Ok(json!({ "id": id }))
+            }
+            "get_thread_history" => {
+                let args: HistoryArgs = parse_args(call)?;
+                let interactions = MemoryStore::get_user_interactions(user_id, None, None).await?;
+                let thread: Vec<Value> = interactions
+                    .iter()
+                    .filter(|log| log.session_id == session_id)
+                    .map(|log| json!({
+                        "from": match log.direction {
+                            MessageDirection::UserToSystem => "sender",
+                            MessageDirection::SystemToUser => "assistant",
+                        },
+                        "timestamp": log.timestamp,
+                        "content": log.content,
+                    }))
+                    .collect();
+                let start = thread.len().saturating_sub(args.limit.unwrap_or(RESULT_LIMIT));
+                Ok(json!(thread[start..]))
+            }
+            "check_outbound_queue" => {
+                let queue = self.queue.as_ref().context("outbound queue is not available")?;
+                let user = normalize_address(user_id);
+                let mut entries = Vec::new();
+                for (status, messages) in [("pending", queue.pending().await?), ("dead", queue.dead_letters().await?)] {
+                    entries.extend(
+                        messages
+                            .into_iter()
+                            .filter(|entry| entry.message.to.iter().any(|to| normalize_address(&to.email) == user))
+                            .map(|entry| json!({
+                                "status": status,
+                                "subject": entry.message.subject,
+                                "created_at": entry.created_at,
+                                "attempts": entry.attempts,
+                                "next_attempt_at": entry.next_attempt_at,
+                                "last_error": entry.last_error,
+                            })),
+                    );
+                }
+                Ok(json!(entries))
+            }
+            other => anyhow::bail!("unknown tool '{}'", other),
+        }
+    }
+}
+
+fn parse_args<T: for<'de> Deserialize<'de>>(call: &ToolCall) -> Result<T> {
+    serde_json::from_value(call.arguments.clone())
+        .with_context(|| format!("invalid arguments for tool '{}'", call.name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::QueueConfig;
+    use crate::email::client::tests::message;
+
+    fn call(name: &str, arguments: Value) -> ToolCall {
+        ToolCall {
+            id: "call_0".to_string(),
+            name: name.to_string(),
+            arguments,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_memory_and_queue_tools() {
+        MemoryStore::initialize_for_tests().await;
+        let dir = tempfile::tempdir().unwrap();
+        let queue = OutboundQueue::open(QueueConfig {
+            dir: dir.path().to_path_buf(),
+            ..QueueConfig::default()
+        })
+        .unwrap();
+        queue
+            .enqueue(message("Tool.User@Example.com", "Re: 报价", "稍后答复"))
+            .await
+            .unwrap();
+        let toolbox = Toolbox::new().with_queue(queue);
+        let user = "tool.user@example.com";
+
+        toolbox.execute(&call("add_knowledge", json!({ "content": "偏好电话沟通" })), user, "t", None).await.unwrap();
+        // 重新处理同一封邮件时同样的任务只记录一次
+        for _ in 0..2 {
+            toolbox.execute(&call("add_task", json!({ "content": "周五前发送报价单" })), user, "t", Some("m1@example.com")).await.unwrap();
+        }
+
+        let found = toolbox.execute(&call("search_memory", json!({ "query": "报价" })), user, "t", None).await.unwrap();
+        assert_eq!(found.as_array().unwrap().len(), 1);
+        assert_eq!(found[0]["type"], "task");
+        let knowledge = toolbox.execute(&call("search_memory", json!({ "memory_type": "knowledge" })), user, "t", None).await.unwrap();
+        assert_eq!(knowledge[0]["content"], "偏好电话沟通");
+
+        let queued = toolbox.execute(&call("check_outbound_queue", json!({})), user, "t", None).await.unwrap();
+        assert_eq!((queued[0]["status"].as_str(), queued[0]["subject"].as_str()), (Some("pending"), Some("Re: 报价")));
+
+        assert!(toolbox.execute(&call("add_task", json!({})), user, "t", None).await.is_err());
+        assert!(toolbox.execute(&call("send_money", json!({})), user, "t", None).await.is_err());
+    }
+}
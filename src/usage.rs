@@ -0,0 +1,322 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use chrono::{DateTime, Datelike, NaiveDate, Utc};
+use futures::StreamExt;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::path::PathBuf;
+use std::sync::Arc;
+use tokio::io::AsyncWriteExt;
+use tokio::sync::Mutex;
+use tracing::{debug, warn};
+
+use crate::config::{BudgetConfig, ModelPrice, UsageConfig};
+use crate::llm::client::{LlmResponse, LlmResult, LlmStream, StreamChunk, TokenUsage};
+use crate::llm::{LlmClient, LlmRequest};
+
+/// 一次模型调用的用量
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UsageRecord {
+    pub timestamp: DateTime<Utc>,
+    pub user_id: String,
+    pub prompt_name: String,
+    pub backend: String,
+    pub model: String,
+    pub prompt_tokens: u32,
+    pub completion_tokens: u32,
+    pub total_tokens: u32,
+    /// 按价格表计算的费用，未配置价格的模型为 0
+    pub cost: f64,
+}
+
+/// 已经用完的预算
+#[derive(Debug, Clone, PartialEq)]
+pub struct BudgetExceeded {
+    /// 预算范围，包含日期，例如 `alice@example.com 2026-10-17`
+    pub scope: String,
+    pub spent: f64,
+    pub limit: f64,
+}
+
+impl fmt::Display for BudgetExceeded {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} 的模型费用 {:.4} 已达到预算 {:.4}", self.scope, self.spent, self.limit)
+    }
+}
+
+/// 模型调用的用量记录
+///
+/// 每次调用追加一行 JSON 到 `path`，启动时读取已有记录，在内存中按天、按用户汇总费用。
+pub struct UsageStore {
+    path: PathBuf,
+    prices: HashMap<String, ModelPrice>,
+    budget: BudgetConfig,
+    state: Mutex<UsageState>,
+}
+
+#[derive(Default)]
+struct UsageState {
+    /// 每天每个用户的费用
+    daily: HashMap<NaiveDate, HashMap<String, f64>>,
+    /// 本次运行中已经通知过 owner 的预算范围
+    notified: HashSet<String>,
+}
+
+impl UsageState {
+    fn add(&mut self, record: &UsageRecord) {
+        *self.daily
+            .entry(record.timestamp.date_naive())
+            .or_default()
+            .entry(record.user_id.clone())
+            .or_default() += record.cost;
+    }
+
+    /// 满足日期条件的费用合计，`user_id` 为 None 时统计所有用户
+    fn spent(&self, day: impl Fn(NaiveDate) -> bool, user_id: Option<&str>) -> f64 {
+        self.daily
+            .iter()
+            .filter(|(date, _)| day(**date))
+            .flat_map(|(_, users)| users.iter())
+            .filter(|(user, _)| user_id.is_none_or(|user_id| user.as_str() == user_id))
+            .map(|(_, cost)| cost)
+            .sum()
+    }
+}
+
+impl UsageStore {
+    pub fn open(config: &UsageConfig) -> Result<Self> {
+        let mut state = UsageState::default();
+        if config.path.exists() {
+            let content = std::fs::read_to_string(&config.path)?;
+            for (index, line) in content.lines().enumerate().filter(|(_, line)| !line.trim().is_empty()) {
+                match serde_json::from_str::<UsageRecord>(line) {
+                    Ok(record) => state.add(&record),
+                    Err(e) => warn!("Skipping invalid usage record at {}:{}: {}", config.path.display(), index + 1, e),
+                }
+            }
+        }
+
+        Ok(Self {
+            path: config.path.clone(),
+            prices: config.prices.clone(),
+            budget: config.budget.clone(),
+            state: Mutex::new(state),
+        })
+    }
+
+    pub fn budget(&self) -> &BudgetConfig {
+        &self.budget
+    }
+
+    /// 按价格表计算费用；模型名没有完全相同的键时使用最长的前缀匹配，例如 `gpt-4o` 匹配 `gpt-4o-2024-08-06`
+    pub fn cost(&self, model: &str, usage: &TokenUsage) -> f64 {
+        let price = self.prices.get(model).or_else(|| {
+            self.prices
+                .iter()
+                .filter(|(name, _)| model.starts_with(name.as_str()))
+                .max_by_key(|(name, _)| name.len())
+                .map(|(_, price)| price)
+        });
+        match price {
+            Some(price) => {
+                (f64::from(usage.prompt_tokens) * price.input + f64::from(usage.completion_tokens) * price.output) / 1_000_000.0
+            }
+            None => 0.0,
+        }
+    }
+
+    pub async fn record(
+        &self,
+        user_id: &str,
+        prompt_name: &str,
+        backend: &str,
+        model: &str,
+        usage: &TokenUsage,
+    ) -> Result<UsageRecord> {
+        let record = UsageRecord {
+            timestamp: Utc::now(),
+            user_id: user_id.to_string(),
+            prompt_name: prompt_name.to_string(),
+            backend: backend.to_string(),
+            model: model.to_string(),
+            prompt_tokens: usage.prompt_tokens,
+            completion_tokens: usage.completion_tokens,
+            total_tokens: usage.total_tokens,
+            cost: self.cost(model, usage),
+        };
+
+        let mut line = serde_json::to_string(&record)?;
+        line.push('\n');
+        let mut state = self.state.lock().await;
+        let mut file = tokio::fs::OpenOptions::new().create(true).append(true).open(&self.path).await?;
+        file.write_all(line.as_bytes()).await?;
+        // tokio 的文件在后台写入，不 flush 的话关闭前的内容可能丢失
+        file.flush().await?;
+        state.add(&record);
+
+        debug!(user = %record.user_id, prompt = %record.prompt_name, model = %record.model, tokens = record.total_tokens, cost = record.cost, "Recorded LLM usage");
+        Ok(record)
+    }
+
+    /// 第一个用完的预算，依次检查用户当天、用户当月、全部当天、全部当月
+    pub async fn check_budget(&self, user_id: &str) -> Option<BudgetExceeded> {
+        let today = Utc::now().date_naive();
+        let month = today.format("%Y-%m");
+        let same_month = |date: NaiveDate| date.year() == today.year() && date.month() == today.month();
+
+        let state = self.state.lock().await;
+        let budgets = [
+            (self.budget.user_daily, format!("{} {}", user_id, today), state.spent(|date| date == today, Some(user_id))),
+            (self.budget.user_monthly, format!("{} {}", user_id, month), state.spent(same_month, Some(user_id))),
+            (self.budget.daily, format!("全部用户 {}", today), state.spent(|date| date == today, None)),
+            (self.budget.monthly, format!("全部用户 {}", month), state.spent(same_month, None)),
+        ];
+        budgets
+            .into_iter()
+            .find(|(limit, _, spent)| *limit > 0.0 && spent >= limit)
+            .map(|(limit, scope, spent)| BudgetExceeded { scope, spent, limit })
+    }
+
+    /// 这个预算范围是否第一次超出；同一范围只通知 owner 一次
+    pub async fn first_notice(&self, exceeded: &BudgetExceeded) -> bool {
+        self.state.lock().await.notified.insert(exceeded.scope.clone())
+    }
+}
+
+/// 处理一封邮件时使用的模型客户端：把每次调用的用量记在发件人名下，需要时替换模型
+pub struct MeteredClient<'a> {
+    inner: &'a dyn LlmClient,
+    usage: Option<Arc<UsageStore>>,
+    user_id: String,
+    model: Option<String>,
+}
+
+impl<'a> MeteredClient<'a> {
+    pub fn new(inner: &'a dyn LlmClient, usage: Option<Arc<UsageStore>>, user_id: &str) -> Self {
+        Self {
+            inner,
+            usage,
+            user_id: user_id.to_string(),
+            model: None,
+        }
+    }
+
+    pub fn with_model(mut self, model: Option<String>) -> Self {
+        self.model = model;
+        self
+    }
+
+    fn prepare(&self, request: &LlmRequest) -> LlmRequest {
+        match &self.model {
+            Some(model) => request.clone().with_model(model.clone()),
+            None => request.clone(),
+        }
+    }
+
+    async fn record(&self, request: &LlmRequest, response: &LlmResponse) {
+        if let Some(usage) = &self.usage {
+            if let Err(e) = usage.record(&self.user_id, &request.prompt_name, &response.backend, &response.model, &response.usage).await {
+                warn!("Failed to record LLM usage: {}", e);
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl LlmClient for MeteredClient<'_> {
+    async fn generate_response(&self, request: &LlmRequest) -> LlmResult<LlmResponse> {
+        let request = self.prepare(request);
+        let response = self.inner.generate_response(&request).await?;
+        self.record(&request, &response).await;
+        Ok(response)
+    }
+
+    async fn generate_streamed(&self, request: &LlmRequest) -> LlmResult<LlmResponse> {
+        let request = self.prepare(request);
+        let response = self.inner.generate_streamed(&request).await?;
+        self.record(&request, &response).await;
+        Ok(response)
+    }
+
+    /// 用量在最后一段到达时记录
+    async fn stream_response(&self, request: &LlmRequest) -> LlmResult<LlmStream> {
+        let request = self.prepare(request);
+        let stream = self.inner.stream_response(&request).await?;
+        let Some(usage) = self.usage.clone() else {
+            return Ok(stream);
+        };
+
+        let (model, backend) = (stream.model.clone(), stream.backend.clone());
+        let labels = Arc::new((self.user_id.clone(), request.prompt_name.clone(), backend.clone(), model.clone()));
+        let chunks = stream.then(move |chunk| {
+            let (usage, labels) = (usage.clone(), labels.clone());
+            async move {
+                if let Ok(StreamChunk { usage: Some(tokens), .. }) = &chunk {
+                    let (user_id, prompt_name, backend, model) = &*labels;
+                    if let Err(e) = usage.record(user_id, prompt_name, backend, model, tokens).await {
+                        warn!("Failed to record LLM usage: {}", e);
+                    }
+                }
+                chunk
+            }
+        });
+        Ok(LlmStream::new(model, backend, chunks))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::BudgetAction;
+
+    fn config(path: PathBuf) -> UsageConfig {
+        UsageConfig {
+            path,
+            prices: HashMap::from([
+                ("deepseek-chat".to_string(), ModelPrice { input: 0.27, output: 1.10 }),
+                ("gpt-4o".to_string(), ModelPrice { input: 2.5, output: 10.0 }),
+                ("gpt-4o-mini".to_string(), ModelPrice { input: 0.15, output: 0.6 }),
+            ]),
+            budget: BudgetConfig {
+                user_daily: 0.02,
+                monthly: 1.0,
+                action: BudgetAction::StopReplying,
+                ..BudgetConfig::default()
+            },
+        }
+    }
+
+    fn tokens(prompt_tokens: u32, completion_tokens: u32) -> TokenUsage {
+        TokenUsage {
+            prompt_tokens,
+            completion_tokens,
+            total_tokens: prompt_tokens + completion_tokens,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_cost_and_budgets_survive_restart() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = config(dir.path().join("usage.jsonl"));
+        let store = UsageStore::open(&config).unwrap();
+
+        assert!((store.cost("gpt-4o-mini-2024-07-18", &tokens(1_000_000, 0)) - 0.15).abs() < 1e-9);
+        assert!((store.cost("gpt-4o-2024-08-06", &tokens(0, 1_000_000)) - 10.0).abs() < 1e-9);
+        assert_eq!(store.cost("llama3", &tokens(1000, 1000)), 0.0);
+
+        store.record("alice@example.com", "email_reply", "deepseek", "deepseek-chat", &tokens(20_000, 10_000)).await.unwrap();
+        assert!(store.check_budget("alice@example.com").await.is_none());
+        store.record("alice@example.com", "email_analysis", "deepseek", "deepseek-chat", &tokens(20_000, 0)).await.unwrap();
+
+        // 重新打开后从记录文件恢复汇总
+        let store = UsageStore::open(&config).unwrap();
+        let exceeded = store.check_budget("alice@example.com").await.unwrap();
+        assert!(exceeded.scope.starts_with("alice@example.com "));
+        assert!((exceeded.spent - 0.0218).abs() < 1e-9);
+        assert!(store.check_budget("bob@example.com").await.is_none());
+
+        assert!(store.first_notice(&exceeded).await);
+        assert!(!store.first_notice(&exceeded).await);
+    }
+}
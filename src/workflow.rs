@@ -1,116 +1,1175 @@
 use anyhow::Result;
+use async_trait::async_trait;
 use std::collections::HashMap;
-use tracing::{debug, info, warn};
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{debug, error, info, warn};
 
-use crate::config;
-use crate::email::{EmailClient, EmailMessage};
-use crate::llm::{LlmClient, LlmRequest};
+use crate::analysis::{analyze_email, EmailAnalysis};
+use crate::approval::{self, Decision, Draft, DraftStore};
+use crate::auth::{self, Authenticator, SystemResolver, Verdict};
+use crate::config::{self, ActionKind, AuthPolicy, BudgetAction, RuleAction};
+use crate::context::ContextBudget;
+use crate::digest::{DigestEntry, DigestStore};
+use crate::email::autoreply::{automated_reason, sent_by_sentio};
+use crate::email::quote::{split_body, BodyParts};
+use crate::email::{EmailAddress, EmailClient, EmailMessage, InboundEmail, MessageHandler, OutboundQueue};
+use crate::llm::client::LlmResponse;
+use crate::llm::{ChatMessage, LlmClient, LlmRequest};
 use crate::memory::{MemoryStore, MemoryType, InteractionLog, MessageDirection};
+use crate::routing::Router;
+use crate::senders::{normalize_address, SenderList, SenderPolicy};
+use crate::tools::Toolbox;
+use crate::usage::{MeteredClient, UsageStore};
+
+/// 回复提示词中最多带上的用户记忆条数
+const PROMPT_MEMORY_LIMIT: usize = 5;
 
 #[allow(dead_code)]
 pub struct EmailWorkflow {
     llm_client: Box<dyn LlmClient>,
     email_client: Box<dyn EmailClient>,
-    allowed_sender: String,
+    senders: SenderList,
+    authenticator: Option<Authenticator>,
+    quote_original: bool,
+    history_turns: usize,
+    /// 同一线程在 `reply_window` 内最多回复的次数，0 表示不限制
+    max_replies_per_thread: usize,
+    reply_window: Duration,
+    router: Router,
+    digest: Option<Arc<DigestStore>>,
+    approval: Option<Approval>,
+    tools: Option<Tools>,
+    usage: Option<Usage>,
+    context: Option<ContextBudget>,
+}
+
+/// 用量统计与预算；超出预算时通知 owner
+struct Usage {
+    store: Arc<UsageStore>,
+    owner: Option<EmailAddress>,
+}
+
+/// 生成回复前可以调用的工具
+struct Tools {
+    toolbox: Toolbox,
+    max_rounds: usize,
+}
+
+/// 邮件所在的线程
+struct Thread {
+    /// 规范化后的发件人地址，记忆、交互记录和回复上限都按它存取
+    user_id: String,
+    session_id: String,
+    /// 本封邮件之前的往来，作为多轮对话发给模型
+    history: Vec<ChatMessage>,
+}
+
+/// 人工确认模式：回复保存为草稿，由 owner 确认后发送
+struct Approval {
+    drafts: Arc<DraftStore>,
+    owner: EmailAddress,
 }
 
 impl EmailWorkflow {
-    pub fn new(llm_client: Box<dyn LlmClient>, email_client: Box<dyn EmailClient>, allowed_sender: String) -> Self {
+    pub fn new(llm_client: Box<dyn LlmClient>, email_client: Box<dyn EmailClient>, senders: SenderList) -> Self {
         Self {
             llm_client,
             email_client,
-            allowed_sender,
+            senders,
+            authenticator: None,
+            quote_original: false,
+            history_turns: 0,
+            max_replies_per_thread: 0,
+            reply_window: Duration::ZERO,
+            router: Router::default(),
+            digest: None,
+            approval: None,
+            tools: None,
+            usage: None,
+            context: None,
         }
     }
 
-    pub async fn process_incoming_email(&self, message: &EmailMessage) -> Result<()> {
-        // 检查发件人是否是允许的邮箱
-        if message.from.email != self.allowed_sender {
-            warn!(
-                "Ignoring email from unauthorized sender: {}. Only {} is allowed.",
-                message.from.email, self.allowed_sender
-            );
+    pub fn with_quote_original(mut self, quote_original: bool) -> Self {
+        self.quote_original = quote_original;
+        self
+    }
+
+    pub fn with_history_turns(mut self, history_turns: usize) -> Self {
+        self.history_turns = history_turns;
+        self
+    }
+
+    pub fn with_reply_limit(mut self, max_replies_per_thread: usize, window: Duration) -> Self {
+        self.max_replies_per_thread = max_replies_per_thread;
+        self.reply_window = window;
+        self
+    }
+
+    pub fn with_authenticator(mut self, authenticator: Authenticator) -> Self {
+        self.authenticator = Some(authenticator);
+        self
+    }
+
+    pub fn with_router(mut self, router: Router) -> Self {
+        self.router = router;
+        self
+    }
+
+    pub fn with_digest(mut self, digest: Arc<DigestStore>) -> Self {
+        self.digest = Some(digest);
+        self
+    }
+
+    pub fn digest(&self) -> Option<Arc<DigestStore>> {
+        self.digest.clone()
+    }
+
+    pub fn with_approval(mut self, drafts: Arc<DraftStore>, owner: EmailAddress) -> Self {
+        self.approval = Some(Approval { drafts, owner });
+        self
+    }
+
+    pub fn with_tools(mut self, toolbox: Toolbox, max_rounds: usize) -> Self {
+        self.tools = Some(Tools { toolbox, max_rounds });
+        self
+    }
+
+    pub fn with_usage(mut self, store: Arc<UsageStore>, owner: Option<EmailAddress>) -> Self {
+        self.usage = Some(Usage { store, owner });
+        self
+    }
+
+    /// 按上下文预算裁剪邮件内容和线程历史
+    pub fn with_context_budget(mut self, context: ContextBudget) -> Self {
+        self.context = Some(context);
+        self
+    }
+
+    pub fn drafts(&self) -> Option<Arc<DraftStore>> {
+        self.approval.as_ref().map(|approval| approval.drafts.clone())
+    }
+
+    /// 读取同一线程中最近的若干轮交互，转换为多轮对话消息
+    ///
+    /// `current` 为正在处理的邮件的交互记录 id，重新处理时上次写入的这封邮件不算作历史。
+    async fn load_thread_history(&self, user_id: &str, session_id: &str, current: &str) -> Result<Vec<ChatMessage>> {
+        if self.history_turns == 0 {
+            return Ok(Vec::new());
+        }
+
+        let interactions = MemoryStore::get_user_interactions(user_id, None, None).await?;
+        let thread: Vec<&InteractionLog> = interactions
+            .iter()
+            .filter(|log| log.session_id == session_id && log.id.as_deref() != Some(current))
+            .collect();
+        let start = thread.len().saturating_sub(self.history_turns);
+
+        let history = thread[start..]
+            .iter()
+            .map(|log| match log.direction {
+                MessageDirection::UserToSystem => ChatMessage::user(log.content.clone()),
+                MessageDirection::SystemToUser => ChatMessage::assistant(log.content.clone()),
+            })
+            .collect();
+        Ok(match &self.context {
+            Some(context) => context.fit_history(history),
+            None => history,
+        })
+    }
+
+    /// 时间窗口内已经发到这个线程的回复数
+    async fn recent_replies(&self, user_id: &str, session_id: &str) -> Result<usize> {
+        let since = chrono::Utc::now() - chrono::Duration::from_std(self.reply_window)?;
+        let interactions = MemoryStore::get_user_interactions(user_id, None, None).await?;
+        Ok(interactions
+            .iter()
+            .filter(|log| log.session_id == session_id && matches!(log.direction, MessageDirection::SystemToUser) && log.timestamp >= since)
+            .count())
+    }
+
+    pub async fn process_incoming_email(&self, message: &InboundEmail) -> Result<()> {
+        // 自己发出的邮件一律不处理
+        if sent_by_sentio(message) {
+            info!("Skipping email sent by Sentio: {}", message.subject);
             return Ok(());
         }
+        // 退信、休假回复、邮件列表等自动邮件照常路由（摘要、转发、任务），但不回复，避免与其他自动程序互相回复
+        let automated = automated_reason(message);
+
+        // owner 对草稿预览的回复，不必在允许列表中；owner 的休假自动回复不能当作确认命令
+        let draft_id = self.approval.as_ref()
+            .filter(|approval| normalize_address(&approval.owner.email) == normalize_address(&message.from.email))
+            .filter(|_| automated.is_none())
+            .and_then(|_| approval::draft_id(&message.subject));
+
+        // 检查发件人是否在允许列表中
+        let policy = self.senders.find(&message.from.email);
+        if policy.is_none() && draft_id.is_none() {
+            warn!("Ignoring email from unauthorized sender: {}", message.from.email);
+            return Ok(());
+        }
+
+        // 验证发件人身份，未通过的邮件不会交给模型
+        let mut authentication = None;
+        if let Some(authenticator) = &self.authenticator {
+            let report = authenticator.verify(message).await;
+            match authenticator.verdict(&report) {
+                Verdict::Accept => debug!("Sender authentication passed: {}", report),
+                Verdict::Reject => {
+                    warn!("Rejecting email from {} that failed sender authentication: {}", message.from.email, report);
+                    return Ok(());
+                }
+                Verdict::Retry => {
+                    anyhow::bail!("Sender authentication for {} temporarily failed: {}", message.from.email, report)
+                }
+            }
+            authentication = Some(report.to_string());
+        }
+
+        if let Some(draft_id) = draft_id {
+            return self.handle_draft_command(message, draft_id).await;
+        }
+        let Some(policy) = policy else {
+            return Ok(());
+        };
 
         info!("Processing email from authorized sender {}: {}", message.from.email, message.subject);
 
+        // 同一邮件线程共用一个会话 ID
+        let session_id = message.thread_id()
+            .map(str::to_string)
+            .unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
+
+        // 地址大小写不同的邮件属于同一个用户
+        let user_id = normalize_address(&message.from.email);
+
+        // 本封邮件的交互记录和分析结果按 Message-ID 只写一次：重新处理时提示词不变，可以命中缓存，历史也不会重复
+        let interaction_id = message.message_id.clone().unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
+
+        // 先读取历史，避免把本封邮件也算进去
+        let thread = Thread {
+            history: self.load_thread_history(&user_id, &session_id, &interaction_id).await?,
+            user_id: user_id.clone(),
+            session_id: session_id.clone(),
+        };
+
+        let mut metadata = HashMap::new();
+        if let Some(message_id) = &message.message_id {
+            metadata.insert("message_id".to_string(), serde_json::json!(message_id));
+        }
+        if let Some(authentication) = authentication {
+            metadata.insert("authentication".to_string(), serde_json::json!(authentication));
+        }
+
+        // 引用的历史邮件和签名与新写的内容分开，记录中只保留新写的内容，引用部分已在线程历史中
+        let parts = split_body(&message.body());
+
         // 记录交互
         let _ = MemoryStore::log_interaction(&InteractionLog {
-            id: None,
-            user_id: message.from.email.clone(),
-            session_id: uuid::Uuid::new_v4().to_string(),
+            id: Some(interaction_id.clone()),
+            user_id: user_id.clone(),
+            session_id: session_id.clone(),
             timestamp: chrono::Utc::now(),
             direction: MessageDirection::UserToSystem,
-            content: format!("Subject: {}\n\n{}", message.subject, message.body),
-            metadata: HashMap::new(),
+            content: format!("Subject: {}\n\n{}", message.subject, parts.text),
+            metadata,
         }).await;
 
+        // 超出预算时改用便宜的模型或不再自动回复
+        let over_budget = self.check_budget(message, &user_id).await?;
+        let model = match over_budget {
+            Some(BudgetAction::CheaperModel) => self.usage.as_ref().and_then(|usage| usage.store.budget().cheaper_model.clone()),
+            _ => None,
+        };
+        let llm = MeteredClient::new(
+            self.llm_client.as_ref(),
+            self.usage.as_ref().map(|usage| usage.store.clone()),
+            &user_id,
+        )
+        .with_model(model);
+
+        // 过长的邮件先裁剪或分段摘要，分析和回复使用同样的内容
+        let parts = match &self.context {
+            Some(context) => context.fit_parts(&llm, parts).await?,
+            None => parts,
+        };
+        let email_content = BodyParts {
+            text: format!("Subject: {}\n\n{}", message.subject, parts.text),
+            quoted: parts.quoted,
+        };
+
         // 分析邮件
-        let mut context = HashMap::new();
-        context.insert("email_content".to_string(), 
-            serde_json::json!(format!("Subject: {}\n\n{}", message.subject, message.body)));
+        let analysis = analyze_email(&llm, &email_content.text, &email_content.quoted).await?;
+        // 无法解析的分析按默认结果路由（归档、转发等照常），但不自动回复
+        let unanalysed = analysis.is_none();
+        let analysis = analysis.unwrap_or_default();
 
-        let analysis_request = LlmRequest::new("email_analysis".to_string(), context);
-        let analysis = self.llm_client.generate_response(&analysis_request).await?;
-        
-        debug!("Email analysis: {}", analysis.content);
+        debug!("Email analysis: {:?}", analysis);
 
         // 存储分析结果
         let memory_store = MemoryStore::get();
-        memory_store.add_memory(
-            &message.from.email,
+        memory_store.add_memory_once(
+            &format!("analysis:{}", interaction_id),
+            &user_id,
             MemoryType::Event,
-            format!("Email analysis for '{}': {}", message.subject, analysis.content)
+            format!("Email analysis for '{}': [{}] {}", message.subject, analysis.category, analysis.summary)
         ).await?;
 
+        // 按路由规则执行动作
+        let (rule, actions) = self.router.route(message, &analysis);
+        info!(rule, actions = ?actions, category = %analysis.category, "Routing email");
+
+        // 已完成的动作按 Message-ID 记录，邮件因其他动作失败而重新处理时跳过，不会再发一次回复
+        let done = match &message.message_id {
+            Some(message_id) => memory_store.completed_actions(message_id).await,
+            None => Vec::new(),
+        };
+        let mut completed = 0;
+        let mut failures = Vec::new();
+        for action in actions {
+            if done.contains(action) {
+                debug!(action = ?action.kind(), "Action already completed for email '{}', skipping", message.subject);
+                continue;
+            }
+            if !policy.allows(action) {
+                info!(action = ?action.kind(), "Action not allowed for sender {}, skipping", message.from.email);
+                continue;
+            }
+            let result = match action {
+                RuleAction::Reply if unanalysed => {
+                    warn!("Analysis of '{}' could not be parsed, not replying automatically", message.subject);
+                    continue;
+                }
+                RuleAction::Reply if automated.is_some() => {
+                    info!("Not replying to automated email from {} ({})", message.from.email, automated.unwrap_or_default());
+                    continue;
+                }
+                RuleAction::Reply if over_budget == Some(BudgetAction::StopReplying) => {
+                    info!("LLM budget exhausted, not replying to {}", message.from.email);
+                    continue;
+                }
+                RuleAction::Reply => self.reply(&llm, message, &email_content, &analysis, policy, &thread).await,
+                RuleAction::Forward { to } => self.forward(message, to).await,
+                RuleAction::Task => self.save_tasks(&user_id, message, &analysis).await,
+                RuleAction::Digest => self.add_digest_entry(message, &analysis).await,
+                RuleAction::Ignore => {
+                    info!("Email '{}' ignored by routing rule {}", message.subject, rule);
+                    continue;
+                }
+            };
+            match result {
+                Ok(()) => {
+                    completed += 1;
+                    if let Some(message_id) = &message.message_id {
+                        memory_store.complete_action(message_id, action).await?;
+                    }
+                }
+                Err(e) => {
+                    error!(action = ?action.kind(), "Action failed for email '{}': {:#}", message.subject, e);
+                    failures.push((action.kind(), e));
+                }
+            }
+        }
+
+        // 有动作失败时邮件保持未读，稍后只重试失败的动作；没有 Message-ID 时无法记录已完成的动作，
+        // 只在回复失败或什么都没完成时重试，避免重复转发
+        let retry = message.message_id.is_some()
+            || completed == 0
+            || failures.iter().any(|(kind, _)| *kind == ActionKind::Reply);
+        match failures.into_iter().next() {
+            Some((_, e)) if retry => Err(e),
+            _ => {
+                info!("Email processed successfully");
+                Ok(())
+            }
+        }
+    }
+
+    async fn reply(
+        &self,
+        llm: &dyn LlmClient,
+        message: &InboundEmail,
+        email_content: &BodyParts,
+        analysis: &EmailAnalysis,
+        policy: &SenderPolicy,
+        thread: &Thread,
+    ) -> Result<()> {
+        let memory_store = MemoryStore::get();
+        let (user_id, session_id) = (thread.user_id.as_str(), thread.session_id.clone());
+
+        if self.max_replies_per_thread > 0
+            && self.recent_replies(user_id, &session_id).await? >= self.max_replies_per_thread
+        {
+            warn!(
+                "Reply limit of {} per {:?} reached for thread {}, not replying to {}",
+                self.max_replies_per_thread, self.reply_window, session_id, message.from.email
+            );
+            return Ok(());
+        }
+
         // 生成回复
         let mut reply_context = HashMap::new();
-        reply_context.insert("original_email".to_string(), 
-            serde_json::json!(format!("Subject: {}\n\n{}", message.subject, message.body)));
+        reply_context.insert("original_email".to_string(), serde_json::json!(email_content.text));
+        reply_context.insert("quoted_email".to_string(), serde_json::json!(email_content.quoted));
         reply_context.insert("analysis_result".to_string(), 
-            serde_json::json!(analysis.content));
+            serde_json::json!(serde_json::to_string_pretty(analysis)?));
+        let memories = memory_store.get_user_memories(user_id).await?;
+        let recent_memories: Vec<&str> = memories.iter()
+            .rev()
+            .take(PROMPT_MEMORY_LIMIT)
+            .map(|memory| memory.content.as_str())
+            .collect();
+        reply_context.insert("memories".to_string(), serde_json::json!(recent_memories));
+        reply_context.insert("persona".to_string(), serde_json::json!(policy.persona));
+        reply_context.insert("language".to_string(), serde_json::json!(policy.language));
 
-        let reply_request = LlmRequest::new("email_reply".to_string(), reply_context);
-        let reply = self.llm_client.generate_response(&reply_request).await?;
+        let reply_request = LlmRequest::new(policy.reply_prompt.clone(), reply_context)
+            .with_history(thread.history.clone());
+        let mut metadata = HashMap::new();
+        let reply = match &self.tools {
+            Some(tools) => {
+                let (reply, invocations) = self
+                    .generate_with_tools(llm, tools, reply_request, user_id, &session_id, message.message_id.as_deref())
+                    .await?;
+                metadata.insert("tool_calls".to_string(), serde_json::json!(invocations));
+                reply
+            }
+            // 流式生成：超时只限制两段输出之间的间隔，长回复不会因总时长超时；中断的流整体重试或换后端
+            None => llm.generate_streamed(&reply_request).await?,
+        };
+        metadata.insert("llm_backend".to_string(), serde_json::json!(reply.backend));
+        metadata.insert("llm_model".to_string(), serde_json::json!(reply.model));
 
         // 发送回复
-        let reply_message = EmailMessage {
-            from: message.to[0].clone(),
-            to: vec![message.from.clone()],
-            subject: format!("Re: {}", message.subject),
-            body: reply.content.clone(),
-            is_html: false,
+        let quoted = self.quote_original.then(|| message.quoted_body());
+        let reply_body = match &quoted {
+            Some(quoted) => format!("{}\n\n{}", reply.content, quoted),
+            None => reply.content.clone(),
         };
+        let reply_message = message.reply(mailbox_address(message)?, reply_body);
+
+        if let Some(approval) = &self.approval {
+            let draft = approval.drafts
+                .create(user_id, &session_id, reply_message, reply.content, quoted, metadata)
+                .await?;
+            self.email_client.send(approval::preview(
+                &draft,
+                message,
+                mailbox_address(message)?,
+                approval.owner.clone(),
+            )).await?;
+            info!(draft = %draft.id, "Reply to {} held for approval", message.reply_address().email);
+            return Ok(());
+        }
 
         self.email_client.send(reply_message).await?;
+        self.log_reply(user_id, session_id, reply.content, metadata).await;
 
-        // 记录回复
+        info!("Reply sent to {}", message.reply_address().email);
+        Ok(())
+    }
+
+    /// 让模型先调用工具再写回复，返回最终回复和每次工具调用的记录
+    ///
+    /// 工具执行失败时把错误告诉模型，由它决定如何继续；超过 `max_rounds` 轮后要求直接写出回复。
+    async fn generate_with_tools(
+        &self,
+        llm: &dyn LlmClient,
+        tools: &Tools,
+        request: LlmRequest,
+        user_id: &str,
+        session_id: &str,
+        message_id: Option<&str>,
+    ) -> Result<(LlmResponse, Vec<serde_json::Value>)> {
+        let mut request = request.with_tools(tools.toolbox.definitions());
+        let mut invocations = Vec::new();
+
+        for round in 0..=tools.max_rounds {
+            let response = llm.generate_response(&request).await?;
+            if response.tool_calls.is_empty() {
+                if response.content.trim().is_empty() {
+                    anyhow::bail!("Model returned an empty reply after {} tool calls", invocations.len());
+                }
+                return Ok((response, invocations));
+            }
+            if round == tools.max_rounds {
+                anyhow::bail!("Model still requested tools after {} rounds", tools.max_rounds);
+            }
+
+            request.tool_messages.push(ChatMessage::tool_request(response.content, response.tool_calls.clone()));
+            for call in response.tool_calls {
+                let result = tools.toolbox.execute(&call, user_id, session_id, message_id).await;
+                debug!(tool = %call.name, ok = result.is_ok(), "Executed tool call");
+                let (output, record) = match result {
+                    Ok(value) => (value.to_string(), serde_json::json!({ "name": call.name, "arguments": call.arguments, "result": value })),
+                    Err(e) => {
+                        warn!(tool = %call.name, "Tool call failed: {:#}", e);
+                        let error = format!("{:#}", e);
+                        (serde_json::json!({ "error": error }).to_string(), serde_json::json!({ "name": call.name, "arguments": call.arguments, "error": error }))
+                    }
+                };
+                invocations.push(record);
+                request.tool_messages.push(ChatMessage::tool_result(call.id, output));
+            }
+            if round + 1 == tools.max_rounds {
+                request.tool_messages.push(ChatMessage::user("工具调用次数已用完，请根据以上信息直接写出回复。"));
+            }
+        }
+        unreachable!("the last round either returns or bails")
+    }
+
+    /// 发件人或全部用户的预算用完时返回生效的处理方式，并在第一次超出时通知 owner
+    async fn check_budget(&self, message: &InboundEmail, user_id: &str) -> Result<Option<BudgetAction>> {
+        let Some(usage) = &self.usage else {
+            return Ok(None);
+        };
+        let Some(exceeded) = usage.store.check_budget(user_id).await else {
+            return Ok(None);
+        };
+
+        let action = usage.store.budget().action;
+        warn!(action = ?action, "LLM budget exhausted: {}", exceeded);
+        if let Some(owner) = &usage.owner {
+            if usage.store.first_notice(&exceeded).await {
+                let consequence = match action {
+                    BudgetAction::StopReplying => "在预算恢复前不再自动回复，邮件仍会分析和记录。".to_string(),
+                    BudgetAction::CheaperModel => format!(
+                        "在预算恢复前改用 {} 处理邮件。",
+                        usage.store.budget().cheaper_model.as_deref().unwrap_or_default()
+                    ),
+                };
+                self.email_client.send(EmailMessage {
+                    from: mailbox_address(message)?,
+                    to: vec![owner.clone()],
+                    subject: "[Sentio] 模型费用超出预算".to_string(),
+                    body: format!("{}。\n{}", exceeded, consequence),
+                    is_html: false,
+                    in_reply_to: None,
+                    references: Vec::new(),
+                }).await?;
+            }
+        }
+        Ok(Some(action))
+    }
+
+    async fn log_reply(&self, user_id: &str, session_id: String, content: String, metadata: HashMap<String, serde_json::Value>) {
         let _ = MemoryStore::log_interaction(&InteractionLog {
             id: None,
-            user_id: message.from.email.clone(),
-            session_id: uuid::Uuid::new_v4().to_string(),
+            user_id: user_id.to_string(),
+            session_id,
             timestamp: chrono::Utc::now(),
             direction: MessageDirection::SystemToUser,
-            content: reply.content,
-            metadata: HashMap::new(),
+            content,
+            metadata,
         }).await;
+    }
+
+    /// 按 owner 的决定处理草稿；草稿不存在或已过期时返回 None
+    pub async fn resolve_draft(&self, id: &str, decision: Decision) -> Result<Option<Draft>> {
+        let Some(approval) = &self.approval else {
+            anyhow::bail!("Approval mode is not enabled");
+        };
+        let Some(mut draft) = approval.drafts.take(id).await? else {
+            return Ok(None);
+        };
+
+        let edited = match decision {
+            Decision::Reject => {
+                info!(draft = %draft.id, "Draft reply to {} rejected", draft.user_id);
+                return Ok(Some(draft));
+            }
+            Decision::Approve => false,
+            Decision::Edit(content) => {
+                draft.set_content(content);
+                true
+            }
+        };
+
+        if let Err(e) = self.email_client.send(draft.reply.clone()).await {
+            approval.drafts.restore(draft).await?;
+            return Err(e.into());
+        }
+
+        let mut metadata = draft.metadata.clone();
+        metadata.insert("draft_id".to_string(), serde_json::json!(draft.id));
+        metadata.insert("edited".to_string(), serde_json::json!(edited));
+        self.log_reply(&draft.user_id, draft.session_id.clone(), draft.content.clone(), metadata).await;
+
+        info!(draft = %draft.id, edited, "Approved reply sent to {}", draft.user_id);
+        Ok(Some(draft))
+    }
+
+    /// 处理 owner 通过邮件回复的确认命令，并把结果回复给 owner
+    async fn handle_draft_command(&self, message: &InboundEmail, id: &str) -> Result<()> {
+        let result = match approval::parse_command(&message.body()) {
+            None => format!("没有识别出对草稿 #{} 的命令，请在第一行写上 批准、修改 或 拒绝。", id),
+            Some(decision) => {
+                let rejected = decision == Decision::Reject;
+                match self.resolve_draft(id, decision).await? {
+                    None => format!("草稿 #{} 不存在或已过期。", id),
+                    Some(draft) if rejected => format!("草稿 #{} 已丢弃，不会回复 {}。", id, draft.user_id),
+                    Some(draft) => format!("草稿 #{} 已发送给 {}。", id, draft.user_id),
+                }
+            }
+        };
 
-        info!("Email processed and reply sent successfully");
+        info!("Handled approval command from owner: {}", result);
+        self.email_client.send(message.reply(mailbox_address(message)?, result)).await?;
         Ok(())
     }
+
+    async fn forward(&self, message: &InboundEmail, to: &str) -> Result<()> {
+        let mut body = format!(
+            "---------- 转发的邮件 ----------\n发件人：{}\n",
+            message.from.email
+        );
+        if let Some(date) = message.date {
+            body.push_str(&format!("日期：{}\n", date.format("%Y-%m-%d %H:%M UTC")));
+        }
+        body.push_str(&format!("主题：{}\n\n{}", message.subject, message.body()));
+
+        self.email_client.send(EmailMessage {
+            from: mailbox_address(message)?,
+            to: vec![EmailAddress::new(to)],
+            subject: format!("Fwd: {}", message.subject),
+            body,
+            is_html: false,
+            in_reply_to: None,
+            references: Vec::new(),
+        }).await?;
+
+        info!("Email '{}' forwarded to {}", message.subject, to);
+        Ok(())
+    }
+
+    /// 每个待办事项保存为一条任务记忆；没有提取到待办时保存摘要
+    async fn save_tasks(&self, user_id: &str, message: &InboundEmail, analysis: &EmailAnalysis) -> Result<()> {
+        let memory_store = MemoryStore::get();
+        let tasks = if analysis.action_items.is_empty() {
+            vec![format!("{}: {}", message.subject, analysis.summary)]
+        } else {
+            analysis.action_items.clone()
+        };
+
+        for task in tasks {
+            memory_store.add_memory(user_id, MemoryType::Task, task).await?;
+        }
+        Ok(())
+    }
+
+    async fn add_digest_entry(&self, message: &InboundEmail, analysis: &EmailAnalysis) -> Result<()> {
+        let Some(digest) = &self.digest else {
+            warn!("Digest action matched but no digest store is configured");
+            return Ok(());
+        };
+
+        digest.add(DigestEntry {
+            received_at: message.date.unwrap_or_else(chrono::Utc::now),
+            from: message.from.email.clone(),
+            subject: message.subject.clone(),
+            category: analysis.category.to_string(),
+            summary: analysis.summary.clone(),
+        }).await
+    }
 }
 
-pub async fn create_workflow() -> Result<EmailWorkflow> {
-    use crate::email::SmtpClient;
-    use crate::llm::DeepSeekClient;
+/// 收到邮件的邮箱地址，回复和转发都从这个地址发出
+fn mailbox_address(message: &InboundEmail) -> Result<EmailAddress> {
+    message.to.first().cloned()
+        .ok_or_else(|| anyhow::anyhow!("Email has no recipient to reply from"))
+}
+
+#[async_trait]
+impl MessageHandler for EmailWorkflow {
+    async fn handle(&self, message: &InboundEmail) -> Result<()> {
+        self.process_incoming_email(message).await
+    }
+}
 
+/// 根据全局配置创建工作流
+///
+/// `queue` 是调用方打开的发件队列：工作流只往里写入回复、转发和草稿预览，工具只读取它，
+/// 投递由调用方对同一个队列启动的唯一一个 `run` 任务完成。
+pub async fn create_workflow(queue: &OutboundQueue) -> Result<EmailWorkflow> {
     let config = config::get();
     
     // 创建客户端
-    let llm_client = Box::new(DeepSeekClient::new()?);
-    let email_client = Box::new(SmtpClient::new(config.email.smtp.clone())?);
+    let llm_client = crate::llm::create_client(&config.llm, &config.prompts)?;
+    // 回复先写入发件队列，由后台任务投递
+    let email_client = Box::new(queue.clone());
+
+    let router = Router::from_config(&config.routing)?;
+    let digest = router.uses_digest()
+        .then(|| Arc::new(DigestStore::new(config.routing.digest.path.clone())));
+
+    let senders = SenderList::from_config(&config.email)?;
 
     // 创建工作流
-    Ok(EmailWorkflow::new(llm_client, email_client, config.email.allowed_sender.clone()))
-}
\ No newline at end of file
+    let mut workflow = EmailWorkflow::new(llm_client, email_client, senders)
+        .with_quote_original(config.email.quote_original)
+        .with_history_turns(config.llm.history_turns)
+        .with_reply_limit(
+            config.email.loop_protection.max_replies_per_thread,
+            Duration::from_secs(config.email.loop_protection.window),
+        )
+        .with_router(router);
+    if config.email.auth.policy == AuthPolicy::Off {
+        warn!("Sender verification is off ([email.auth] policy = \"off\"); forged From addresses will reach the LLM");
+    } else {
+        let resolver = SystemResolver::new(Duration::from_secs(config.email.auth.dns_timeout))?;
+        let trusted_relays = config
+            .email
+            .auth
+            .trusted_relays
+            .iter()
+            .map(|relay| auth::parse_network(relay).ok_or_else(|| anyhow::anyhow!("invalid email.auth.trusted_relays entry: {}", relay)))
+            .collect::<Result<Vec<_>>>()?;
+        workflow = workflow.with_authenticator(
+            Authenticator::new(config.email.auth.policy, Box::new(resolver)).with_trusted_relays(trusted_relays),
+        );
+    }
+    if let Some(digest) = digest {
+        workflow = workflow.with_digest(digest);
+    }
+    if config.approval.enabled {
+        let owner = config.email.owner_address()
+            .ok_or_else(|| anyhow::anyhow!("email.owner must be set to approve draft replies"))?;
+        let drafts = DraftStore::new(config.approval.path.clone(), Duration::from_secs(config.approval.timeout));
+        workflow = workflow.with_approval(Arc::new(drafts), EmailAddress::new(owner));
+    }
+    if config.usage.budget.action == BudgetAction::CheaperModel && config.usage.budget.cheaper_model.is_none() {
+        anyhow::bail!("usage.budget.cheaper_model must be set when usage.budget.action = \"cheaper_model\"");
+    }
+    let usage = UsageStore::open(&config.usage)?;
+    workflow = workflow.with_usage(Arc::new(usage), config.email.owner_address().map(EmailAddress::new));
+    let mut models = vec![config.llm.model.as_str()];
+    models.extend(config.llm.fallbacks.iter().map(|fallback| fallback.model.as_str()));
+    models.extend(config.prompts.settings.values().filter_map(|settings| settings.model.as_deref()));
+    models.extend(config.usage.budget.cheaper_model.as_deref());
+    workflow = workflow.with_context_budget(ContextBudget::new(&config.llm.context, &models));
+    if config.tools.enabled {
+        if config.tools.max_rounds == 0 {
+            anyhow::bail!("tools.max_rounds must be at least 1 when tools.enabled = true");
+        }
+        let toolbox = Toolbox::new().with_queue(queue.clone());
+        workflow = workflow.with_tools(toolbox, config.tools.max_rounds);
+    }
+    Ok(workflow)
+}
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::config::EmailConfig;
+    use crate::email::client::EmailResult;
+    use crate::email::parser::parse_message;
+    use crate::llm::client::tests::{request, response};
+    use crate::llm::client::{LlmResponse, LlmResult};
+    use std::sync::Mutex;
+
+    /// 记录所有发出邮件的发送端
+    #[derive(Clone, Default)]
+    pub(crate) struct CapturingClient {
+        pub sent: Arc<Mutex<Vec<EmailMessage>>>,
+    }
+
+    #[async_trait]
+    impl EmailClient for CapturingClient {
+        async fn send(&self, message: EmailMessage) -> EmailResult<String> {
+            self.sent.lock().unwrap().push(message);
+            Ok("sent".to_string())
+        }
+    }
+
+    /// 不应被调用的模型
+    pub(crate) struct UnusedLlm;
+
+    #[async_trait]
+    impl LlmClient for UnusedLlm {
+        async fn generate_response(&self, request: &LlmRequest) -> LlmResult<LlmResponse> {
+            panic!("unexpected LLM request: {}", request.prompt_name)
+        }
+    }
+
+    /// 开启确认模式的工作流，只允许 alice 发信，owner 为 boss
+    pub(crate) async fn approval_workflow(drafts: Arc<DraftStore>) -> (EmailWorkflow, CapturingClient) {
+        MemoryStore::initialize_for_tests().await;
+        let config: EmailConfig = toml::from_str(
+            r#"
+            smtp = { host = "smtp.example.com", port = 587, username = "sentio@example.com", password = "p" }
+            allowed_sender = "alice@example.com"
+            "#,
+        )
+        .unwrap();
+        let client = CapturingClient::default();
+        let workflow = EmailWorkflow::new(Box::new(UnusedLlm), Box::new(client.clone()), SenderList::from_config(&config).unwrap())
+            .with_approval(drafts, EmailAddress::new("Boss@Example.com"));
+        (workflow, client)
+    }
+
+    pub(crate) async fn create_draft(drafts: &DraftStore) -> Draft {
+        let original = parse_message(b"From: alice@example.com\r\nTo: sentio@example.com\r\nMessage-ID: <m1@example.com>\r\nSubject: =?UTF-8?B?5ZGo5oql?=\r\n\r\nHi\r\n").unwrap();
+        let reply = original.reply(EmailAddress::new("sentio@example.com"), "好的\n\n> Hi".to_string());
+        drafts.create("alice@example.com", "m1@example.com", reply, "好的".to_string(), Some("> Hi".to_string()), HashMap::new()).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_owner_edits_draft_by_email() {
+        let dir = tempfile::tempdir().unwrap();
+        let drafts = Arc::new(DraftStore::new(dir.path().join("drafts.json"), Duration::from_secs(3600)));
+        let (workflow, client) = approval_workflow(drafts.clone()).await;
+        let draft = create_draft(&drafts).await;
+
+        let command = format!(
+            "From: boss@example.com\r\nTo: sentio@example.com\r\nSubject: Re: [Sentio 草稿 #{}] Re: 周报\r\nContent-Type: text/plain; charset=utf-8\r\n\r\n修改\r\n收到，周五前给你。\r\n\r\n> 原草稿\r\n",
+            draft.id
+        );
+        let command = parse_message(command.as_bytes()).unwrap();
+        workflow.process_incoming_email(&command).await.unwrap();
+
+        {
+            let sent = client.sent.lock().unwrap();
+            assert_eq!(sent[0].to[0].email, "alice@example.com");
+            assert_eq!(sent[0].body, "收到，周五前给你。\n\n> Hi");
+            assert_eq!(sent[0].in_reply_to.as_deref(), Some("m1@example.com"));
+            assert_eq!(sent[1].to[0].email, "boss@example.com");
+            assert!(sent[1].body.contains("已发送"));
+        }
+
+        // 草稿只能确认一次；不是 owner 的人回复同样的主题会被忽略
+        workflow.process_incoming_email(&command).await.unwrap();
+        assert!(client.sent.lock().unwrap()[2].body.contains("不存在或已过期"));
+        let stranger = parse_message(format!("From: eve@example.com\r\nTo: sentio@example.com\r\nSubject: [Sentio 草稿 #{}]\r\n\r\n批准\r\n", draft.id).as_bytes()).unwrap();
+        workflow.process_incoming_email(&stranger).await.unwrap();
+        assert_eq!(client.sent.lock().unwrap().len(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_recent_replies_counts_thread_within_window() {
+        let dir = tempfile::tempdir().unwrap();
+        let drafts = Arc::new(DraftStore::new(dir.path().join("drafts.json"), Duration::from_secs(3600)));
+        let (workflow, _) = approval_workflow(drafts).await;
+        let workflow = workflow.with_reply_limit(2, Duration::from_secs(3600));
+
+        let user = "loop-test@example.com";
+        let log = |session: &str, direction, age_minutes| InteractionLog {
+            id: None,
+            user_id: user.to_string(),
+            session_id: session.to_string(),
+            timestamp: chrono::Utc::now() - chrono::Duration::minutes(age_minutes),
+            direction,
+            content: "x".to_string(),
+            metadata: HashMap::new(),
+        };
+        for interaction in [
+            log("t1", MessageDirection::SystemToUser, 5),
+            log("t1", MessageDirection::UserToSystem, 5),
+            log("t1", MessageDirection::SystemToUser, 90),
+            log("t2", MessageDirection::SystemToUser, 5),
+        ] {
+            MemoryStore::log_interaction(&interaction).await.unwrap();
+        }
+
+        assert_eq!(workflow.recent_replies(user, "t1").await.unwrap(), 1);
+        workflow.log_reply(user, "t1".to_string(), "y".to_string(), HashMap::new()).await;
+        assert_eq!(workflow.recent_replies(user, "t1").await.unwrap(), 2);
+    }
+
+    /// 按顺序返回预设响应的模型，记录收到的请求
+    struct ToolScriptLlm {
+        responses: Mutex<Vec<LlmResponse>>,
+        requests: Arc<Mutex<Vec<LlmRequest>>>,
+    }
+
+    #[async_trait]
+    impl LlmClient for ToolScriptLlm {
+        async fn generate_response(&self, request: &LlmRequest) -> LlmResult<LlmResponse> {
+            self.requests.lock().unwrap().push(request.clone());
+            Ok(LlmResponse { request_id: request.id, ..self.responses.lock().unwrap().remove(0) })
+        }
+    }
+
+    /// `ToolScriptLlm` 的预设响应，请求 ID 在返回时换成实际请求的
+    fn scripted(content: &str) -> LlmResponse {
+        response(&request(), content)
+    }
+
+    #[tokio::test]
+    async fn test_tool_calls_are_executed_and_recorded() {
+        MemoryStore::initialize_for_tests().await;
+        let response = |content: &str, tool_calls| LlmResponse { tool_calls, ..scripted(content) };
+        let call = |id: &str, name: &str, arguments| crate::llm::client::ToolCall {
+            id: id.to_string(),
+            name: name.to_string(),
+            arguments,
+        };
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let llm = ToolScriptLlm {
+            responses: Mutex::new(vec![
+                response("", vec![
+                    call("c1", "add_task", serde_json::json!({ "content": "下周一回电话" })),
+                    call("c2", "add_task", serde_json::json!({})),
+                ]),
+                response("已记下，下周一给您回电话。", Vec::new()),
+            ]),
+            requests: requests.clone(),
+        };
+        let config: EmailConfig = toml::from_str(
+            r#"
+            smtp = { host = "smtp.example.com", port = 587, username = "sentio@example.com", password = "p" }
+            allowed_sender = "tools-test@example.com"
+            "#,
+        )
+        .unwrap();
+        let workflow = EmailWorkflow::new(Box::new(llm), Box::new(CapturingClient::default()), SenderList::from_config(&config).unwrap());
+        let tools = Tools { toolbox: Toolbox::new(), max_rounds: 2 };
+
+        let user = "tools-test@example.com";
+        let request = LlmRequest::new("email_reply".to_string(), HashMap::new());
+        let (reply, invocations) = workflow.generate_with_tools(workflow.llm_client.as_ref(), &tools, request, user, "t", None).await.unwrap();
+
+        assert_eq!(reply.content, "已记下，下周一给您回电话。");
+        assert_eq!(invocations.len(), 2);
+        assert_eq!(invocations[0]["name"], "add_task");
+        assert!(invocations[0]["result"]["id"].is_string());
+        assert!(invocations[1]["error"].as_str().unwrap().contains("invalid arguments"));
+
+        let tasks = MemoryStore::get().get_user_memories(user).await.unwrap();
+        assert_eq!(tasks[0].content, "下周一回电话");
+
+        // 第二次请求带上了工具调用和两个结果
+        let second = &requests.lock().unwrap()[1];
+        let roles: Vec<&str> = second.tool_messages.iter().map(|message| message.role.as_str()).collect();
+        assert_eq!(roles, vec!["assistant", "tool", "tool"]);
+        assert_eq!(second.tool_messages[2].tool_call_id.as_deref(), Some("c2"));
+        assert!(!second.tools.is_empty());
+    }
+
+    /// 发往 `broken` 中地址的邮件发送失败的发送端
+    #[derive(Clone, Default)]
+    struct FlakyClient {
+        sent: CapturingClient,
+        broken: Arc<Mutex<Option<String>>>,
+    }
+
+    #[async_trait]
+    impl EmailClient for FlakyClient {
+        async fn send(&self, message: EmailMessage) -> EmailResult<String> {
+            if self.broken.lock().unwrap().as_deref() == Some(message.to[0].email.as_str()) {
+                return Err(crate::email::client::EmailError::Connection("connection reset".to_string()));
+            }
+            self.sent.send(message).await
+        }
+    }
+
+    /// 先回复再转发给 boss 的工作流
+    fn reply_and_forward(user: &str, llm: ToolScriptLlm, client: &FlakyClient) -> EmailWorkflow {
+        let config: EmailConfig = toml::from_str(&format!(
+            "smtp = {{ host = \"smtp.example.com\", port = 587, username = \"sentio@example.com\", password = \"p\" }}\nallowed_sender = \"{}\"",
+            user
+        ))
+        .unwrap();
+        let routing: config::RoutingConfig =
+            toml::from_str(r#"default_actions = [{ type = "reply" }, { type = "forward", to = "boss@example.com" }]"#).unwrap();
+        EmailWorkflow::new(Box::new(llm), Box::new(client.clone()), SenderList::from_config(&config).unwrap())
+            .with_router(Router::from_config(&routing).unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_failed_action_is_retried_without_repeating_completed_ones() {
+        MemoryStore::initialize_for_tests().await;
+        let user = "actions-test@example.com";
+        let analysis = "{\"category\": \"工作相关\", \"needs_reply\": true}";
+        let llm = ToolScriptLlm {
+            responses: Mutex::new(vec![scripted(analysis), scripted("收到。"), scripted(analysis), scripted(analysis)]),
+            requests: Default::default(),
+        };
+        let client = FlakyClient::default();
+        *client.broken.lock().unwrap() = Some("boss@example.com".to_string());
+        let workflow = reply_and_forward(user, llm, &client);
+        let email = format!("From: {}\r\nTo: sentio@example.com\r\nMessage-ID: <actions-1@example.com>\r\nSubject: hi\r\n\r\nHi\r\n", user);
+        let email = parse_message(email.as_bytes()).unwrap();
+
+        // 转发失败时邮件留待重试，重试时只做转发，不再发一次回复
+        assert!(workflow.process_incoming_email(&email).await.is_err());
+        assert!(workflow.process_incoming_email(&email).await.is_err());
+        *client.broken.lock().unwrap() = None;
+        workflow.process_incoming_email(&email).await.unwrap();
+
+        let sent = client.sent.sent.lock().unwrap();
+        let recipients: Vec<&str> = sent.iter().map(|message| message.to[0].email.as_str()).collect();
+        assert_eq!(recipients, vec![user, "boss@example.com"]);
+    }
+
+    #[tokio::test]
+    async fn test_failed_reply_is_retried_after_forward_succeeds() {
+        MemoryStore::initialize_for_tests().await;
+        let user = "reply-retry-test@example.com";
+        let analysis = "{\"category\": \"工作相关\", \"needs_reply\": true}";
+        let llm = ToolScriptLlm {
+            responses: Mutex::new([analysis, "收到。"].repeat(3).into_iter().map(scripted).collect()),
+            requests: Default::default(),
+        };
+        let client = FlakyClient::default();
+        *client.broken.lock().unwrap() = Some(user.to_string());
+        let workflow = reply_and_forward(user, llm, &client);
+
+        // 回复失败、转发成功：邮件仍留待重试，回复不会丢失，转发也不会重复
+        let email = |header: &str| {
+            let raw = format!("From: {}\r\nTo: sentio@example.com\r\n{}Subject: hi\r\n\r\nHi\r\n", user, header);
+            parse_message(raw.as_bytes()).unwrap()
+        };
+        let with_id = email("Message-ID: <reply-retry-1@example.com>\r\n");
+        assert!(workflow.process_incoming_email(&with_id).await.is_err());
+        *client.broken.lock().unwrap() = None;
+        workflow.process_incoming_email(&with_id).await.unwrap();
+
+        // 没有 Message-ID 时同样保留回复失败的邮件
+        *client.broken.lock().unwrap() = Some(user.to_string());
+        assert!(workflow.process_incoming_email(&email("")).await.is_err());
+
+        let sent = client.sent.sent.lock().unwrap();
+        let recipients: Vec<&str> = sent.iter().map(|message| message.to[0].email.as_str()).collect();
+        assert_eq!(recipients, vec!["boss@example.com", user, "boss@example.com"]);
+    }
+
+    #[tokio::test]
+    async fn test_reprocessed_email_hits_cache_without_duplicating_history() {
+        MemoryStore::initialize_for_tests().await;
+        let user = "reprocess-test@example.com";
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let llm = ToolScriptLlm {
+            responses: Mutex::new(vec![scripted("{\"category\": \"工作相关\", \"needs_reply\": true}"), scripted("收到。")]),
+            requests: requests.clone(),
+        };
+        let dir = tempfile::tempdir().unwrap();
+        let cache = config::LlmCacheConfig { dir: dir.path().to_path_buf(), ..Default::default() };
+        let llm = crate::llm::cache::CachingClient::new(Box::new(llm), &cache, "m", crate::llm::prompt::PromptRegistry::builtin().unwrap()).unwrap();
+        let config: EmailConfig = toml::from_str(&format!(
+            "smtp = {{ host = \"smtp.example.com\", port = 587, username = \"sentio@example.com\", password = \"p\" }}\nallowed_sender = \"{}\"",
+            user
+        ))
+        .unwrap();
+        let client = FlakyClient::default();
+        *client.broken.lock().unwrap() = Some(user.to_string());
+        let workflow = EmailWorkflow::new(Box::new(llm), Box::new(client.clone()), SenderList::from_config(&config).unwrap())
+            .with_history_turns(10);
+        let email = format!("From: {}\r\nTo: sentio@example.com\r\nMessage-ID: <reprocess-1@example.com>\r\nSubject: hi\r\n\r\nHi\r\n", user);
+        let email = parse_message(email.as_bytes()).unwrap();
+
+        // 回复发送失败后重新处理：提示词与上次相同，分析和回复都命中缓存
+        assert!(workflow.process_incoming_email(&email).await.is_err());
+        *client.broken.lock().unwrap() = None;
+        workflow.process_incoming_email(&email).await.unwrap();
+        assert_eq!(requests.lock().unwrap().len(), 2);
+        assert_eq!(client.sent.sent.lock().unwrap()[0].body, "收到。");
+
+        let interactions = MemoryStore::get_user_interactions(user, None, None).await.unwrap();
+        let directions: Vec<bool> = interactions.iter().map(|log| matches!(log.direction, MessageDirection::UserToSystem)).collect();
+        assert_eq!(directions, vec![true, false]);
+        let memories = MemoryStore::get().get_user_memories(user).await.unwrap();
+        assert_eq!(memories.iter().filter(|memory| memory.content.starts_with("Email analysis")).count(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_automated_mail_is_routed_but_not_replied_to() {
+        MemoryStore::initialize_for_tests().await;
+        let user = "newsletter-test@example.com";
+        let llm = ToolScriptLlm {
+            responses: Mutex::new(vec![scripted("{\"category\": \"营销推广\", \"needs_reply\": true}")]),
+            requests: Default::default(),
+        };
+        let config: EmailConfig = toml::from_str(&format!(
+            "smtp = {{ host = \"smtp.example.com\", port = 587, username = \"sentio@example.com\", password = \"p\" }}\nallowed_sender = \"{}\"",
+            user
+        ))
+        .unwrap();
+        let routing: config::RoutingConfig =
+            toml::from_str(r#"default_actions = [{ type = "reply" }, { type = "forward", to = "boss@example.com" }]"#).unwrap();
+        let client = CapturingClient::default();
+        let workflow = EmailWorkflow::new(Box::new(llm), Box::new(client.clone()), SenderList::from_config(&config).unwrap())
+            .with_router(Router::from_config(&routing).unwrap());
+
+        // 邮件列表发来的邮件照常转发，只是不自动回复
+        let email = format!("From: {}\r\nTo: sentio@example.com\r\nList-Id: <news.example.com>\r\nSubject: 十月特惠\r\n\r\nHi\r\n", user);
+        workflow.process_incoming_email(&parse_message(email.as_bytes()).unwrap()).await.unwrap();
+        let sent = client.sent.lock().unwrap();
+        assert_eq!(sent.len(), 1);
+        assert_eq!((sent[0].to[0].email.as_str(), sent[0].subject.as_str()), ("boss@example.com", "Fwd: 十月特惠"));
+    }
+
+    #[tokio::test]
+    async fn test_sender_address_case_does_not_split_user_state() {
+        MemoryStore::initialize_for_tests().await;
+        let analysis = "{\"category\": \"工作相关\", \"needs_reply\": true}";
+        let llm = ToolScriptLlm {
+            responses: Mutex::new(vec![scripted(analysis), scripted("收到。"), scripted(analysis)]),
+            requests: Default::default(),
+        };
+        let config: EmailConfig = toml::from_str(
+            r#"
+            smtp = { host = "smtp.example.com", port = 587, username = "sentio@example.com", password = "p" }
+            allowed_sender = "case-test@example.com"
+            "#,
+        )
+        .unwrap();
+        let client = CapturingClient::default();
+        let workflow = EmailWorkflow::new(Box::new(llm), Box::new(client.clone()), SenderList::from_config(&config).unwrap())
+            .with_reply_limit(1, Duration::from_secs(3600));
+
+        // 换成大写地址也算同一线程的第二封，不能绕过回复上限
+        let first = "From: Case-Test@Example.com\r\nTo: sentio@example.com\r\nMessage-ID: <case-1@example.com>\r\nSubject: hi\r\n\r\nHi\r\n";
+        let second = "From: CASE-TEST@EXAMPLE.COM\r\nTo: sentio@example.com\r\nMessage-ID: <case-2@example.com>\r\nIn-Reply-To: <case-1@example.com>\r\nSubject: Re: hi\r\n\r\nHi again\r\n";
+        workflow.process_incoming_email(&parse_message(first.as_bytes()).unwrap()).await.unwrap();
+        workflow.process_incoming_email(&parse_message(second.as_bytes()).unwrap()).await.unwrap();
+        assert_eq!(client.sent.lock().unwrap().len(), 1);
+
+        let interactions = MemoryStore::get_user_interactions("case-test@example.com", None, None).await.unwrap();
+        assert_eq!(interactions.len(), 3);
+        assert_eq!(MemoryStore::get().get_user_memories("case-test@example.com").await.unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_exhausted_budget_stops_replies_and_notifies_owner_once() {
+        MemoryStore::initialize_for_tests().await;
+        let dir = tempfile::tempdir().unwrap();
+        let usage = crate::config::UsageConfig {
+            path: dir.path().join("usage.jsonl"),
+            prices: HashMap::from([("m".to_string(), crate::config::ModelPrice { input: 1.0, output: 1.0 })]),
+            budget: crate::config::BudgetConfig { user_daily: 0.01, ..Default::default() },
+        };
+        let store = Arc::new(UsageStore::open(&usage).unwrap());
+        let user = "budget-test@example.com";
+        let spent = crate::llm::client::TokenUsage { prompt_tokens: 10_000, completion_tokens: 0, total_tokens: 10_000 };
+        store.record(user, "email_reply", "scripted", "m", &spent).await.unwrap();
+
+        let analysis = || LlmResponse { usage: spent.clone(), ..scripted("{\"category\": \"工作相关\", \"needs_reply\": true}") };
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let llm = ToolScriptLlm { responses: Mutex::new(vec![analysis(), analysis()]), requests: requests.clone() };
+        let config: EmailConfig = toml::from_str(&format!(
+            "smtp = {{ host = \"smtp.example.com\", port = 587, username = \"sentio@example.com\", password = \"p\" }}\nallowed_sender = \"{}\"",
+            user
+        ))
+        .unwrap();
+        let client = CapturingClient::default();
+        let workflow = EmailWorkflow::new(Box::new(llm), Box::new(client.clone()), SenderList::from_config(&config).unwrap())
+            .with_usage(store.clone(), Some(EmailAddress::new("boss@example.com")));
+
+        // 换成大写地址不能绕过发件人预算
+        let email = parse_message(format!("From: {}\r\nTo: sentio@example.com\r\nSubject: hi\r\n\r\nHi\r\n", user.to_uppercase()).as_bytes()).unwrap();
+        workflow.process_incoming_email(&email).await.unwrap();
+        workflow.process_incoming_email(&email).await.unwrap();
+
+        // 只分析不回复，owner 只收到一次通知
+        let sent = client.sent.lock().unwrap();
+        assert_eq!(sent.len(), 1);
+        assert_eq!(sent[0].to[0].email, "boss@example.com");
+        assert!(sent[0].body.contains(user));
+        assert_eq!(requests.lock().unwrap().len(), 2);
+
+        // 分析调用也记入用量
+        let lines = std::fs::read_to_string(dir.path().join("usage.jsonl")).unwrap();
+        assert_eq!(lines.lines().filter(|line| line.contains("email_analysis")).count(), 2);
+    }
+}